@@ -0,0 +1,105 @@
+// writes bits most significant first into bytes, the last byte is padded with zero bits
+pub struct BitWriter<'a> {
+    bytes: &'a mut Vec<u8>,
+    // bits already used in the last byte, 0 when the next bit starts a new byte
+    used_bits: u32,
+}
+
+impl<'a> BitWriter<'a> {
+    pub fn new(bytes: &'a mut Vec<u8>) -> Self {
+        Self { bytes, used_bits: 0 }
+    }
+
+    pub fn write_bit(&mut self, bit: bool) {
+        if self.used_bits == 0 {
+            self.bytes.push(0);
+        }
+        if bit {
+            *self.bytes.last_mut().unwrap() |= 0x80 >> self.used_bits;
+        }
+        self.used_bits = (self.used_bits + 1) % 8;
+    }
+
+    // the lowest no_of_bits bits of value
+    pub fn write_bits(&mut self, value: u64, no_of_bits: u32) {
+        for bit in (0..no_of_bits).rev() {
+            self.write_bit(value >> bit & 1 == 1);
+        }
+    }
+
+    // n zero bits followed by a one bit
+    pub fn write_unary(&mut self, n: u32) {
+        for _ in 0..n {
+            self.write_bit(false);
+        }
+        self.write_bit(true);
+    }
+}
+
+pub struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    pub fn read_bit(&mut self) -> Option<bool> {
+        let byte = self.bytes.get(self.position / 8)?;
+        let bit = byte & (0x80 >> (self.position % 8)) != 0;
+        self.position += 1;
+        Some(bit)
+    }
+
+    pub fn read_bits(&mut self, no_of_bits: u32) -> Option<u64> {
+        let mut value = 0;
+        for _ in 0..no_of_bits {
+            value = value << 1 | self.read_bit()? as u64;
+        }
+        Some(value)
+    }
+
+    // counts the zero bits up to the next one bit, None if there are more than max_zeros
+    pub fn read_unary(&mut self, max_zeros: u32) -> Option<u32> {
+        let mut zeros = 0;
+        while !self.read_bit()? {
+            zeros += 1;
+            if zeros > max_zeros {
+                return None;
+            }
+        }
+        Some(zeros)
+    }
+
+    // whole bytes taken by the bits read so far
+    pub fn bytes_read(&self) -> usize {
+        self.position.div_ceil(8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bits_round_trip() {
+        let mut bytes = Vec::new();
+        let mut writer = BitWriter::new(&mut bytes);
+        writer.write_bits(0b101, 3);
+        writer.write_unary(2);
+        writer.write_bits(0xabcd, 16);
+        assert_eq!(bytes.len(), 3);
+        assert_eq!(bytes[0], 0b1010_0110);
+
+        let mut reader = BitReader::new(&bytes);
+        assert_eq!(reader.read_bits(3), Some(0b101));
+        assert_eq!(reader.read_unary(8), Some(2));
+        assert_eq!(reader.read_bits(16), Some(0xabcd));
+        assert_eq!(reader.bytes_read(), 3);
+        // the padding of the last byte can be read, the end of the bytes can not
+        assert_eq!(reader.read_bits(2), Some(0));
+        assert_eq!(reader.read_bit(), None);
+    }
+}
@@ -0,0 +1,116 @@
+use crate::compressors::{
+    bits::{BitReader, BitWriter},
+    posting_codec::PostingCodec,
+};
+
+/*
+Elias gamma and delta codes only exist for numbers from 1, every value is
+encoded as value + 1 so gaps of 0, like a first position of 0, can be
+encoded as well.
+
+gamma(x)-> floor(log2 x) zero bits, then x in binary (starting with its 1 bit)
+delta(x)-> gamma(number of bits of x), then x in binary without its leading 1 bit
+ */
+pub struct EliasGammaCodec;
+
+pub struct EliasDeltaCodec;
+
+// floor(log2 x) for x >= 1
+fn log2(x: u64) -> u32 {
+    63 - x.leading_zeros()
+}
+
+fn write_gamma(writer: &mut BitWriter, x: u64) {
+    let no_of_bits = log2(x);
+    writer.write_unary(no_of_bits);
+    writer.write_bits(x, no_of_bits);
+}
+
+// x is at most 2^32, one more than the largest u32
+fn read_gamma(reader: &mut BitReader) -> Option<u64> {
+    let no_of_bits = reader.read_unary(32)?;
+    Some(1 << no_of_bits | reader.read_bits(no_of_bits)?)
+}
+
+fn to_value(x: u64) -> Option<u32> {
+    u32::try_from(x - 1).ok()
+}
+
+fn decode_values(
+    bytes: &[u8],
+    count: usize,
+    read_value: fn(&mut BitReader) -> Option<u64>,
+) -> Option<(Vec<u32>, usize)> {
+    let mut reader = BitReader::new(bytes);
+    let mut values = Vec::with_capacity(count);
+    for _ in 0..count {
+        values.push(to_value(read_value(&mut reader)?)?);
+    }
+    Some((values, reader.bytes_read()))
+}
+
+impl PostingCodec for EliasGammaCodec {
+    fn encode(&self, values: &[u32], bytes: &mut Vec<u8>) {
+        let mut writer = BitWriter::new(bytes);
+        for value in values {
+            write_gamma(&mut writer, *value as u64 + 1);
+        }
+    }
+
+    fn decode(&self, bytes: &[u8], count: usize) -> Option<(Vec<u32>, usize)> {
+        decode_values(bytes, count, read_gamma)
+    }
+}
+
+impl PostingCodec for EliasDeltaCodec {
+    fn encode(&self, values: &[u32], bytes: &mut Vec<u8>) {
+        let mut writer = BitWriter::new(bytes);
+        for value in values {
+            let x = *value as u64 + 1;
+            let no_of_bits = log2(x);
+            write_gamma(&mut writer, no_of_bits as u64 + 1);
+            writer.write_bits(x, no_of_bits);
+        }
+    }
+
+    fn decode(&self, bytes: &[u8], count: usize) -> Option<(Vec<u32>, usize)> {
+        decode_values(bytes, count, |reader| {
+            let no_of_bits = read_gamma(reader)? - 1;
+            if no_of_bits > 32 {
+                return None;
+            }
+            Some(1 << no_of_bits | reader.read_bits(no_of_bits as u32)?)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(codec: &dyn PostingCodec, values: &[u32]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        codec.encode(values, &mut bytes);
+        bytes
+    }
+
+    #[test]
+    fn test_gamma_codes() {
+        // 1 -> 1, 2 -> 010, 5 -> 00101
+        assert_eq!(encode(&EliasGammaCodec, &[0]), vec![0b1000_0000]);
+        assert_eq!(encode(&EliasGammaCodec, &[0, 1, 4]), vec![0b1010_0010, 0b1000_0000]);
+    }
+
+    #[test]
+    fn test_delta_codes() {
+        // 1 -> 1, 2 -> 0100, 9 -> 00100001
+        assert_eq!(encode(&EliasDeltaCodec, &[0, 1]), vec![0b1010_0000]);
+        assert_eq!(encode(&EliasDeltaCodec, &[8]), vec![0b0010_0001]);
+    }
+
+    #[test]
+    fn test_delta_is_shorter_than_gamma_for_large_values() {
+        let values = [100_000, 250_000, 1_000_000];
+        assert!(encode(&EliasDeltaCodec, &values).len() < encode(&EliasGammaCodec, &values).len());
+    }
+}
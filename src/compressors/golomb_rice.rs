@@ -0,0 +1,85 @@
+use crate::compressors::{
+    bits::{BitReader, BitWriter},
+    posting_codec::PostingCodec,
+};
+
+// the most zero bits of a quotient, k is raised until the largest value stays within it
+const MAX_QUOTIENT_BITS: u32 = 6;
+
+/*
+Golomb coding with a power of two as the divisor. Every value is split into
+value >> k, written in unary, and its lowest k bits written as they are.
+Gaps spread evenly around their mean, as doc id gaps of a term tend to be,
+are coded in about log2(mean) + 2 bits.
+
+Layout->
+k (u8) bits of the values
+ */
+pub struct GolombRiceCodec;
+
+fn no_of_bits(value: u64) -> u32 {
+    64 - value.leading_zeros()
+}
+
+// k close to log2 of the mean, the best divisor for geometrically distributed gaps, but
+// large enough that the quotient of the largest value stays short
+fn choose_k(values: &[u32]) -> u32 {
+    let sum: u64 = values.iter().map(|value| *value as u64).sum();
+    let mean = sum / values.len().max(1) as u64;
+    let max = values.iter().max().copied().unwrap_or(0) as u64;
+    no_of_bits(mean)
+        .saturating_sub(1)
+        .max(no_of_bits(max).saturating_sub(MAX_QUOTIENT_BITS))
+}
+
+impl PostingCodec for GolombRiceCodec {
+    fn encode(&self, values: &[u32], bytes: &mut Vec<u8>) {
+        let k = choose_k(values);
+        bytes.push(k as u8);
+        let mut writer = BitWriter::new(bytes);
+        for value in values {
+            writer.write_unary(value >> k);
+            writer.write_bits(*value as u64, k);
+        }
+    }
+
+    fn decode(&self, bytes: &[u8], count: usize) -> Option<(Vec<u32>, usize)> {
+        let k = *bytes.first()? as u32;
+        if k > 32 {
+            return None;
+        }
+        let mut reader = BitReader::new(&bytes[1..]);
+        let mut values = Vec::with_capacity(count);
+        for _ in 0..count {
+            let quotient = reader.read_unary(1 << MAX_QUOTIENT_BITS)? as u64;
+            let value = quotient << k | reader.read_bits(k)?;
+            values.push(u32::try_from(value).ok()?);
+        }
+        Some((values, 1 + reader.bytes_read()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_choose_k() {
+        assert_eq!(choose_k(&[]), 0);
+        assert_eq!(choose_k(&[1, 1, 1]), 0);
+        assert_eq!(choose_k(&[8, 9, 10, 12]), 3);
+        // an outlier keeps its quotient below 2^6
+        let mut values = vec![1; 127];
+        values.push(1 << 20);
+        assert_eq!(choose_k(&values), 15);
+    }
+
+    #[test]
+    fn test_rice_codes() {
+        // k = 2, 9 -> 00 1 01, 6 -> 0 1 10
+        let mut bytes = Vec::new();
+        GolombRiceCodec.encode(&[9, 6], &mut bytes);
+        assert_eq!(bytes, vec![2, 0b0010_1011, 0b0000_0000]);
+        assert_eq!(GolombRiceCodec.decode(&bytes, 2), Some((vec![9, 6], 3)));
+    }
+}
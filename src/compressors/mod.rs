@@ -1 +1,7 @@
+pub mod bits;
+pub mod elias;
+pub mod golomb_rice;
+pub mod pfor_delta;
+pub mod posting_codec;
+pub mod simple;
 pub mod vb_encode;
@@ -0,0 +1,210 @@
+use crate::compressors::{
+    posting_codec::PostingCodec,
+    vb_encode::{vb_decode, vb_encode},
+};
+
+// values per block, the number of postings of a full chunk
+pub const BLOCK_LEN: usize = 128;
+
+/*
+PForDelta packs a block of up to 128 values with the same number of bits b.
+b is picked for the block so that the values fitting in b bits and the
+exceptions, the few larger values, take the fewest bytes together. An
+exception keeps its lowest b bits in its slot, the rest of it is stored
+after the packed values with its position in the block.
+
+Block Layout->
+b (u8) no_of_exceptions (u8) packed values exceptions
+
+The packed values are no_of_values * b bits filled from the lowest bit of
+the first byte up, padded to a whole byte. Every exception is its position
+(u8) followed by value >> b variable byte encoded. A sequence longer than
+a block is a list of blocks, the last one holding what is left.
+ */
+pub struct PForDeltaCodec;
+
+fn vb_length(value: u32) -> usize {
+    (32 - value.leading_zeros()).div_ceil(7).max(1) as usize
+}
+
+fn packed_length(no_of_values: usize, b: u32) -> usize {
+    (no_of_values * b as usize).div_ceil(8)
+}
+
+fn is_exception(value: u32, b: u32) -> bool {
+    b < 32 && value >> b != 0
+}
+
+fn block_length(values: &[u32], b: u32) -> usize {
+    let exceptions_length: usize = values
+        .iter()
+        .filter(|value| is_exception(**value, b))
+        .map(|value| 1 + vb_length(value >> b))
+        .sum();
+    2 + packed_length(values.len(), b) + exceptions_length
+}
+
+// the smallest encoding, on a tie the one with fewer exceptions to patch
+fn choose_b(values: &[u32]) -> u32 {
+    (0..=32)
+        .min_by_key(|b| {
+            let no_of_exceptions = values.iter().filter(|value| is_exception(**value, *b)).count();
+            (block_length(values, *b), no_of_exceptions)
+        })
+        .unwrap()
+}
+
+fn mask(b: u32) -> u64 {
+    (1 << b) - 1
+}
+
+fn encode_block(values: &[u32], bytes: &mut Vec<u8>) {
+    let b = choose_b(values);
+    let exceptions: Vec<usize> = (0..values.len())
+        .filter(|i| is_exception(values[*i], b))
+        .collect();
+    bytes.push(b as u8);
+    bytes.push(exceptions.len() as u8);
+
+    let mut buffer: u64 = 0;
+    let mut buffered_bits = 0;
+    for value in values {
+        buffer |= (*value as u64 & mask(b)) << buffered_bits;
+        buffered_bits += b;
+        while buffered_bits >= 8 {
+            bytes.push(buffer as u8);
+            buffer >>= 8;
+            buffered_bits -= 8;
+        }
+    }
+    if buffered_bits > 0 {
+        bytes.push(buffer as u8);
+    }
+
+    for i in exceptions {
+        bytes.push(i as u8);
+        bytes.extend(vb_encode(&(values[i] >> b)));
+    }
+}
+
+// decodes a block of count values into the start of values, returns the number of bytes
+// the block takes
+pub fn decode_block(bytes: &[u8], count: usize, values: &mut [u32; BLOCK_LEN]) -> Option<usize> {
+    if count > BLOCK_LEN || bytes.len() < 2 {
+        return None;
+    }
+    let b = bytes[0] as u32;
+    let no_of_exceptions = bytes[1] as usize;
+    if b > 32 || no_of_exceptions > count {
+        return None;
+    }
+    let mut offset = 2;
+    let packed_end = offset + packed_length(count, b);
+    if packed_end > bytes.len() {
+        return None;
+    }
+
+    let mut buffer: u64 = 0;
+    let mut buffered_bits = 0;
+    for value in values[..count].iter_mut() {
+        while buffered_bits < b {
+            buffer |= (bytes[offset] as u64) << buffered_bits;
+            buffered_bits += 8;
+            offset += 1;
+        }
+        *value = (buffer & mask(b)) as u32;
+        buffer >>= b;
+        buffered_bits -= b;
+    }
+    offset = packed_end;
+
+    for _ in 0..no_of_exceptions {
+        let i = *bytes.get(offset)? as usize;
+        offset += 1;
+        let (high_bits, bytes_read) = vb_decode(&bytes[offset..]);
+        // a value cut off by the end of the bytes has no last byte
+        if i >= count || bytes_read == 0 || bytes[offset + bytes_read - 1] < 128 {
+            return None;
+        }
+        offset += bytes_read;
+        values[i] = u32::try_from((high_bits as u64) << b | values[i] as u64).ok()?;
+    }
+    Some(offset)
+}
+
+impl PostingCodec for PForDeltaCodec {
+    fn encode(&self, values: &[u32], bytes: &mut Vec<u8>) {
+        for block in values.chunks(BLOCK_LEN) {
+            encode_block(block, bytes);
+        }
+    }
+
+    fn decode(&self, bytes: &[u8], count: usize) -> Option<(Vec<u32>, usize)> {
+        let mut values = Vec::with_capacity(count);
+        let mut block = [0; BLOCK_LEN];
+        let mut offset = 0;
+        while values.len() < count {
+            let block_count = (count - values.len()).min(BLOCK_LEN);
+            offset += decode_block(&bytes[offset..], block_count, &mut block)?;
+            values.extend_from_slice(&block[..block_count]);
+        }
+        Some((values, offset))
+    }
+
+    fn decode_block(&self, bytes: &[u8], count: usize, values: &mut [u32; BLOCK_LEN]) -> Option<usize> {
+        decode_block(bytes, count, values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(values: &[u32]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        PForDeltaCodec.encode(values, &mut bytes);
+        bytes
+    }
+
+    #[test]
+    fn test_values_are_packed_with_the_same_bits() {
+        // 3 bits each, 5 | 2 << 3 | 7 << 6 | 1 << 9
+        assert_eq!(encode(&[5, 2, 7, 1]), vec![3, 0, 0b1101_0101, 0b0000_0011]);
+        // runs of consecutive doc ids are gaps of 0 and take no bits at all
+        assert_eq!(encode(&[0; 128]), vec![0, 0]);
+    }
+
+    #[test]
+    fn test_large_values_are_exceptions() {
+        let mut values = vec![3; 100];
+        values[42] = 100_000;
+        let bytes = encode(&values);
+        // 2 bits for every value, 100_000 >> 2 is patched in from 3 bytes after its position
+        assert_eq!(&bytes[..2], &[2, 1]);
+        assert_eq!(bytes.len(), 2 + 25 + 1 + 3);
+        assert_eq!(bytes[27], 42);
+
+        let mut block = [0; BLOCK_LEN];
+        assert_eq!(decode_block(&bytes, values.len(), &mut block), Some(bytes.len()));
+        assert_eq!(&block[..values.len()], &values[..]);
+    }
+
+    #[test]
+    fn test_sequences_are_split_into_blocks() {
+        let values: Vec<u32> = (0..300).map(|i| i % 5).collect();
+        let bytes = encode(&values);
+        // two full blocks and one of 44 values, 3 bits each
+        assert_eq!(bytes.len(), 2 * (2 + 48) + 2 + 17);
+        assert_eq!(PForDeltaCodec.decode(&bytes, values.len()), Some((values, bytes.len())));
+    }
+
+    #[test]
+    fn test_invalid_blocks_are_not_decoded() {
+        let mut block = [0; BLOCK_LEN];
+        assert_eq!(decode_block(&[33, 0], 1, &mut block), None);
+        // an exception past the values of the block
+        assert_eq!(decode_block(&[0, 1, 5, 0x81], 2, &mut block), None);
+        // high bits shifted out of a u32
+        assert_eq!(decode_block(&[31, 1, 0, 0, 0, 0, 0, 0x84], 1, &mut block), None);
+    }
+}
@@ -0,0 +1,266 @@
+use serde::{Deserialize, Serialize};
+
+use crate::compressors::{
+    elias::{EliasDeltaCodec, EliasGammaCodec},
+    golomb_rice::GolombRiceCodec,
+    pfor_delta::{BLOCK_LEN, PForDeltaCodec},
+    simple::{SIMPLE_16, SIMPLE_9},
+    vb_encode::VariableByteCodec,
+};
+
+/*
+Compresses a sequence of small numbers, the doc id gaps of a chunk. The
+values of a sequence are encoded one after the other without a separator,
+the decoder is told how many values to read. Codecs working on bits pad
+the last byte of a sequence.
+ */
+pub trait PostingCodec {
+    // appends the encoding of the values to bytes
+    fn encode(&self, values: &[u32], bytes: &mut Vec<u8>);
+
+    // decodes count values from the start of bytes together with the number of bytes they
+    // take, None if bytes end before count values or do not hold a valid encoding
+    fn decode(&self, bytes: &[u8], count: usize) -> Option<(Vec<u32>, usize)>;
+
+    // same as decode for at most BLOCK_LEN values, which are written to the start of values.
+    // The chunk reader decodes every chunk into the same buffer this way instead of
+    // allocating a Vec per chunk
+    fn decode_block(&self, bytes: &[u8], count: usize, values: &mut [u32; BLOCK_LEN]) -> Option<usize> {
+        if count > BLOCK_LEN {
+            return None;
+        }
+        let (decoded, bytes_read) = self.decode(bytes, count)?;
+        values[..count].copy_from_slice(&decoded);
+        Some(bytes_read)
+    }
+
+    // whether every value can be encoded, the Simple codecs only hold values up to 2^28 - 1
+    fn can_encode(&self, _values: &[u32]) -> bool {
+        true
+    }
+}
+
+/*
+The codecs an index can be built with. The id is stored in the header of
+final.idx for the codec picked for the index and in front of every chunk
+for the codec its doc ids were encoded with, a chunk whose values the
+picked codec can not encode is written with variable byte encoding.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PostingCodecId {
+    #[default]
+    VariableByte,
+    EliasGamma,
+    EliasDelta,
+    GolombRice,
+    Simple9,
+    Simple16,
+    PForDelta,
+}
+
+impl PostingCodecId {
+    pub const ALL: [Self; 7] = [
+        Self::VariableByte,
+        Self::EliasGamma,
+        Self::EliasDelta,
+        Self::GolombRice,
+        Self::Simple9,
+        Self::Simple16,
+        Self::PForDelta,
+    ];
+
+    pub fn to_u8(self) -> u8 {
+        match self {
+            Self::VariableByte => 1,
+            Self::EliasGamma => 2,
+            Self::EliasDelta => 3,
+            Self::GolombRice => 4,
+            Self::Simple9 => 5,
+            Self::Simple16 => 6,
+            Self::PForDelta => 7,
+        }
+    }
+
+    pub fn from_u8(id: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|codec_id| codec_id.to_u8() == id)
+    }
+
+    pub fn codec(self) -> &'static dyn PostingCodec {
+        match self {
+            Self::VariableByte => &VariableByteCodec,
+            Self::EliasGamma => &EliasGammaCodec,
+            Self::EliasDelta => &EliasDeltaCodec,
+            Self::GolombRice => &GolombRiceCodec,
+            Self::Simple9 => &SIMPLE_9,
+            Self::Simple16 => &SIMPLE_16,
+            Self::PForDelta => &PForDeltaCodec,
+        }
+    }
+}
+
+// encodes the values with the codec, or with variable byte encoding if the codec can not
+// encode all of them. Returns the codec that was used
+pub fn encode_with_fallback(
+    codec_id: PostingCodecId,
+    values: &[u32],
+    bytes: &mut Vec<u8>,
+) -> PostingCodecId {
+    let codec_id = if codec_id.codec().can_encode(values) {
+        codec_id
+    } else {
+        PostingCodecId::VariableByte
+    };
+    codec_id.codec().encode(values, bytes);
+    codec_id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn round_trip(codec_id: PostingCodecId, values: &[u32]) {
+        let mut bytes = vec![0xaa];
+        codec_id.codec().encode(values, &mut bytes);
+        // whatever follows the values is left alone
+        bytes.push(0xff);
+        let (decoded, bytes_read) = codec_id
+            .codec()
+            .decode(&bytes[1..], values.len())
+            .unwrap_or_else(|| panic!("{:?} could not decode {:?}", codec_id, values));
+        assert_eq!(decoded, values, "{:?}", codec_id);
+        assert_eq!(bytes_read, bytes.len() - 2, "{:?}", codec_id);
+    }
+
+    #[test]
+    fn test_every_codec_round_trips() {
+        let gaps: Vec<u32> = (0..300).map(|i| (i * 7919) % 97 + 1).collect();
+        let sequences: [&[u32]; 6] = [
+            &[],
+            &[0],
+            &[1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+            &[5, 0, 300, 2, 16384, 1, (1 << 28) - 1, 7],
+            &[1, 2, 1, 3, 1, 1, 100_000, 1, 2],
+            &gaps,
+        ];
+        for codec_id in PostingCodecId::ALL {
+            for values in sequences {
+                round_trip(codec_id, values);
+            }
+        }
+        for codec_id in [
+            PostingCodecId::VariableByte,
+            PostingCodecId::EliasGamma,
+            PostingCodecId::EliasDelta,
+            PostingCodecId::GolombRice,
+            PostingCodecId::PForDelta,
+        ] {
+            round_trip(codec_id, &[u32::MAX, 0, u32::MAX - 1, 3]);
+        }
+    }
+
+    #[test]
+    fn test_truncated_values_are_not_decoded() {
+        let values: Vec<u32> = (1..=40).collect();
+        for codec_id in PostingCodecId::ALL {
+            let mut bytes = Vec::new();
+            codec_id.codec().encode(&values, &mut bytes);
+            assert_eq!(
+                codec_id.codec().decode(&bytes[..bytes.len() - 1], values.len()),
+                None,
+                "{:?}",
+                codec_id
+            );
+        }
+    }
+
+    #[test]
+    fn test_decode_block_matches_decode() {
+        let values: Vec<u32> = (0..BLOCK_LEN as u32).map(|i| (i * 7919) % 97).collect();
+        for codec_id in PostingCodecId::ALL {
+            let mut bytes = Vec::new();
+            codec_id.codec().encode(&values, &mut bytes);
+            let mut block = [u32::MAX; BLOCK_LEN];
+            assert_eq!(
+                codec_id.codec().decode_block(&bytes, values.len(), &mut block),
+                Some(bytes.len()),
+                "{:?}",
+                codec_id
+            );
+            assert_eq!(&block[..], &values[..], "{:?}", codec_id);
+            let truncated = &bytes[..bytes.len() - 1];
+            assert_eq!(codec_id.codec().decode_block(truncated, values.len(), &mut block), None);
+        }
+    }
+
+    // the time PForDelta and variable byte encoding take to decode the doc id gaps of full
+    // chunks into one buffer, as the chunk reader does. Only meaningful in a release build:
+    // cargo test --release decode_time -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn test_decode_time_of_pfor_delta_and_variable_byte() {
+        // gaps of a term in about one of every 20 documents, with a few long jumps
+        let chunks: Vec<Vec<u32>> = (0..1000u32)
+            .map(|chunk| {
+                (0..BLOCK_LEN as u32)
+                    .map(|i| match (chunk * 31 + i * 7919) % 257 {
+                        0 => 70_000 + i,
+                        gap => gap % 40,
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut block = [0; BLOCK_LEN];
+        for codec_id in [PostingCodecId::VariableByte, PostingCodecId::PForDelta] {
+            let encoded: Vec<Vec<u8>> = chunks
+                .iter()
+                .map(|values| {
+                    let mut bytes = Vec::new();
+                    codec_id.codec().encode(values, &mut bytes);
+                    bytes
+                })
+                .collect();
+            let started = Instant::now();
+            let mut checksum: u64 = 0;
+            for _ in 0..100 {
+                for bytes in &encoded {
+                    codec_id.codec().decode_block(bytes, BLOCK_LEN, &mut block).unwrap();
+                    checksum += block.iter().map(|value| *value as u64).sum::<u64>();
+                }
+            }
+            let elapsed = started.elapsed();
+            assert_eq!(
+                checksum,
+                100 * chunks.iter().flatten().map(|value| *value as u64).sum::<u64>()
+            );
+            println!(
+                "{:?}: {} bytes, {:.2} ns per value",
+                codec_id,
+                encoded.iter().map(|bytes| bytes.len()).sum::<usize>(),
+                elapsed.as_nanos() as f64 / (100 * chunks.len() * BLOCK_LEN) as f64
+            );
+        }
+    }
+
+    #[test]
+    fn test_codec_ids() {
+        for codec_id in PostingCodecId::ALL {
+            assert_eq!(PostingCodecId::from_u8(codec_id.to_u8()), Some(codec_id));
+        }
+        assert_eq!(PostingCodecId::from_u8(0), None);
+        assert_eq!(PostingCodecId::VariableByte.to_u8(), 1);
+    }
+
+    #[test]
+    fn test_values_too_large_fall_back_to_variable_byte() {
+        let mut bytes = Vec::new();
+        assert_eq!(
+            encode_with_fallback(PostingCodecId::Simple9, &[1, 1 << 28], &mut bytes),
+            PostingCodecId::VariableByte
+        );
+        assert_eq!(
+            encode_with_fallback(PostingCodecId::Simple16, &[1, 2, 3], &mut bytes),
+            PostingCodecId::Simple16
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use crate::compressors::posting_codec::PostingCodec;
+
+/*
+Simple-9 and Simple-16 pack as many values as fit into a 32 bit word. The
+top 4 bits of a word select how its other 28 bits are split into values,
+the values fill the word from its lowest bits up. The encoder takes the
+first layout, in order of decreasing number of values, that holds the next
+values. Words are stored little endian, the last word of a sequence may
+have unused slots, the decoder stops after the number of values it is asked
+for.
+
+A layout is a list of (number of values, bits per value).
+ */
+pub struct SimpleCodec {
+    layouts: &'static [&'static [(u32, u32)]],
+}
+
+const DATA_BITS: u32 = 28;
+
+pub const SIMPLE_9: SimpleCodec = SimpleCodec {
+    layouts: &[
+        &[(28, 1)],
+        &[(14, 2)],
+        &[(9, 3)],
+        &[(7, 4)],
+        &[(5, 5)],
+        &[(4, 7)],
+        &[(3, 9)],
+        &[(2, 14)],
+        &[(1, 28)],
+    ],
+};
+
+// Simple-9 wastes up to 3 bits of a word, Simple-16 uses all 28 with layouts mixing widths
+pub const SIMPLE_16: SimpleCodec = SimpleCodec {
+    layouts: &[
+        &[(28, 1)],
+        &[(7, 2), (14, 1)],
+        &[(7, 1), (7, 2), (7, 1)],
+        &[(14, 1), (7, 2)],
+        &[(14, 2)],
+        &[(1, 4), (8, 3)],
+        &[(1, 3), (4, 4), (3, 3)],
+        &[(7, 4)],
+        &[(4, 5), (2, 4)],
+        &[(2, 4), (4, 5)],
+        &[(3, 6), (2, 5)],
+        &[(2, 5), (3, 6)],
+        &[(4, 7)],
+        &[(1, 10), (2, 9)],
+        &[(2, 14)],
+        &[(1, 28)],
+    ],
+};
+
+// bits of every slot of the layout in order
+fn slot_widths(layout: &[(u32, u32)]) -> impl Iterator<Item = u32> + '_ {
+    layout
+        .iter()
+        .flat_map(|(no_of_values, no_of_bits)| (0..*no_of_values).map(move |_| *no_of_bits))
+}
+
+impl PostingCodec for SimpleCodec {
+    fn encode(&self, values: &[u32], bytes: &mut Vec<u8>) {
+        let mut remaining = values;
+        while !remaining.is_empty() {
+            let selector = self
+                .layouts
+                .iter()
+                .position(|layout| {
+                    slot_widths(layout)
+                        .zip(remaining)
+                        .all(|(no_of_bits, value)| (*value as u64) < 1 << no_of_bits)
+                })
+                .expect("values encoded with a Simple codec are below 2^28");
+            let mut word = (selector as u32) << DATA_BITS;
+            let mut shift = 0;
+            let mut no_of_values = 0;
+            for (no_of_bits, value) in slot_widths(self.layouts[selector]).zip(remaining) {
+                word |= value << shift;
+                shift += no_of_bits;
+                no_of_values += 1;
+            }
+            bytes.extend_from_slice(&word.to_le_bytes());
+            remaining = &remaining[no_of_values..];
+        }
+    }
+
+    fn decode(&self, bytes: &[u8], count: usize) -> Option<(Vec<u32>, usize)> {
+        let mut values = Vec::with_capacity(count);
+        let mut offset = 0;
+        while values.len() < count {
+            let word = u32::from_le_bytes(bytes.get(offset..offset + 4)?.try_into().unwrap());
+            offset += 4;
+            let layout = self.layouts.get((word >> DATA_BITS) as usize)?;
+            let mut shift = 0;
+            for no_of_bits in slot_widths(layout) {
+                if values.len() == count {
+                    break;
+                }
+                values.push(word >> shift & ((1 << no_of_bits) - 1));
+                shift += no_of_bits;
+            }
+        }
+        Some((values, offset))
+    }
+
+    fn can_encode(&self, values: &[u32]) -> bool {
+        values.iter().all(|value| *value < 1 << DATA_BITS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(codec: &SimpleCodec, values: &[u32]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        codec.encode(values, &mut bytes);
+        bytes
+    }
+
+    #[test]
+    fn test_layouts_use_28_bits() {
+        for layout in SIMPLE_9.layouts.iter().chain(SIMPLE_16.layouts) {
+            assert!(slot_widths(layout).sum::<u32>() <= DATA_BITS);
+        }
+        for layout in SIMPLE_16.layouts {
+            assert_eq!(slot_widths(layout).sum::<u32>(), DATA_BITS);
+        }
+    }
+
+    #[test]
+    fn test_values_are_packed_into_words() {
+        // 28 gaps of 1 take a single word with selector 0
+        assert_eq!(encode(&SIMPLE_9, &[1; 28]), ((1u32 << 28) - 1).to_le_bytes().to_vec());
+        // 4 does not fit 2 bits, both values go into slots of 3 bits
+        let word = u32::from_le_bytes(encode(&SIMPLE_9, &[3, 4]).try_into().unwrap());
+        assert_eq!(word >> 28, 2);
+        assert_eq!(word & 0xff, 0x23);
+    }
+
+    #[test]
+    fn test_simple_16_packs_tighter() {
+        // a value of 4 bits followed by values of 3 bits fits one Simple-16 word
+        let values = [9, 1, 2, 3, 1, 0, 2, 1, 3];
+        assert_eq!(encode(&SIMPLE_16, &values).len(), 4);
+        assert_eq!(encode(&SIMPLE_9, &values).len(), 8);
+        assert!(!SIMPLE_16.can_encode(&[1 << 28]));
+    }
+}
@@ -1,3 +1,5 @@
+use crate::compressors::{pfor_delta::BLOCK_LEN, posting_codec::PostingCodec};
+
 pub fn vb_encode(num: &u32) -> Vec<u8> {
     let mut mutable_num = num.clone();
     let mut bytes = Vec::<u8>::new();
@@ -30,6 +32,48 @@ pub fn vb_decode(bytes: &[u8]) -> (u32, usize) {
     (number, bytes_read)
 }
 
+// every value in bytes of 7 bits, the high bit marks the last byte of a value
+pub struct VariableByteCodec;
+
+impl PostingCodec for VariableByteCodec {
+    fn encode(&self, values: &[u32], bytes: &mut Vec<u8>) {
+        for value in values {
+            bytes.extend(vb_encode(value));
+        }
+    }
+
+    fn decode(&self, bytes: &[u8], count: usize) -> Option<(Vec<u32>, usize)> {
+        let mut values = Vec::with_capacity(count);
+        let mut offset = 0;
+        for _ in 0..count {
+            let (value, bytes_read) = vb_decode(&bytes[offset..]);
+            // a value cut off by the end of the bytes has no last byte
+            if bytes_read == 0 || bytes[offset + bytes_read - 1] < 128 {
+                return None;
+            }
+            values.push(value);
+            offset += bytes_read;
+        }
+        Some((values, offset))
+    }
+
+    fn decode_block(&self, bytes: &[u8], count: usize, values: &mut [u32; BLOCK_LEN]) -> Option<usize> {
+        if count > BLOCK_LEN {
+            return None;
+        }
+        let mut offset = 0;
+        for value in values[..count].iter_mut() {
+            let (decoded, bytes_read) = vb_decode(&bytes[offset..]);
+            if bytes_read == 0 || bytes[offset + bytes_read - 1] < 128 {
+                return None;
+            }
+            *value = decoded;
+            offset += bytes_read;
+        }
+        Some(offset)
+    }
+}
+
 #[cfg(test)]
 mod vb_decode_tests {
     use super::*;
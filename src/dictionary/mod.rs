@@ -14,28 +14,59 @@ pub struct Term {
     pub posting: Posting,
 }
 
+// what SPIMI may hold in memory before it flushes a run, unless configured otherwise
+pub const DEFAULT_MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+
+// a hash map slot holds the key and the value inline plus one control byte, and the map
+// keeps at most 7 of every 8 slots filled
+const ENTRY_SIZE: usize = (size_of::<(String, Vec<Posting>)>() + 1) * 8 / 7;
+
+/*
+Keeps count of the bytes the dictionary holds on the heap, the hash map
+slots, the term strings, the posting vectors and the position vectors.
+Capacities are counted rather than lengths, a vector that grew to hold one
+more posting holds the memory of its whole new capacity.
+ */
 #[derive(Debug, Clone)]
 pub struct Dictionary {
-    current_size: u32,
+    current_size: usize,
+    memory_budget: usize,
     dictionary: HashMap<String, Vec<Posting>>,
 }
 
 impl Dictionary {
     pub fn new() -> Dictionary {
-        return Dictionary {
+        Self::with_memory_budget(DEFAULT_MEMORY_BUDGET)
+    }
+
+    pub fn with_memory_budget(memory_budget: usize) -> Dictionary {
+        Dictionary {
             current_size: 0,
+            memory_budget,
             dictionary: HashMap::new(),
-        };
+        }
     }
 
-    pub fn max_size(& self)->u32{
-        return 10000000;
+    pub fn max_size(& self)->usize{
+        self.memory_budget
     }
-    pub fn size(& self) -> u32 {
+
+    pub fn set_max_size(&mut self, memory_budget: usize) {
+        self.memory_budget = memory_budget;
+    }
+
+    // estimated number of bytes held by the dictionary
+    pub fn size(& self) -> usize {
         self.current_size
     }
+
     pub fn clear(&mut self) {
         self.dictionary.clear();
+        self.current_size = 0;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dictionary.is_empty()
     }
 
     pub fn does_term_already_exist(&mut self, term: &str) -> bool {
@@ -43,14 +74,19 @@ impl Dictionary {
     }
 
     pub fn add_term_posting(&mut self, term: &str, posting: Vec<Posting>) {
-        let posting_list = posting.clone();
-        self.dictionary.insert(String::from(term), posting);
-        for posting in posting_list {
-            let posting_length = posting.positions.len() as u32;
-            self.current_size += 4 + 4 * posting_length;
+        let postings_size = |postings: &Vec<Posting>| {
+            postings.capacity() * size_of::<Posting>()
+                + postings
+                    .iter()
+                    .map(|posting| posting.positions.capacity() * size_of::<u32>())
+                    .sum::<usize>()
+        };
+        let added_size = postings_size(&posting);
+        match self.dictionary.insert(String::from(term), posting) {
+            Some(replaced_posting) => self.current_size -= postings_size(&replaced_posting),
+            None => self.current_size += ENTRY_SIZE + term.len(),
         }
-        self.current_size+=4;
-        self.current_size += term.len() as u32;
+        self.current_size += added_size;
     }
 
     pub fn get_postings(&self, term: &str) -> Option<Vec<Posting>> {
@@ -63,16 +99,16 @@ impl Dictionary {
     pub fn add_term(&mut self, term: &str) {
         if !self.does_term_already_exist(term) {
             self.dictionary.insert(String::from(term), Vec::new());
-            self.current_size+=4;
-            self.current_size +=term.len() as u32
+            self.current_size += ENTRY_SIZE + term.len();
         }
     }
 
     pub fn append_to_term(&mut self, term: &str, posting: Posting) {
         if let Some(postings_list) = self.dictionary.get_mut(term) {
-            let posting_length = posting.positions.len() as u32;
+            let capacity = postings_list.capacity();
+            self.current_size += posting.positions.capacity() * size_of::<u32>();
             postings_list.push(posting);
-            self.current_size += 4 + 4 * posting_length;
+            self.current_size += (postings_list.capacity() - capacity) * size_of::<Posting>();
         }
     }
 
@@ -90,6 +126,15 @@ impl Dictionary {
 mod dictionary_tests {
     use super::*;
 
+    fn postings_size(dict: &Dictionary, term: &str) -> usize {
+        let postings = &dict.dictionary[term];
+        postings.capacity() * size_of::<Posting>()
+            + postings
+                .iter()
+                .map(|posting| posting.positions.capacity() * size_of::<u32>())
+                .sum::<usize>()
+    }
+
     #[test]
     fn test_does_term_already_exist_empty_dictionary() {
         let mut dict = Dictionary::new();
@@ -283,6 +328,47 @@ mod dictionary_tests {
         assert_eq!(retrieved_postings[0].positions, vec![1, 5, 10, 15, 20]);
     }
 
+    #[test]
+    fn test_size_counts_terms_postings_and_positions() {
+        let mut dict = Dictionary::new();
+        dict.add_term("rust");
+        assert_eq!(dict.size(), ENTRY_SIZE + 4);
+
+        dict.append_to_term("rust", Posting::new(1, vec![1, 2, 3]));
+        let postings_capacity = dict.dictionary["rust"].capacity();
+        assert_eq!(
+            dict.size(),
+            ENTRY_SIZE + 4 + postings_capacity * size_of::<Posting>() + 3 * size_of::<u32>()
+        );
+        assert_eq!(dict.size(), ENTRY_SIZE + 4 + postings_size(&dict, "rust"));
+
+        // growing the postings vector counts its whole new capacity
+        for doc_id in 2..=100 {
+            dict.append_to_term("rust", Posting::new(doc_id, vec![0]));
+        }
+        assert_eq!(dict.size(), ENTRY_SIZE + 4 + postings_size(&dict, "rust"));
+    }
+
+    #[test]
+    fn test_add_term_posting_replaces_size_of_old_postings() {
+        let mut dict = Dictionary::new();
+        dict.add_term_posting("rust", vec![Posting::new(1, vec![1, 2, 3, 4])]);
+        dict.add_term_posting("rust", vec![Posting::new(2, vec![5])]);
+        assert_eq!(dict.size(), ENTRY_SIZE + 4 + postings_size(&dict, "rust"));
+    }
+
+    #[test]
+    fn test_clear_resets_size() {
+        let mut dict = Dictionary::with_memory_budget(1024);
+        dict.add_term("rust");
+        dict.append_to_term("rust", Posting::new(1, vec![1]));
+        assert!(dict.size() > 0);
+        dict.clear();
+        assert_eq!(dict.size(), 0);
+        assert!(dict.is_empty());
+        assert_eq!(dict.max_size(), 1024);
+    }
+
     #[test]
     fn test_posting_with_empty_positions() {
         let mut dict = Dictionary::new();
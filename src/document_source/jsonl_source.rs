@@ -0,0 +1,138 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+use serde::{Deserialize, Deserializer};
+
+use crate::document_source::{Document, DocumentSource, has_extension};
+
+/*
+One JSON object per line, only id and body are required.
+Exports from other systems frequently use a numeric id or call the
+body "text", both are accepted.
+ */
+#[derive(Debug, Deserialize)]
+struct JsonlRecord {
+    #[serde(deserialize_with = "deserialize_id")]
+    id: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    url: String,
+    #[serde(alias = "text")]
+    body: String,
+}
+
+fn deserialize_id<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = serde_json::Value::deserialize(deserializer)?;
+    match value {
+        serde_json::Value::String(id) => Ok(id),
+        other => Ok(other.to_string()),
+    }
+}
+
+#[derive(Default)]
+pub struct JsonlSource {}
+
+impl JsonlSource {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl DocumentSource for JsonlSource {
+    fn is_supported_file(&self, path: &Path) -> bool {
+        has_extension(path, &["jsonl", "ndjson"])
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<Box<dyn Iterator<Item = Document>>> {
+        let file = File::open(path)?;
+        let reader = BufReader::new(file);
+        let path_for_errors = path.display().to_string();
+
+        let documents = reader
+            .lines()
+            .enumerate()
+            .filter_map(move |(i, line)| {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(e) => {
+                        eprintln!("Error reading line {} of {}: {}", i + 1, path_for_errors, e);
+                        return None;
+                    }
+                };
+                if line.trim().is_empty() {
+                    return None;
+                }
+                match serde_json::from_str::<JsonlRecord>(&line) {
+                    Ok(record) => Some(Document {
+                        id: record.id,
+                        title: record.title,
+                        url: record.url,
+                        body: record.body,
+                        links: Vec::new(),
+                    }),
+                    Err(e) => {
+                        eprintln!("Error parsing line {} of {}: {}", i + 1, path_for_errors, e);
+                        None
+                    }
+                }
+            });
+        Ok(Box::new(documents))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_reads_one_document_per_line() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("export.jsonl");
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, r#"{{"id":"a1","title":"First","url":"https://x/a1","body":"hello world"}}"#).unwrap();
+        writeln!(file).unwrap();
+        writeln!(file, r#"{{"id":42,"text":"numeric id and text alias"}}"#).unwrap();
+
+        let source = JsonlSource::new();
+        assert!(source.is_supported_file(&path));
+        let documents: Vec<Document> = source.read_file(&path).unwrap().collect();
+        assert_eq!(documents.len(), 2);
+        assert_eq!(
+            documents[0],
+            Document {
+                id: "a1".to_string(),
+                title: "First".to_string(),
+                url: "https://x/a1".to_string(),
+                body: "hello world".to_string(),
+                links: Vec::new(),
+            }
+        );
+        assert_eq!(documents[1].id, "42");
+        assert_eq!(documents[1].title, "");
+        assert_eq!(documents[1].body, "numeric id and text alias");
+    }
+
+    #[test]
+    fn test_skips_malformed_lines() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("broken.jsonl");
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, r#"{{"id":"1","body":"ok"}}"#).unwrap();
+        writeln!(file, "not json").unwrap();
+        writeln!(file, r#"{{"title":"missing id and body"}}"#).unwrap();
+        writeln!(file, r#"{{"id":"2","body":"also ok"}}"#).unwrap();
+
+        let source = JsonlSource::new();
+        let ids: Vec<String> = source.read_file(&path).unwrap().map(|d| d.id).collect();
+        assert_eq!(ids, vec!["1", "2"]);
+    }
+}
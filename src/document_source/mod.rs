@@ -0,0 +1,46 @@
+pub mod jsonl_source;
+pub mod plain_text_source;
+pub mod wikipedia_source;
+
+use std::{io, path::Path};
+
+/*
+A document as handed to the indexer by a DocumentSource.
+The id is whatever identifier the corpus uses for the document (the
+Wikipedia article id, a JSONL record id, a file path) and is kept in the
+document metadata so callers can map our doc ids back to their own ids.
+The links are only filled in by sources whose corpus has link markup and
+they are not kept in the document store.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    pub id: String,
+    pub title: String,
+    pub url: String,
+    pub body: String,
+    pub links: Vec<Link>,
+}
+
+// a link to the document titled target, the anchor text is the text of the link as it
+// appears in the linking document
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub target: String,
+    pub anchor_text: String,
+}
+
+pub trait DocumentSource: Send + Sync {
+    // whether the indexer should hand this file to the source while walking the corpus directory
+    fn is_supported_file(&self, path: &Path) -> bool;
+
+    // streams every document contained in the file, documents that cannot be parsed are
+    // reported and skipped so that one bad record does not abort the whole build
+    fn read_file(&self, path: &Path) -> io::Result<Box<dyn Iterator<Item = Document>>>;
+}
+
+pub(crate) fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    match path.extension().and_then(|s| s.to_str()) {
+        Some(extension) => extensions.contains(&extension),
+        None => false,
+    }
+}
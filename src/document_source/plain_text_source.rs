@@ -0,0 +1,67 @@
+use std::{fs, io, path::Path};
+
+use crate::document_source::{Document, DocumentSource, has_extension};
+
+/*
+Every .txt file is a single document, the file name (without the
+extension) is used as the title and the path as both id and url.
+ */
+#[derive(Default)]
+pub struct PlainTextSource {}
+
+impl PlainTextSource {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl DocumentSource for PlainTextSource {
+    fn is_supported_file(&self, path: &Path) -> bool {
+        has_extension(path, &["txt"])
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<Box<dyn Iterator<Item = Document>>> {
+        let body = fs::read_to_string(path)?;
+        let title = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let path_string = path.display().to_string();
+        let document = Document {
+            id: path_string.clone(),
+            title,
+            url: path_string,
+            body,
+            links: Vec::new(),
+        };
+        Ok(Box::new(std::iter::once(document)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_reads_file_as_single_document() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("release_notes.txt");
+        fs::write(&path, "line one\nline two\n").unwrap();
+
+        let source = PlainTextSource::new();
+        assert!(source.is_supported_file(&path));
+        let documents: Vec<Document> = source.read_file(&path).unwrap().collect();
+        assert_eq!(documents.len(), 1);
+        assert_eq!(documents[0].title, "release_notes");
+        assert_eq!(documents[0].id, path.display().to_string());
+        assert_eq!(documents[0].body, "line one\nline two\n");
+    }
+
+    #[test]
+    fn test_only_txt_files_are_supported() {
+        let source = PlainTextSource::new();
+        assert!(!source.is_supported_file(Path::new("notes.md")));
+        assert!(!source.is_supported_file(Path::new("wiki_00.bz2")));
+    }
+}
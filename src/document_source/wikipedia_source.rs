@@ -0,0 +1,207 @@
+use std::{fs::File, io::{self, BufReader}, path::Path};
+
+use bzip2::read::BzDecoder;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::document_source::{Document, DocumentSource, Link, has_extension};
+
+// Define the structure matching your JSON format
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct WikiArticle {
+    pub url: String,
+    pub text: Vec<Vec<String>>,
+    pub id: String,
+    pub title: String,
+}
+
+pub(crate) fn extract_plaintext(text: &[Vec<String>]) -> String {
+    // Join all paragraphs and sentences
+    let full_text = text
+        .iter()
+        .map(|paragraph| paragraph.join(""))
+        .collect::<Vec<String>>()
+        .join("\n\n"); // Separate paragraphs with double newline
+
+    // Remove all HTML/XML tags using regex
+    let tag_regex = Regex::new(r"<[^>]*>").unwrap();
+    tag_regex.replace_all(&full_text, "").to_string()
+}
+
+// the links of the article in the order they appear, the dump keeps the target of every
+// link as a percent encoded title in the href
+pub(crate) fn extract_links(text: &[Vec<String>]) -> Vec<Link> {
+    let link_regex = Regex::new(r#"<a href="([^"]*)">(.*?)</a>"#).unwrap();
+    let tag_regex = Regex::new(r"<[^>]*>").unwrap();
+    text.iter()
+        .flatten()
+        .flat_map(|sentence| link_regex.captures_iter(sentence))
+        .map(|captures| Link {
+            target: percent_decode(&captures[1]),
+            anchor_text: tag_regex.replace_all(&captures[2], "").trim().to_string(),
+        })
+        .filter(|link| !link.target.is_empty())
+        .collect()
+}
+
+fn percent_decode(encoded: &str) -> String {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(hex) = encoded.get(i + 1..i + 3)
+            && let Ok(byte) = u8::from_str_radix(hex, 16)
+        {
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/*
+Reads the processed Wikipedia dump, every .bz2 file holds a stream of
+concatenated JSON articles whose text is a list of paragraphs, each of
+which is a list of sentences still containing the link markup.
+ */
+#[derive(Default)]
+pub struct WikipediaBz2Source {}
+
+impl WikipediaBz2Source {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl DocumentSource for WikipediaBz2Source {
+    fn is_supported_file(&self, path: &Path) -> bool {
+        has_extension(path, &["bz2"])
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<Box<dyn Iterator<Item = Document>>> {
+        let file = File::open(path)?;
+        let decoder = BzDecoder::new(file);
+        let reader = BufReader::new(decoder);
+
+        // Create a streaming deserializer
+        let stream = serde_json::Deserializer::from_reader(reader).into_iter::<WikiArticle>();
+
+        let documents = stream
+            .enumerate()
+            .filter_map(|(i, result)| match result {
+                Ok(article) => Some(Document {
+                    body: extract_plaintext(&article.text),
+                    links: extract_links(&article.text),
+                    id: article.id,
+                    title: article.title,
+                    url: article.url,
+                }),
+                Err(e) => {
+                    eprintln!("Error parsing object {}: {}", i + 1, e);
+                    None
+                }
+            });
+        Ok(Box::new(documents))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bzip2::{Compression, write::BzEncoder};
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    fn write_bz2(dir: &TempDir, name: &str, contents: &str) -> std::path::PathBuf {
+        let path = dir.path().join(name);
+        let file = File::create(&path).unwrap();
+        let mut encoder = BzEncoder::new(file, Compression::default());
+        encoder.write_all(contents.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn test_extract_plaintext_strips_tags() {
+        let text = vec![
+            vec!["Rust".to_string()],
+            vec![
+                "is a <a href=\"Programming%20language\">language</a>".to_string(),
+                " for systems.".to_string(),
+            ],
+        ];
+        assert_eq!(extract_plaintext(&text), "Rust\n\nis a language for systems.");
+    }
+
+    #[test]
+    fn test_extract_links_decodes_targets() {
+        let text = vec![
+            vec!["<a href=\"Rust%20%28programming%20language%29\">Rust</a> uses".to_string()],
+            vec![
+                " <a href=\"Cargo\">cargo</a> and <a href=\"Caf%C3%A9\">cafés</a>".to_string(),
+                " and <a href=\"\">nothing</a>, 100%".to_string(),
+            ],
+        ];
+        let targets: Vec<String> = extract_links(&text).into_iter().map(|link| link.target).collect();
+        assert_eq!(targets, vec!["Rust (programming language)", "Cargo", "Café"]);
+    }
+
+    #[test]
+    fn test_extract_links_keeps_anchor_text() {
+        let text = vec![vec![
+            "the <a href=\"Rust\">rust <i>language</i></a> and <a href=\"Cargo\"> </a>".to_string(),
+        ]];
+        assert_eq!(
+            extract_links(&text),
+            vec![
+                Link {
+                    target: "Rust".to_string(),
+                    anchor_text: "rust language".to_string(),
+                },
+                Link {
+                    target: "Cargo".to_string(),
+                    anchor_text: String::new(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reads_articles_from_bz2() {
+        let dir = TempDir::new().unwrap();
+        let path = write_bz2(
+            &dir,
+            "wiki_00.bz2",
+            r#"{"id":"12","url":"https://en.wikipedia.org/wiki?curid=12","title":"Anarchism","text":[["Anarchism"],["is a <a href=\"political%20philosophy\">political philosophy</a>."]]}
+{"id":"25","url":"https://en.wikipedia.org/wiki?curid=25","title":"Autism","text":[["Autism"]]}"#,
+        );
+
+        let source = WikipediaBz2Source::new();
+        assert!(source.is_supported_file(&path));
+        let documents: Vec<Document> = source.read_file(&path).unwrap().collect();
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[0].id, "12");
+        assert_eq!(documents[0].title, "Anarchism");
+        assert_eq!(documents[0].body, "Anarchism\n\nis a political philosophy.");
+        assert_eq!(
+            documents[0].links,
+            vec![Link {
+                target: "political philosophy".to_string(),
+                anchor_text: "political philosophy".to_string(),
+            }]
+        );
+        assert!(documents[1].links.is_empty());
+        assert_eq!(documents[1].url, "https://en.wikipedia.org/wiki?curid=25");
+    }
+
+    #[test]
+    fn test_ignores_other_extensions() {
+        let source = WikipediaBz2Source::new();
+        assert!(!source.is_supported_file(Path::new("wiki_00.json")));
+        assert!(!source.is_supported_file(Path::new("wiki_00")));
+    }
+}
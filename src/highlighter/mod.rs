@@ -0,0 +1,154 @@
+/*
+Builds the excerpt shown with a search result. Positions are word
+positions in the body as handed out by the tokenizer, which counts every
+whitespace separated word, so position i is the i-th word of the stored
+body text. The passage is the window of window_size words holding the
+most distinct query terms, ties are broken by the total number of query
+term occurrences and then by the earliest window.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Highlighter {
+    pre_tag: String,
+    post_tag: String,
+    window_size: usize,
+    // snippets are only built for this many results of a query, building one means
+    // decompressing the stored document
+    max_snippets: usize,
+}
+
+impl Default for Highlighter {
+    fn default() -> Self {
+        Self::new("<b>", "</b>", 30, 10)
+    }
+}
+
+const ELLIPSIS: &str = "...";
+
+impl Highlighter {
+    pub fn new(pre_tag: &str, post_tag: &str, window_size: usize, max_snippets: usize) -> Self {
+        Self {
+            pre_tag: pre_tag.to_string(),
+            post_tag: post_tag.to_string(),
+            window_size: window_size.max(1),
+            max_snippets,
+        }
+    }
+
+    pub fn get_max_snippets(&self) -> usize {
+        self.max_snippets
+    }
+
+    // term_positions holds the positions of every query term in the document, returns the
+    // first and one past the last word of the best window
+    fn find_best_window(&self, term_positions: &[Vec<u32>], no_of_words: usize) -> (usize, usize) {
+        let mut hits: Vec<(u32, usize)> = term_positions
+            .iter()
+            .enumerate()
+            .flat_map(|(term_index, positions)| {
+                positions.iter().map(move |position| (*position, term_index))
+            })
+            .filter(|(position, _)| (*position as usize) < no_of_words)
+            .collect();
+        hits.sort();
+        if hits.is_empty() {
+            return (0, self.window_size.min(no_of_words));
+        }
+
+        let mut best = (0, 0, 0);
+        let mut best_start = 0;
+        let mut end = 0;
+        let mut term_counts = vec![0; term_positions.len()];
+        let mut no_of_distinct_terms = 0;
+        for start in 0..hits.len() {
+            while end < hits.len() && ((hits[end].0 - hits[start].0) as usize) < self.window_size {
+                if term_counts[hits[end].1] == 0 {
+                    no_of_distinct_terms += 1;
+                }
+                term_counts[hits[end].1] += 1;
+                end += 1;
+            }
+            let score = (no_of_distinct_terms, end - start, usize::MAX - start);
+            if score > best {
+                best = score;
+                best_start = start;
+            }
+            term_counts[hits[start].1] -= 1;
+            if term_counts[hits[start].1] == 0 {
+                no_of_distinct_terms -= 1;
+            }
+        }
+
+        // the words left over in the window are split between both sides of the cluster
+        let first = hits[best_start].0 as usize;
+        let last = hits[best_start + best.1 - 1].0 as usize;
+        let padding = (self.window_size - (last - first + 1)) / 2;
+        let start = first.saturating_sub(padding);
+        let end = (start + self.window_size).min(no_of_words);
+        (end.saturating_sub(self.window_size), end)
+    }
+
+    // returns the best passage of the text with every word at one of the term positions
+    // wrapped in the markers
+    pub fn highlight(&self, text: &str, term_positions: &[Vec<u32>]) -> String {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let (start, end) = self.find_best_window(term_positions, words.len());
+
+        let mut snippet_words = Vec::with_capacity(end - start);
+        for (position, word) in words.iter().enumerate().take(end).skip(start) {
+            let is_hit = term_positions
+                .iter()
+                .any(|positions| positions.contains(&(position as u32)));
+            if is_hit {
+                snippet_words.push(format!("{}{}{}", self.pre_tag, word, self.post_tag));
+            } else {
+                snippet_words.push(word.to_string());
+            }
+        }
+        let mut snippet = snippet_words.join(" ");
+        if start > 0 {
+            snippet.insert_str(0, ELLIPSIS);
+        }
+        if end < words.len() {
+            snippet.push_str(ELLIPSIS);
+        }
+        snippet
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight_short_text() {
+        let highlighter = Highlighter::new("[", "]", 10, 10);
+        assert_eq!(
+            highlighter.highlight("the quick brown fox", &[vec![1], vec![3]]),
+            "the [quick] brown [fox]"
+        );
+    }
+
+    #[test]
+    fn test_picks_densest_window() {
+        let highlighter = Highlighter::new("[", "]", 4, 10);
+        let text = "rust is fast . memory safety without garbage collection in rust memory";
+        // rust at 0 and 10, memory at 4 and 11
+        let snippet = highlighter.highlight(text, &[vec![0, 10], vec![4, 11]]);
+        assert_eq!(snippet, "...collection in [rust] [memory]");
+    }
+
+    #[test]
+    fn test_more_distinct_terms_beat_more_occurrences() {
+        let highlighter = Highlighter::new("[", "]", 3, 10);
+        let text = "a a a x x x a b x";
+        let snippet = highlighter.highlight(text, &[vec![0, 1, 2, 6], vec![7]]);
+        assert_eq!(snippet, "...[a] [b] x");
+    }
+
+    #[test]
+    fn test_without_positions_returns_start_of_text() {
+        let highlighter = Highlighter::new("[", "]", 3, 10);
+        assert_eq!(highlighter.highlight("one two three four", &[]), "one two three...");
+        assert_eq!(highlighter.highlight("", &[vec![2]]), "");
+    }
+}
@@ -27,16 +27,16 @@ impl MapInMemoryDict {
             term_map: HashMap::new(),
         };
     }
-    // pub fn add_term(&mut self, term: &str, block_ids: Vec<u32>, term_frequency: u32, term_id: u32) {
-    //     self.term_map.insert(
-    //         term.to_string(),
-    //         MapInMemoryDictPointer {
-    //             term_id,
-    //             term_frequency,
-    //             block_ids,
-    //         },
-    //     );
-    // }
+    pub fn add_term(&mut self, term: &str, block_ids: Vec<u32>, term_frequency: u32, term_id: u32) {
+        self.term_map.insert(
+            term.to_string(),
+            MapInMemoryDictPointer {
+                term_id,
+                term_frequency,
+                block_ids,
+            },
+        );
+    }
 
     pub fn get_terms(&self) -> Vec<String> {
         let mut keys = Vec::new();
@@ -75,7 +75,7 @@ impl MapInMemoryDict {
         }
     }
 
-    pub fn find(&mut self, term: &str) -> Option<&MapInMemoryDictPointer> {
+    pub fn find(&self, term: &str) -> Option<&MapInMemoryDictPointer> {
         self.term_map.get(term)
     }
 }
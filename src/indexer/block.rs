@@ -4,15 +4,21 @@ use std::{
     io::{self, BufReader, Read, Seek},
 };
 
-use crate::indexer::chunk::Chunk;
+use crate::indexer::{
+    checksum::{CorruptionError, crc32},
+    chunk::Chunk,
+    index_file::block_offset,
+};
 
-const BLOCK_SIZE: usize = 64000;
+pub const BLOCK_SIZE: usize = 64000;
+// every block is followed by the crc32 of its bytes in final.idx
+pub const BLOCK_CHECKSUM_SIZE: usize = 4;
 pub struct Block {
     pub current_block_size: u32,
     pub no_of_terms: u32,
     pub block_id: u32,
-    pub current_chunk: Chunk,
-    pub chunks: Vec<Chunk>,
+    // chunks being written, they own their positions
+    pub chunks: Vec<Chunk<'static>>,
     pub block_bytes: [u8; BLOCK_SIZE],
     pub terms: Vec<u32>,
     pub term_offsets: Vec<u16>,
@@ -24,7 +30,6 @@ impl Block {
             current_block_size: 4,
             no_of_terms: 0,
             block_id: block_id,
-            current_chunk: Chunk::new(0),
             chunks: Vec::new(),
             block_bytes: [0; BLOCK_SIZE],
             term_offsets: Vec::new(),
@@ -47,8 +52,9 @@ impl Block {
         -1
     }
 
-    pub fn space_used(&self) -> u32 {
-        self.current_block_size + 1 + self.current_chunk.size_of_chunk
+    // bytes the block takes once the chunk is added
+    pub fn size_with(&self, chunk: &Chunk) -> u32 {
+        self.current_block_size + chunk.size_of_chunk
     }
     pub fn set_block_id(&mut self, block_id: u32) {
         self.block_id = block_id;
@@ -56,13 +62,9 @@ impl Block {
     pub fn set_no_of_terms(&mut self, no_of_terms: u32) {
         self.no_of_terms = no_of_terms;
     }
-    pub fn reset_current_chunk(&mut self) {
-        self.current_chunk.reset();
-    }
-    pub fn add_current_chunk(&mut self) {
-        println!("{:?}", self.current_chunk);
-        self.chunks.push(self.current_chunk.clone());
-        self.current_block_size += self.current_chunk.size_of_chunk;
+    pub fn add_chunk(&mut self, chunk: Chunk<'static>) {
+        self.current_block_size += chunk.size_of_chunk;
+        self.chunks.push(chunk);
     }
 
     pub fn add_term(&mut self, term: u32) {
@@ -70,7 +72,7 @@ impl Block {
         self.terms.push(term);
     }
 
-    pub fn get_chunk_for_doc<'a>(&self, doc_id: u32, chunks: &'a [Chunk]) -> Option<&'a Chunk> {
+    pub fn get_chunk_for_doc<'a, 'b>(&self, doc_id: u32, chunks: &'a [Chunk<'b>]) -> Option<&'a Chunk<'b>> {
         let mut i = 0;
         while i < chunks.len() {
             if chunks[i].max_doc_id < doc_id {
@@ -99,7 +101,7 @@ impl Block {
                 term_offsets.extend(bytes);
             }
             encoded_chunks.extend(&chunk.encode());
-            term_offset_start += (chunk.doc_ids.len() + chunk.positions.len() + 8) as u16;
+            term_offset_start += chunk.size_of_chunk as u16;
         }
         let mut offset = 0;
         self.block_bytes[offset..offset + no_of_terms.len()].copy_from_slice(&no_of_terms);
@@ -111,7 +113,9 @@ impl Block {
         self.block_bytes[offset..offset + encoded_chunks.len()].copy_from_slice(&encoded_chunks);
     }
 
-   pub fn decode_chunks_for_term(&self, term_id: u32, term_index: usize) -> Vec<Chunk> {
+   // a chunk that does not decode is returned as a CorruptionError, the chunks borrow their
+   // positions from the block
+   pub fn decode_chunks_for_term(&self, term_id: u32, term_index: usize) -> io::Result<Vec<Chunk<'_>>> {
         let mut chunk_vec: Vec<Chunk> = Vec::new();
         let term_offset_start = self.term_offsets[term_index] as usize;
         let term_off_end = if term_index == self.terms.len() - 1 {
@@ -122,8 +126,8 @@ impl Block {
 
         let chunk_bytes = &self.block_bytes[term_offset_start..term_off_end];
         let mut chunk_offset = 0;
-        let mut current_chunk = Chunk::new(term_id);
-        while chunk_offset < chunk_bytes.len() {
+        // the chunks of the last term are followed by the zero bytes the block is padded with
+        while chunk_offset + 4 <= chunk_bytes.len() {
             let chunk_size = u32::from_le_bytes(
                 chunk_bytes[chunk_offset..chunk_offset + 4]
                     .try_into()
@@ -132,20 +136,46 @@ impl Block {
             if chunk_size == 0 {
                 break;
             }
+            let chunk_end = chunk_offset + chunk_size as usize;
+            if chunk_size < 4 || chunk_end > chunk_bytes.len() {
+                return Err(self.invalid(format!(
+                    "a chunk of {} bytes of term id {} does not fit the block",
+                    chunk_size, term_id
+                )));
+            }
+            let mut current_chunk = Chunk::new(term_id);
+            current_chunk.block_id = self.block_id;
             current_chunk
-                .decode(&chunk_bytes[chunk_offset + 4..chunk_offset + chunk_size as usize]);
-            chunk_vec.push(current_chunk.clone());
-            chunk_offset += chunk_size as usize;
+                .decode(&chunk_bytes[chunk_offset + 4..chunk_end])
+                .map_err(|reason| self.invalid(reason))?;
+            chunk_vec.push(current_chunk);
+            chunk_offset = chunk_end;
         }
-        chunk_vec
+        Ok(chunk_vec)
     }
 
+    // reads the block from final.idx, a block whose bytes do not match its checksum or whose
+    // term table points outside of it is returned as a CorruptionError
     pub fn init(&mut self, reader: &mut BufReader<& mut File>) -> io::Result<()> {
-        let _ = reader.seek(std::io::SeekFrom::Start(
-            (self.block_id * BLOCK_SIZE as u32).into(),
-        ))?;
+        let _ = reader.seek(std::io::SeekFrom::Start(block_offset(self.block_id)))?;
         let _ = reader.read_exact(&mut self.block_bytes)?;
+        let mut checksum = [0u8; BLOCK_CHECKSUM_SIZE];
+        reader.read_exact(&mut checksum)?;
+        let stored_checksum = u32::from_le_bytes(checksum);
+        let computed_checksum = crc32(&self.block_bytes);
+        if stored_checksum != computed_checksum {
+            return Err(CorruptionError::BlockChecksumMismatch {
+                block_id: self.block_id,
+                stored: stored_checksum,
+                computed: computed_checksum,
+            }
+            .into());
+        }
         let no_of_terms_in_block = u32::from_le_bytes(self.block_bytes[0..4].try_into().unwrap());
+        let term_table_end = 4 + 6 * no_of_terms_in_block as usize;
+        if term_table_end > BLOCK_SIZE {
+            return Err(self.invalid(format!("{} terms do not fit", no_of_terms_in_block)));
+        }
         self.no_of_terms = no_of_terms_in_block;
         let mut offset = 4;
         let mut terms: Vec<u32> = Vec::new();
@@ -162,11 +192,30 @@ impl Block {
             term_offsets.push(term_offset);
             offset += 2;
         }
+        // the chunks of a term start where the chunks of the term before end
+        let mut last_term_offset = term_table_end;
+        for term_offset in &term_offsets {
+            if (*term_offset as usize) < last_term_offset || *term_offset as usize > BLOCK_SIZE {
+                return Err(self.invalid(format!("term offset {} is out of order", term_offset)));
+            }
+            last_term_offset = *term_offset as usize;
+        }
+        if !terms.is_sorted() {
+            return Err(self.invalid("terms are out of order".to_string()));
+        }
 
         self.term_offsets = term_offsets;
         self.terms = terms;
         Ok(())
     }
+
+    fn invalid(&self, reason: String) -> io::Error {
+        CorruptionError::InvalidBlock {
+            block_id: self.block_id,
+            reason,
+        }
+        .into()
+    }
 }
 
 #[cfg(test)]
@@ -175,7 +224,7 @@ mod tests {
     use crate::{
         dictionary::Posting,
         indexer::{
-            helper::vb_decode_positions,
+            checksum::as_corruption_error,
             index_merge_writer::{MergedIndexBlockWriter},
         },
     };
@@ -211,6 +260,27 @@ mod tests {
         assert_eq!(block.terms, vec![1]);
     }
 
+    #[test]
+    fn test_corrupt_block_is_detected() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let file = temp_file.reopen().unwrap();
+        let mut writer = MergedIndexBlockWriter::new(file, Some(64));
+        writer.add_term(1, vec![create_test_postings(10, vec![5, 10, 15])]).unwrap();
+        writer.finish().unwrap();
+
+        let mut bytes = std::fs::read(temp_file.path()).unwrap();
+        bytes[block_offset(0) as usize + 20] ^= 0x80;
+        std::fs::write(temp_file.path(), bytes).unwrap();
+        let mut file = temp_file.reopen().unwrap();
+        let mut reader = BufReader::new(&mut file);
+        let mut block = Block::new(0);
+        let error = block.init(&mut reader).unwrap_err();
+        assert!(matches!(
+            as_corruption_error(&error),
+            Some(CorruptionError::BlockChecksumMismatch { block_id: 0, .. })
+        ));
+    }
+
     #[test]
     fn test_add_multiple_terms() {
         let temp_file = NamedTempFile::new().unwrap();
@@ -287,28 +357,28 @@ mod tests {
         let metadata = writer.get_term_metadata(1).unwrap();
         let mut block = Block::new(metadata.block_ids[0]);
         block.init(&mut reader).unwrap();
-        let chunks = block.decode_chunks_for_term(1, 0);
-        let doc_ids =chunks[0].get_doc_ids();
+        let chunks = block.decode_chunks_for_term(1, 0).unwrap();
+        let doc_ids =chunks[0].get_doc_ids().unwrap();
         assert_eq!(doc_ids, vec![10, 1000, 10000, 100000]);
-        let postings1 = chunks[0].get_posting_list(0);
+        let postings1 = chunks[0].get_posting_list(0).unwrap();
         assert_eq!(postings1, vec![1, 6, 7, 13, 20]);
-        let postings2 = chunks[0].get_posting_list(1);
+        let postings2 = chunks[0].get_posting_list(1).unwrap();
         assert_eq!(postings2, vec![2, 6, 8, 9]);
-        let postings3 = chunks[0].get_posting_list(2);
+        let postings3 = chunks[0].get_posting_list(2).unwrap();
         assert_eq!(postings3, vec![3, 5]);
-        let postings4 = chunks[0].get_posting_list(3);
+        let postings4 = chunks[0].get_posting_list(3).unwrap();
         assert_eq!(postings4, vec![4, 5, 6, 9, 10]);
 
-        let chunks = block.decode_chunks_for_term(2, 1);
-        let doc_ids = vb_decode_positions(&chunks[0].doc_ids);
+        let chunks = block.decode_chunks_for_term(2, 1).unwrap();
+        let doc_ids = chunks[0].get_doc_ids().unwrap();
         assert_eq!(doc_ids, vec![12, 14, 90, 100]);
-        let postings1 = chunks[0].get_posting_list(0);
+        let postings1 = chunks[0].get_posting_list(0).unwrap();
         assert_eq!(postings1, vec![1, 6, 7, 13, 20]);
-        let postings2 = chunks[0].get_posting_list(1);
+        let postings2 = chunks[0].get_posting_list(1).unwrap();
         assert_eq!(postings2, vec![2, 6, 8, 9]);
-        let postings3 = chunks[0].get_posting_list(2);
+        let postings3 = chunks[0].get_posting_list(2).unwrap();
         assert_eq!(postings3, vec![3, 5, 7, 19, 22, 49]);
-        let postings4 = chunks[0].get_posting_list(3);
+        let postings4 = chunks[0].get_posting_list(3).unwrap();
         assert_eq!(postings4, vec![4, 5, 6, 9, 10]);
     }
 
@@ -338,26 +408,26 @@ mod tests {
         let mut reader = BufReader::new(& mut file);
         let mut block1 = Block::new(metadata.block_ids[0]);
         block1.init(&mut reader).unwrap();
-        let chunks1 = block1.decode_chunks_for_term(1, 0);
+        let chunks1 = block1.decode_chunks_for_term(1, 0).unwrap();
         let mut postings_read = Vec::new();
         for chunk in chunks1 {
-            let doc_ids = vb_decode_positions(&chunk.doc_ids);
+            let doc_ids = chunk.get_doc_ids().unwrap();
             for index in 0..doc_ids.len() {
                 postings_read.push(Posting {
                     doc_id: doc_ids[index],
-                    positions: chunk.get_posting_list(index as u32),
+                    positions: chunk.get_posting_list(index as u32).unwrap(),
                 });
             }
         }
         let mut block2 = Block::new(metadata.block_ids[1]);
         block2.init(&mut reader).unwrap();
-        let chunks2 = block2.decode_chunks_for_term(1, 0);
+        let chunks2 = block2.decode_chunks_for_term(1, 0).unwrap();
         for chunk in chunks2 {
-            let doc_ids = vb_decode_positions(&chunk.doc_ids);
+            let doc_ids = chunk.get_doc_ids().unwrap();
             for index in 0..doc_ids.len() {
                 postings_read.push(Posting {
                     doc_id: doc_ids[index],
-                    positions: chunk.get_posting_list(index as u32),
+                    positions: chunk.get_posting_list(index as u32).unwrap(),
                 });
             }
         }
@@ -0,0 +1,109 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{compressors::posting_codec::PostingCodecId, indexer::doc_id_order::DocIdOrder};
+
+pub const BUILD_STATISTICS_FILE_NAME: &str = "build_statistics.json";
+
+/*
+What a build produced and where its time went, written as JSON into the
+index directory next to final.idx.
+
+Parsing and inverting run on several threads at the same time, their times
+are summed over the threads and can add up to more than the total time of
+the build. The merge runs on one thread. A build resumed from a checkpoint
+counts the documents indexed before the interruption but only the time
+spent after it.
+ */
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BuildStatistics {
+    pub no_of_documents: u32,
+    pub no_of_distinct_terms: u32,
+    pub no_of_postings: u64,
+    pub no_of_positions: u64,
+    pub no_of_runs: u32,
+    pub no_of_blocks: u32,
+    // links between documents of the index, links to titles outside of it are not counted
+    pub no_of_links: u64,
+    // near-duplicates found, skipped or marked depending on the duplicate handling. Only
+    // documents of this build are compared, not those of segments written before
+    pub no_of_duplicates: u32,
+    pub doc_id_order: DocIdOrder,
+    pub posting_codec: PostingCodecId,
+    // estimated bytes of the doc id stream alone, the gap encoded doc ids of the chunks
+    // with posting_codec, with the doc ids in read order and in doc_id_order. The same when
+    // the documents were not reordered. Term frequencies, positions, chunk headers and block
+    // padding are not counted
+    pub doc_id_stream_bytes: u64,
+    pub doc_id_stream_bytes_before_reordering: u64,
+    // size of final.idx
+    pub index_size_in_bytes: u64,
+    // size final.idx has with the doc ids in read order, measured by writing it a second
+    // time. The same when the documents were not reordered. Blocks are padded to their full
+    // size, so only a change in the number of blocks shows up here
+    pub index_size_in_bytes_before_reordering: u64,
+    pub bytes_per_posting: f64,
+    pub parse_seconds: f64,
+    pub invert_seconds: f64,
+    pub merge_seconds: f64,
+    pub total_seconds: f64,
+}
+
+impl BuildStatistics {
+    pub fn write_to_disk(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.flush()
+    }
+
+    pub fn read_from_disk(path: &Path) -> io::Result<Self> {
+        Ok(serde_json::from_reader(io::BufReader::new(File::open(path)?))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_round_trip_as_json() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(BUILD_STATISTICS_FILE_NAME);
+        let statistics = BuildStatistics {
+            no_of_documents: 3,
+            no_of_distinct_terms: 10,
+            no_of_postings: 12,
+            no_of_positions: 20,
+            no_of_runs: 2,
+            no_of_blocks: 1,
+            no_of_links: 7,
+            no_of_duplicates: 1,
+            doc_id_order: DocIdOrder::Title,
+            posting_codec: PostingCodecId::Simple16,
+            doc_id_stream_bytes: 9,
+            doc_id_stream_bytes_before_reordering: 12,
+            index_size_in_bytes: 64000,
+            index_size_in_bytes_before_reordering: 128000,
+            bytes_per_posting: 5333.25,
+            parse_seconds: 0.5,
+            invert_seconds: 0.25,
+            merge_seconds: 0.125,
+            total_seconds: 1.0,
+        };
+        statistics.write_to_disk(&path).unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json["no_of_documents"], 3);
+        assert_eq!(json["merge_seconds"], 0.125);
+        assert_eq!(json["doc_id_order"], "Title");
+        assert_eq!(json["posting_codec"], "Simple16");
+        assert_eq!(BuildStatistics::read_from_disk(&path).unwrap(), statistics);
+    }
+}
@@ -0,0 +1,230 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::indexer::{
+    document_metadata::{
+        DocumentMetadata, read_document_metadata_record, write_document_metadata_record,
+    },
+    document_store::DocumentStoreCheckpoint,
+    helper::{read_string, read_u32, read_u64, write_string, write_u32, write_u64},
+};
+
+pub const CHECKPOINT_FILE_NAME: &str = "checkpoint.dat";
+pub const DOCUMENT_METADATA_LOG_FILE_NAME: &str = "documents.log";
+
+/*
+Records how far an interrupted build got, a restarted build skips the
+input files that were fully consumed and continues with the next doc id.
+
+File Layout->
+corpus_path
+no_of_docs
+no_of_runs
+no_of_completed_files
+completed_file_path (one per file)
+document_metadata_log_length
+link_log_length
+signature_log_length
+no_of_duplicates
+document store checkpoint
+
+Everything a checkpoint refers to is synced to disk before the checkpoint
+file is written, the run files up to no_of_runs, the first
+document_metadata_log_length bytes of documents.log, the first
+link_log_length bytes of links.log, the first signature_log_length bytes of
+signatures.log and the document store
+up to its checkpoint. Whatever was written after that is thrown away on
+resume and the files it came from are read again.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexCheckpoint {
+    pub corpus_path: String,
+    pub no_of_docs: u32,
+    pub no_of_runs: u32,
+    pub completed_files: Vec<String>,
+    pub document_metadata_log_length: u64,
+    pub link_log_length: u64,
+    pub signature_log_length: u64,
+    // near-duplicates found before the checkpoint, for the build statistics
+    pub no_of_duplicates: u32,
+    pub document_store: DocumentStoreCheckpoint,
+}
+
+impl IndexCheckpoint {
+    // the checkpoint is written next to its final place and renamed over it, so a crash
+    // while writing leaves the previous checkpoint intact
+    pub fn write_to_disk(&self, path: &Path) -> io::Result<()> {
+        let temporary_path = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&temporary_path)?);
+        write_string(&mut writer, &self.corpus_path)?;
+        write_u32(&mut writer, self.no_of_docs)?;
+        write_u32(&mut writer, self.no_of_runs)?;
+        write_u32(&mut writer, self.completed_files.len() as u32)?;
+        for completed_file in &self.completed_files {
+            write_string(&mut writer, completed_file)?;
+        }
+        write_u64(&mut writer, self.document_metadata_log_length)?;
+        write_u64(&mut writer, self.link_log_length)?;
+        write_u64(&mut writer, self.signature_log_length)?;
+        write_u32(&mut writer, self.no_of_duplicates)?;
+        self.document_store.write(&mut writer)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        drop(writer);
+        fs::rename(&temporary_path, path)
+    }
+
+    // returns None when no build was interrupted
+    pub fn read_from_disk(path: &Path) -> io::Result<Option<Self>> {
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let mut reader = BufReader::new(File::open(path)?);
+        let corpus_path = read_string(&mut reader)?;
+        let no_of_docs = read_u32(&mut reader)?;
+        let no_of_runs = read_u32(&mut reader)?;
+        let no_of_completed_files = read_u32(&mut reader)?;
+        let mut completed_files = Vec::with_capacity(no_of_completed_files as usize);
+        for _ in 0..no_of_completed_files {
+            completed_files.push(read_string(&mut reader)?);
+        }
+        Ok(Some(Self {
+            corpus_path,
+            no_of_docs,
+            no_of_runs,
+            completed_files,
+            document_metadata_log_length: read_u64(&mut reader)?,
+            link_log_length: read_u64(&mut reader)?,
+            signature_log_length: read_u64(&mut reader)?,
+            no_of_duplicates: read_u32(&mut reader)?,
+            document_store: DocumentStoreCheckpoint::read(&mut reader)?,
+        }))
+    }
+}
+
+// the document metadata of a running build, appended to at every checkpoint so a
+// checkpoint does not have to rewrite the metadata of every document indexed before it
+pub struct DocumentMetadataLog {
+    writer: BufWriter<File>,
+    length: u64,
+}
+
+impl DocumentMetadataLog {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            length: 0,
+        })
+    }
+
+    // cuts the log back to the length recorded by a checkpoint and returns the metadata
+    // it holds
+    pub fn resume(path: &Path, length: u64) -> io::Result<(Self, HashMap<u32, DocumentMetadata>)> {
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        file.set_len(length)?;
+
+        let mut log = Vec::with_capacity(length as usize);
+        (&file).read_to_end(&mut log)?;
+        let mut cursor = Cursor::new(log);
+        let mut document_metadata = HashMap::new();
+        while cursor.position() < length {
+            let (doc_id, metadata) = read_document_metadata_record(&mut cursor)?;
+            document_metadata.insert(doc_id, metadata);
+        }
+
+        file.seek(SeekFrom::End(0))?;
+        Ok((
+            Self {
+                writer: BufWriter::new(file),
+                length,
+            },
+            document_metadata,
+        ))
+    }
+
+    pub fn append(&mut self, doc_id: u32, metadata: &DocumentMetadata) -> io::Result<()> {
+        let mut record = Vec::new();
+        write_document_metadata_record(&mut record, doc_id, metadata)?;
+        self.writer.write_all(&record)?;
+        self.length += record.len() as u64;
+        Ok(())
+    }
+
+    // syncs the appended records and returns the length to record in the checkpoint
+    pub fn sync(&mut self) -> io::Result<u64> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_all()?;
+        Ok(self.length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::document_store::DocumentStoreWriter;
+    use tempfile::TempDir;
+
+    fn create_test_metadata(i: u32) -> DocumentMetadata {
+        DocumentMetadata {
+            doc_external_id: format!("ext-{}", i),
+            doc_name: format!("Title {}", i),
+            doc_url: format!("https://example.org/{}", i),
+            doc_length: i,
+            title_length: 2,
+            url_length: 4,
+            anchor_length: 0,
+            duplicate_of: 0,
+        }
+    }
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let mut document_store_writer =
+            DocumentStoreWriter::create(&dir.path().join("stored_fields.dat")).unwrap();
+        let checkpoint = IndexCheckpoint {
+            corpus_path: "/corpus".to_string(),
+            no_of_docs: 42,
+            no_of_runs: 3,
+            completed_files: vec!["/corpus/a.txt".to_string(), "/corpus/b.txt".to_string()],
+            document_metadata_log_length: 1234,
+            link_log_length: 567,
+            signature_log_length: 89,
+            no_of_duplicates: 2,
+            document_store: document_store_writer.checkpoint().unwrap(),
+        };
+
+        let path = dir.path().join(CHECKPOINT_FILE_NAME);
+        assert_eq!(IndexCheckpoint::read_from_disk(&path).unwrap(), None);
+        checkpoint.write_to_disk(&path).unwrap();
+        assert_eq!(IndexCheckpoint::read_from_disk(&path).unwrap(), Some(checkpoint));
+    }
+
+    #[test]
+    fn test_document_metadata_log_resume_drops_unsynced_records() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(DOCUMENT_METADATA_LOG_FILE_NAME);
+
+        let mut log = DocumentMetadataLog::create(&path).unwrap();
+        log.append(1, &create_test_metadata(1)).unwrap();
+        log.append(2, &create_test_metadata(2)).unwrap();
+        let length = log.sync().unwrap();
+        log.append(3, &create_test_metadata(3)).unwrap();
+        log.sync().unwrap();
+        drop(log);
+
+        let (mut log, document_metadata) = DocumentMetadataLog::resume(&path, length).unwrap();
+        assert_eq!(document_metadata.len(), 2);
+        assert_eq!(document_metadata[&2], create_test_metadata(2));
+
+        log.append(3, &create_test_metadata(30)).unwrap();
+        let length = log.sync().unwrap();
+        drop(log);
+        let (_, document_metadata) = DocumentMetadataLog::resume(&path, length).unwrap();
+        assert_eq!(document_metadata[&3], create_test_metadata(30));
+    }
+}
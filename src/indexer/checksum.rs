@@ -0,0 +1,164 @@
+use std::{error::Error, fmt, io, path::PathBuf};
+
+// CRC-32 as used by zip and png, reflected with polynomial 0xedb88320
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+// checksum of bytes that are read or written piece by piece
+#[derive(Debug, Clone, Copy)]
+pub struct Crc32 {
+    crc: u32,
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Crc32 {
+    pub fn new() -> Self {
+        Self { crc: 0xffffffff }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.crc = CRC32_TABLE[((self.crc ^ *byte as u32) & 0xff) as usize] ^ (self.crc >> 8);
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        self.crc ^ 0xffffffff
+    }
+}
+
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(bytes);
+    crc.finish()
+}
+
+/*
+Bytes read back from a run file, final.idx or the manifest that are not what was
+written. Returned inside an io::Error of kind InvalidData, so it travels
+through the io::Result of every reader, callers that need to tell
+corruption apart from other failures look for it with as_corruption_error.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum CorruptionError {
+    BlockChecksumMismatch {
+        block_id: u32,
+        stored: u32,
+        computed: u32,
+    },
+    // the checksum matched but the block does not decode
+    InvalidBlock { block_id: u32, reason: String },
+    RunFileChecksumMismatch { stored: u32, computed: u32 },
+    InvalidRunFile { reason: String },
+    // final.idx decodes but disagrees with the lexicon or its own footer, found by verify
+    LexiconMismatch { term: String, reason: String },
+    FooterMismatch { reason: String },
+    InvalidManifest { reason: String },
+    // any of the above together with the file it was found in, added by callers that know it
+    InFile {
+        path: PathBuf,
+        corruption: Box<CorruptionError>,
+    },
+}
+
+impl CorruptionError {
+    pub fn in_file(self, path: PathBuf) -> Self {
+        Self::InFile {
+            path,
+            corruption: Box::new(self),
+        }
+    }
+}
+
+impl fmt::Display for CorruptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BlockChecksumMismatch {
+                block_id,
+                stored,
+                computed,
+            } => write!(
+                f,
+                "block {} is corrupt, its checksum is {:08x} but its bytes sum to {:08x}",
+                block_id, stored, computed
+            ),
+            Self::InvalidBlock { block_id, reason } => {
+                write!(f, "block {} is corrupt, {}", block_id, reason)
+            }
+            Self::RunFileChecksumMismatch { stored, computed } => write!(
+                f,
+                "run file is corrupt, its checksum is {:08x} but its bytes sum to {:08x}",
+                stored, computed
+            ),
+            Self::InvalidRunFile { reason } => write!(f, "run file is corrupt, {}", reason),
+            Self::LexiconMismatch { term, reason } => {
+                write!(f, "lexicon does not match the index for {:?}, {}", term, reason)
+            }
+            Self::FooterMismatch { reason } => {
+                write!(f, "footer does not match the blocks of the index, {}", reason)
+            }
+            Self::InvalidManifest { reason } => write!(f, "segment manifest is corrupt, {}", reason),
+            Self::InFile { path, corruption } => write!(f, "{}: {}", path.display(), corruption),
+        }
+    }
+}
+
+impl Error for CorruptionError {}
+
+impl From<CorruptionError> for io::Error {
+    fn from(error: CorruptionError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, error)
+    }
+}
+
+pub fn as_corruption_error(error: &io::Error) -> Option<&CorruptionError> {
+    error.get_ref()?.downcast_ref::<CorruptionError>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        let mut crc = Crc32::new();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.finish(), 0xcbf43926);
+    }
+
+    #[test]
+    fn test_corruption_error_survives_io_error() {
+        let error: io::Error = CorruptionError::InvalidRunFile {
+            reason: "too short".to_string(),
+        }
+        .into();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "run file is corrupt, too short");
+        assert!(matches!(
+            as_corruption_error(&error),
+            Some(CorruptionError::InvalidRunFile { .. })
+        ));
+        assert_eq!(as_corruption_error(&io::Error::other("other")), None);
+    }
+}
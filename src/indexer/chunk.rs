@@ -1,253 +1,439 @@
+use std::{borrow::Cow, io};
+
 use crate::{
-    compressors::vb_encode::vb_encode,
-    indexer::helper::{vb_decode_positions, vb_encode_positions},
+    compressors::{
+        posting_codec::{PostingCodec, PostingCodecId},
+        vb_encode::VariableByteCodec,
+    },
+    dictionary::Posting,
+    indexer::checksum::CorruptionError,
 };
-const POSITIONS_DELIMITER: u8 = 0x00;
+// size_of_chunk, max_doc_id, codec id, no_of_postings and the lengths of the doc ids and the
+// term frequencies
+pub const CHUNK_HEADER_SIZE: u32 = 14;
+pub const MAX_POSTINGS_PER_CHUNK: usize = 128;
+
+/*
+Chunk Layout->
+size_of_chunk max_doc_id codec_id (u8) no_of_postings (u8) doc_ids_length (u16)
+term_frequencies_length (u16) doc_ids term_frequencies positions
+
+The doc ids are encoded with the codec of the chunk, the first one as it is
+and every other one as its gap to the doc id before it minus 1, so
+consecutive doc ids are runs of zeros. The term frequencies, the number of
+positions of every posting, are encoded with the codec of the chunk as well.
+Scoring only decodes these two sections.
+
+The positions of every posting are variable byte encoded gaps, the first
+position as it is. There is no separator between the postings, the term
+frequencies tell how many values belong to each one, so the positions of a
+single posting are found by skipping the values of the ones before it.
+
+A decoded chunk borrows its positions from the block it was read from, they
+are only decoded when a phrase check or a snippet asks for them.
+ */
 #[derive(Debug, Clone, PartialEq)]
-pub struct Chunk {
-    pub size_of_chunk: u32, // stored on disk
-    pub max_doc_id: u32,    // stored on disk
-    pub doc_ids: Vec<u8>,   // stored on disk
-    pub positions: Vec<u8>, // stored on disk
-    pub no_of_postings: u8,
+pub struct Chunk<'a> {
+    pub size_of_chunk: u32,        // stored on disk
+    pub max_doc_id: u32,           // stored on disk
+    pub codec_id: PostingCodecId,  // stored on disk
+    pub no_of_postings: u8,        // stored on disk
+    pub doc_ids: Vec<u8>,          // stored on disk
+    pub term_frequencies: Vec<u8>, // stored on disk
+    pub positions: Cow<'a, [u8]>,  // stored on disk
     pub term: u32,
-    pub last_doc_id: u32,
+    // the block the chunk was decoded from, for the errors of a chunk that does not decode
+    pub block_id: u32,
 }
 
-impl Chunk {
+impl<'a> Chunk<'a> {
     pub fn new(term: u32) -> Self {
         Self {
-            size_of_chunk: 8,
+            size_of_chunk: CHUNK_HEADER_SIZE,
             max_doc_id: 0,
-            last_doc_id: 0,
+            codec_id: PostingCodecId::VariableByte,
             no_of_postings: 0,
             term: term,
+            block_id: 0,
             doc_ids: Vec::new(),
-            positions: Vec::new(),
+            term_frequencies: Vec::new(),
+            positions: Cow::Borrowed(&[]),
         }
     }
 
-    pub fn finish(&mut self) {
-        if self.doc_ids.len() > 0 {
-            self.doc_ids.push(POSITIONS_DELIMITER);
-            self.size_of_chunk+=1;
+    // the postings have to be sorted by doc id and at most MAX_POSTINGS_PER_CHUNK
+    pub fn from_postings(term: u32, postings: &[Posting], codec_id: PostingCodecId) -> Self {
+        let doc_id_gaps = encode_doc_id_gaps(postings.iter().map(|posting| posting.doc_id));
+        let term_frequencies: Vec<u32> =
+            postings.iter().map(|posting| posting.positions.len() as u32).collect();
+        // both sections share the codec of the chunk, variable byte encoding if the picked
+        // codec can not encode one of them
+        let codec_id = if codec_id.codec().can_encode(&doc_id_gaps)
+            && codec_id.codec().can_encode(&term_frequencies)
+        {
+            codec_id
+        } else {
+            PostingCodecId::VariableByte
+        };
+        let mut doc_id_bytes = Vec::new();
+        codec_id.codec().encode(&doc_id_gaps, &mut doc_id_bytes);
+        let mut term_frequency_bytes = Vec::new();
+        codec_id.codec().encode(&term_frequencies, &mut term_frequency_bytes);
+        let mut positions = Vec::new();
+        for posting in postings {
+            VariableByteCodec.encode(&encode_position_gaps(&posting.positions), &mut positions);
+        }
+        Self {
+            size_of_chunk: CHUNK_HEADER_SIZE
+                + doc_id_bytes.len() as u32
+                + term_frequency_bytes.len() as u32
+                + positions.len() as u32,
+            max_doc_id: postings.last().map(|posting| posting.doc_id).unwrap_or(0),
+            codec_id,
+            no_of_postings: postings.len() as u8,
+            term,
+            block_id: 0,
+            doc_ids: doc_id_bytes,
+            term_frequencies: term_frequency_bytes,
+            positions: Cow::Owned(positions),
         }
-    }
-  
-    pub fn reset(&mut self) {
-        self.size_of_chunk = 8;
-        self.last_doc_id = 0;
-        self.max_doc_id = 0;
-        self.positions.clear();
-        self.doc_ids.clear();
-        self.no_of_postings = 0;
     }
 
     pub fn encode(&self) -> Vec<u8> {
         let mut chunk_bytes: Vec<u8> = Vec::new();
         chunk_bytes.extend_from_slice(&self.size_of_chunk.to_le_bytes());
         chunk_bytes.extend_from_slice(&self.max_doc_id.to_le_bytes());
+        chunk_bytes.push(self.codec_id.to_u8());
+        chunk_bytes.push(self.no_of_postings);
+        chunk_bytes.extend_from_slice(&(self.doc_ids.len() as u16).to_le_bytes());
+        chunk_bytes.extend_from_slice(&(self.term_frequencies.len() as u16).to_le_bytes());
         chunk_bytes.extend(&self.doc_ids);
-        chunk_bytes.extend(&self.positions);
+        chunk_bytes.extend(&self.term_frequencies);
+        chunk_bytes.extend_from_slice(&self.positions);
         chunk_bytes
     }
 
-    pub fn get_doc_ids(& self)->Vec<u32>{
-        vb_decode_positions(&self.doc_ids)
-    } 
-    pub fn get_posting_list(& self,index:u32)->Vec<u32>{
-       
-       let mut posting_list: &[u8] = &[];
-        let mut current_index=0;
-        let mut i=0;
-        while current_index<index+1{
-            let mut j=i;
-            while self.positions[j]!=0{
-                j+=1;
-            }
-            posting_list=&self.positions[i as usize..j as usize];
-            i=j+1;
-            current_index+=1;
+    // a section of the chunk that does not decode although its block matched its checksum
+    fn invalid(&self, section: &str) -> io::Error {
+        CorruptionError::InvalidBlock {
+            block_id: self.block_id,
+            reason: format!("the {} of a chunk of term id {} do not decode", section, self.term),
         }
-        vb_decode_positions(posting_list)
+        .into()
     }
 
-    pub fn decode(&mut self, chunk_bytes: &[u8]) {
-        self.size_of_chunk = (4 + chunk_bytes.len()) as u32;
+    // decodes the doc ids into the start of doc_ids and returns how many there are. The
+    // caller keeps the buffer across chunks, nothing is allocated
+    pub fn read_doc_ids(&self, doc_ids: &mut [u32; MAX_POSTINGS_PER_CHUNK]) -> io::Result<usize> {
+        let no_of_postings = self.no_of_postings as usize;
+        self.codec_id
+            .codec()
+            .decode_block(&self.doc_ids, no_of_postings, doc_ids)
+            .and_then(|_| decode_doc_id_gaps(&mut doc_ids[..no_of_postings]))
+            .ok_or_else(|| self.invalid("doc ids"))?;
+        Ok(no_of_postings)
+    }
+
+    // the number of positions of every posting in the order of the doc ids, decoded into
+    // term_frequencies like read_doc_ids without decoding any position
+    pub fn read_term_frequencies(
+        &self,
+        term_frequencies: &mut [u32; MAX_POSTINGS_PER_CHUNK],
+    ) -> io::Result<usize> {
+        let no_of_postings = self.no_of_postings as usize;
+        self.codec_id
+            .codec()
+            .decode_block(&self.term_frequencies, no_of_postings, term_frequencies)
+            .ok_or_else(|| self.invalid("term frequencies"))?;
+        Ok(no_of_postings)
+    }
+
+    pub fn get_doc_ids(& self)->io::Result<Vec<u32>>{
+        let mut doc_ids = [0; MAX_POSTINGS_PER_CHUNK];
+        let no_of_postings = self.read_doc_ids(&mut doc_ids)?;
+        Ok(doc_ids[..no_of_postings].to_vec())
+    }
+
+    // see read_term_frequencies
+    pub fn get_term_frequencies(&self) -> io::Result<Vec<u32>> {
+        let mut term_frequencies = [0; MAX_POSTINGS_PER_CHUNK];
+        let no_of_postings = self.read_term_frequencies(&mut term_frequencies)?;
+        Ok(term_frequencies[..no_of_postings].to_vec())
+    }
+
+    // the positions of the posting at index, empty if there is no posting at index
+    pub fn get_posting_list(& self,index:u32)->io::Result<Vec<u32>>{
+        let term_frequencies = self.get_term_frequencies()?;
+        let Some(term_frequency) = term_frequencies.get(index as usize) else {
+            return Ok(Vec::new());
+        };
+        let values_before: u32 = term_frequencies[..index as usize].iter().sum();
+        skip_vb_values(&self.positions, values_before as usize)
+            .and_then(|offset| {
+                VariableByteCodec.decode(&self.positions[offset..], *term_frequency as usize)
+            })
+            .map(|(gaps, _)| decode_position_gaps(gaps))
+            .ok_or_else(|| self.invalid("positions"))
+    }
+
+    // every posting of the chunk, the positions have to add up to the term frequencies
+    pub fn get_postings(&self) -> io::Result<Vec<Posting>> {
+        let doc_ids = self.get_doc_ids()?;
+        let term_frequencies = self.get_term_frequencies()?;
+        let mut postings = Vec::with_capacity(doc_ids.len());
         let mut offset = 0;
-        let max_doc_id = u32::from_le_bytes(chunk_bytes[offset..offset + 4].try_into().unwrap());
-        offset += 4;
-        self.max_doc_id = max_doc_id;
-        println!("size of chunk {} max doc id {}",self.size_of_chunk,self.max_doc_id);
-        if max_doc_id == 0 {
-            return;
+        for (doc_id, term_frequency) in doc_ids.into_iter().zip(term_frequencies) {
+            let (gaps, bytes_read) = VariableByteCodec
+                .decode(&self.positions[offset..], term_frequency as usize)
+                .ok_or_else(|| self.invalid("positions"))?;
+            offset += bytes_read;
+            postings.push(Posting::new(doc_id, decode_position_gaps(gaps)));
         }
-        let mut index = offset;
-        while index < chunk_bytes.len() {
-            if chunk_bytes[index] == 0 {
-                break;
-            }
-            index += 1;
+        if offset != self.positions.len() {
+            return Err(self.invalid("positions"));
         }
-        self.doc_ids = chunk_bytes[offset..index].to_vec();
-        self.positions = chunk_bytes[index+1..].to_vec();
-    }
-    pub fn add_encoded_doc_id(&mut self, doc_id: u32, encoded_doc_id: Vec<u8>) {
-        self.last_doc_id = doc_id;
-        self.size_of_chunk += encoded_doc_id.len() as u32;
-        self.doc_ids.extend_from_slice(&encoded_doc_id);
+        Ok(postings)
     }
-    pub fn encode_doc_id(&mut self, doc_id: u32) -> Vec<u8> {
-        let encoded_doc_id: Vec<u8> = vb_encode(&(doc_id - self.last_doc_id));
-        encoded_doc_id
-    }
-    pub fn add_encoded_positions(&mut self, encoded_positions: Vec<u8>) {
-        self.size_of_chunk += encoded_positions.len() as u32;
-        self.positions.extend_from_slice(&encoded_positions);
+
+    // chunk_bytes start after size_of_chunk, returns why they are no chunk otherwise
+    pub fn decode(&mut self, chunk_bytes: &'a [u8]) -> Result<(), String> {
+        if chunk_bytes.len() < CHUNK_HEADER_SIZE as usize - 4 {
+            return Err(format!("a chunk of {} bytes is too short", chunk_bytes.len() + 4));
+        }
+        self.size_of_chunk = (4 + chunk_bytes.len()) as u32;
+        self.max_doc_id = u32::from_le_bytes(chunk_bytes[0..4].try_into().unwrap());
+        self.codec_id = PostingCodecId::from_u8(chunk_bytes[4])
+            .ok_or_else(|| format!("a chunk has the unknown codec id {}", chunk_bytes[4]))?;
+        self.no_of_postings = chunk_bytes[5];
+        let doc_ids_length = u16::from_le_bytes(chunk_bytes[6..8].try_into().unwrap()) as usize;
+        let term_frequencies_length =
+            u16::from_le_bytes(chunk_bytes[8..10].try_into().unwrap()) as usize;
+        let doc_ids_end = 10 + doc_ids_length;
+        let term_frequencies_end = doc_ids_end + term_frequencies_length;
+        if term_frequencies_end > chunk_bytes.len() {
+            return Err(format!(
+                "{} bytes of doc ids and {} bytes of term frequencies do not fit their chunk",
+                doc_ids_length, term_frequencies_length
+            ));
+        }
+        self.doc_ids = chunk_bytes[10..doc_ids_end].to_vec();
+        self.term_frequencies = chunk_bytes[doc_ids_end..term_frequencies_end].to_vec();
+        self.positions = Cow::Borrowed(&chunk_bytes[term_frequencies_end..]);
+        Ok(())
     }
-    pub fn encode_positions(&mut self, positions: &Vec<u32>) -> Vec<u8> {
-        let mut encoded_positions: Vec<u8> = vb_encode_positions(&positions);
-        encoded_positions.push(POSITIONS_DELIMITER);
-        encoded_positions
+}
+
+// the offset after the first no_of_values variable byte encoded values, every value ends
+// with a byte of at least 128
+fn skip_vb_values(bytes: &[u8], no_of_values: usize) -> Option<usize> {
+    if no_of_values == 0 {
+        return Some(0);
     }
+    bytes
+        .iter()
+        .enumerate()
+        .filter(|(_, byte)| **byte >= 128)
+        .nth(no_of_values - 1)
+        .map(|(i, _)| i + 1)
+}
+
+fn encode_position_gaps(positions: &[u32]) -> Vec<u32> {
+    let mut last_position = 0;
+    positions
+        .iter()
+        .map(|position| {
+            let gap = position - last_position;
+            last_position = *position;
+            gap
+        })
+        .collect()
+}
 
-    pub fn set_max_doc_id(&mut self, doc_id: u32) {
-        self.max_doc_id = self.max_doc_id.max(doc_id);
+fn decode_position_gaps(gaps: Vec<u32>) -> Vec<u32> {
+    let mut position = 0;
+    gaps.into_iter()
+        .map(|gap| {
+            position += gap;
+            position
+        })
+        .collect()
+}
+
+// the values the doc ids of a chunk are stored as, see the chunk layout
+pub fn encode_doc_id_gaps(doc_ids: impl Iterator<Item = u32>) -> Vec<u32> {
+    let mut last_doc_id = None;
+    doc_ids
+        .map(|doc_id| {
+            let gap = match last_doc_id {
+                Some(last_doc_id) => doc_id - last_doc_id - 1,
+                None => doc_id,
+            };
+            last_doc_id = Some(doc_id);
+            gap
+        })
+        .collect()
+}
+
+// turns the gaps into doc ids, None if a doc id does not fit a u32
+fn decode_doc_id_gaps(values: &mut [u32]) -> Option<()> {
+    for i in 1..values.len() {
+        values[i] = values[i - 1].checked_add(values[i])?.checked_add(1)?;
     }
+    Some(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::indexer::checksum::as_corruption_error;
+
+    fn create_test_postings() -> Vec<Posting> {
+        vec![
+            Posting { doc_id: 3, positions: vec![0, 4] },
+            Posting { doc_id: 200, positions: vec![7] },
+            Posting { doc_id: 20_000, positions: vec![] },
+        ]
+    }
 
     #[test]
     fn test_new_chunk() {
         let chunk = Chunk::new(42);
 
         assert_eq!(chunk.term, 42);
-        assert_eq!(chunk.size_of_chunk, 8);
+        assert_eq!(chunk.size_of_chunk, CHUNK_HEADER_SIZE);
         assert_eq!(chunk.max_doc_id, 0);
-        assert_eq!(chunk.last_doc_id, 0);
         assert_eq!(chunk.no_of_postings, 0);
         assert!(chunk.doc_ids.is_empty());
+        assert!(chunk.term_frequencies.is_empty());
         assert!(chunk.positions.is_empty());
     }
 
     #[test]
-    fn test_reset() {
-        let mut chunk = Chunk::new(1);
-
-        // Modify the chunk
-        chunk.size_of_chunk = 100;
-        chunk.last_doc_id = 50;
-        chunk.max_doc_id = 75;
-        chunk.no_of_postings = 5;
-        chunk.doc_ids.extend_from_slice(&[1, 2, 3]);
-        chunk.positions.extend_from_slice(&[4, 5, 6]);
-
-        // Reset and verify
-        chunk.reset();
-
-        assert_eq!(chunk.size_of_chunk, 8);
-        assert_eq!(chunk.last_doc_id, 0);
-        assert_eq!(chunk.max_doc_id, 0);
-        assert_eq!(chunk.no_of_postings, 0);
-        assert!(chunk.doc_ids.is_empty());
-        assert!(chunk.positions.is_empty());
-        assert_eq!(chunk.term, 1); // term should not change
+    fn test_chunk_round_trips_with_every_codec() {
+        let postings = create_test_postings();
+        for codec_id in PostingCodecId::ALL {
+            let chunk = Chunk::from_postings(7, &postings, codec_id);
+            assert_eq!(chunk.codec_id, codec_id);
+            assert_eq!(chunk.max_doc_id, 20_000);
+            let chunk_bytes = chunk.encode();
+            assert_eq!(chunk_bytes.len(), chunk.size_of_chunk as usize);
+
+            let mut decoded = Chunk::new(7);
+            decoded.decode(&chunk_bytes[4..]).unwrap();
+            assert_eq!(decoded, chunk);
+            // the positions are left where they are until a posting list is asked for
+            assert!(matches!(decoded.positions, Cow::Borrowed(_)));
+            assert_eq!(decoded.get_doc_ids().unwrap(), vec![3, 200, 20_000]);
+            assert_eq!(decoded.get_term_frequencies().unwrap(), vec![2, 1, 0]);
+            assert_eq!(decoded.get_postings().unwrap(), postings.clone());
+            assert_eq!(decoded.get_posting_list(0).unwrap(), vec![0, 4]);
+            assert_eq!(decoded.get_posting_list(1).unwrap(), vec![7]);
+            assert_eq!(decoded.get_posting_list(2).unwrap(), Vec::<u32>::new());
+        }
     }
 
     #[test]
-    fn test_add_encoded_doc_id() {
-        let mut chunk = Chunk::new(1);
-        let encoded = vec![0x85, 0x01]; // example encoded value
-
-        chunk.add_encoded_doc_id(100, encoded.clone());
-
-        assert_eq!(chunk.last_doc_id, 100);
-        assert_eq!(chunk.size_of_chunk, 8 + encoded.len() as u32);
-        assert_eq!(chunk.doc_ids, encoded);
+    fn test_doc_ids_are_stored_as_gaps() {
+        assert_eq!(encode_doc_id_gaps([3, 200, 201, 20_000].into_iter()), vec![3, 196, 0, 19_798]);
+        let mut values = vec![3, 196, 0, 19_798];
+        assert_eq!(decode_doc_id_gaps(&mut values), Some(()));
+        assert_eq!(values, vec![3, 200, 201, 20_000]);
+        // doc id 0 only shows up in tests, it still round trips
+        let mut values = encode_doc_id_gaps([0, 1].into_iter());
+        assert_eq!(decode_doc_id_gaps(&mut values), Some(()));
+        assert_eq!(values, vec![0, 1]);
+        // a gap past the largest doc id
+        assert_eq!(decode_doc_id_gaps(&mut [u32::MAX, 0]), None);
     }
 
     #[test]
-    fn test_add_multiple_encoded_doc_ids() {
-        let mut chunk = Chunk::new(1);
-
-        let encoded1 = vec![0x01];
-        let encoded2 = vec![0x02, 0x03];
-
-        chunk.add_encoded_doc_id(10, encoded1.clone());
-        chunk.add_encoded_doc_id(20, encoded2.clone());
-
-        assert_eq!(chunk.last_doc_id, 20);
-        assert_eq!(chunk.size_of_chunk, 8 + 1 + 2);
-        assert_eq!(chunk.doc_ids, vec![0x01, 0x02, 0x03]);
+    fn test_sections_are_read_into_a_caller_buffer() {
+        let chunk = Chunk::from_postings(7, &create_test_postings(), PostingCodecId::PForDelta);
+        let mut values = [0; MAX_POSTINGS_PER_CHUNK];
+        assert_eq!(chunk.read_doc_ids(&mut values).unwrap(), 3);
+        assert_eq!(&values[..3], &[3, 200, 20_000]);
+        // the buffer is reused for the next section without being cleared
+        assert_eq!(chunk.read_term_frequencies(&mut values).unwrap(), 3);
+        assert_eq!(&values[..3], &[2, 1, 0]);
     }
 
     #[test]
-    fn test_encode_doc_id_first_document() {
-        let mut chunk = Chunk::new(1);
-
-        let encoded = chunk.encode_doc_id(100);
-
-        // First doc_id is encoded as is (100 - 0)
-        assert!(!encoded.is_empty());
-        // The actual encoding depends on vb_encode implementation
+    fn test_positions_are_found_by_term_frequencies() {
+        // 16384 is variable byte encoded as 0x01 0x00 0x80, a 0x00 inside the positions
+        // used to be taken as the end of a posting
+        let postings = vec![
+            Posting { doc_id: 1, positions: vec![16384, 16390] },
+            Posting { doc_id: 2, positions: vec![0, 1 << 21] },
+            Posting { doc_id: 3, positions: vec![5] },
+        ];
+        let chunk = Chunk::from_postings(1, &postings, PostingCodecId::PForDelta);
+        assert!(chunk.positions.contains(&0x00));
+        assert_eq!(chunk.get_posting_list(0).unwrap(), vec![16384, 16390]);
+        assert_eq!(chunk.get_posting_list(1).unwrap(), vec![0, 1 << 21]);
+        assert_eq!(chunk.get_posting_list(2).unwrap(), vec![5]);
+        assert_eq!(chunk.get_posting_list(3).unwrap(), Vec::<u32>::new());
+        assert_eq!(chunk.get_postings().unwrap(), postings);
     }
 
     #[test]
-    fn test_encode_doc_id_subsequent_documents() {
-        let mut chunk = Chunk::new(1);
-        chunk.last_doc_id = 100;
-
-        let encoded = chunk.encode_doc_id(150);
-        let vb_encoded = vb_encode(&50);
-        // Should encode the delta (150 - 100 = 50)
-        assert_eq!(encoded, vb_encoded);
+    fn test_positions_not_matching_term_frequencies_are_refused() {
+        let mut chunk = Chunk::from_postings(1, &create_test_postings(), PostingCodecId::VariableByte);
+        chunk.block_id = 5;
+        chunk.positions.to_mut().push(0x81);
+        assert_invalid_block(chunk.get_postings(), 5);
+        chunk.positions.to_mut().truncate(1);
+        assert_invalid_block(chunk.get_postings(), 5);
+        assert_invalid_block(chunk.get_posting_list(1), 5);
+        // scoring never looks at the positions
+        assert_eq!(chunk.get_term_frequencies().unwrap(), vec![2, 1, 0]);
     }
 
-    #[test]
-    fn test_add_encoded_positions() {
-        let mut chunk = Chunk::new(1);
-        let encoded_pos = vec![0x10, 0x20, 0x30];
-
-        chunk.add_encoded_positions(encoded_pos.clone());
-
-        assert_eq!(chunk.size_of_chunk, 8 + encoded_pos.len() as u32);
-        assert_eq!(chunk.positions, encoded_pos);
+    fn assert_invalid_block<T: std::fmt::Debug>(result: io::Result<T>, expected_block_id: u32) {
+        let error = result.unwrap_err();
+        match as_corruption_error(&error) {
+            Some(CorruptionError::InvalidBlock { block_id, .. }) => {
+                assert_eq!(*block_id, expected_block_id)
+            }
+            _ => panic!("expected an invalid block, got {}", error),
+        }
     }
 
     #[test]
-    fn test_add_multiple_encoded_positions() {
-        let mut chunk = Chunk::new(1);
-
-        let pos1 = vec![0x01, 0x02];
-        let pos2 = vec![0x03, 0x04, 0x05];
-
-        chunk.add_encoded_positions(pos1.clone());
-        chunk.add_encoded_positions(pos2.clone());
-
-        assert_eq!(chunk.size_of_chunk, 8 + 2 + 3);
-        assert_eq!(chunk.positions, vec![0x01, 0x02, 0x03, 0x04, 0x05]);
+    fn test_sections_that_do_not_decode_are_corruption_errors() {
+        let mut chunk = Chunk::from_postings(1, &create_test_postings(), PostingCodecId::VariableByte);
+        chunk.block_id = 9;
+        // the last doc id runs past the end of its section
+        chunk.doc_ids.pop();
+        assert_invalid_block(chunk.get_doc_ids(), 9);
+        chunk.term_frequencies.pop();
+        let mut values = [0; MAX_POSTINGS_PER_CHUNK];
+        assert_invalid_block(chunk.read_term_frequencies(&mut values), 9);
     }
 
     #[test]
-    fn test_encode_positions_empty() {
-        let mut chunk = Chunk::new(1);
-        let positions = vec![];
-
-        let encoded = chunk.encode_positions(&positions);
+    fn test_empty_chunk() {
+        let chunk = Chunk::from_postings(1, &[], PostingCodecId::GolombRice);
+        let chunk_bytes = chunk.encode();
+        let mut decoded = Chunk::new(1);
+        decoded.decode(&chunk_bytes[4..]).unwrap();
+        assert_eq!(decoded.get_doc_ids().unwrap(), Vec::<u32>::new());
+        assert_eq!(decoded.get_postings().unwrap(), Vec::new());
+        assert_eq!(decoded.max_doc_id, 0);
+    }
 
-        // Should still have delimiter
-        assert_eq!(encoded.last(), Some(&POSITIONS_DELIMITER));
+    #[test]
+    fn test_invalid_chunk_is_refused() {
+        let mut chunk_bytes = Chunk::from_postings(1, &create_test_postings(), PostingCodecId::EliasGamma).encode();
+        chunk_bytes[8] = 42;
+        assert!(Chunk::new(1).decode(&chunk_bytes[4..]).unwrap_err().contains("codec id 42"));
+        assert!(Chunk::new(1).decode(&chunk_bytes[4..9]).unwrap_err().contains("too short"));
     }
 
     #[test]
     fn test_chunk_clone() {
         let mut chunk1 = Chunk::new(1);
         chunk1.doc_ids = vec![1, 2, 3];
-        chunk1.positions = vec![4, 5, 6];
-        chunk1.last_doc_id = 42;
+        chunk1.positions = Cow::Owned(vec![4, 5, 6]);
 
         let chunk2 = chunk1.clone();
 
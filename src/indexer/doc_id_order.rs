@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    compressors::posting_codec::{PostingCodecId, encode_with_fallback},
+    dictionary::Posting,
+    indexer::{
+        chunk::{MAX_POSTINGS_PER_CHUNK, encode_doc_id_gaps},
+        document_metadata::DocumentMetadata,
+    },
+};
+
+/*
+The order the doc ids of a build are handed out in. The indexer numbers the
+documents in the order it reads them, reordering renumbers them before the
+final merge so that similar documents, which share many terms, get close doc
+ids. The gaps between the doc ids of a posting list get smaller and take
+fewer bytes in final.idx.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum DocIdOrder {
+    #[default]
+    ReadOrder,
+    // pages under the same path of a site tend to be about the same things
+    Url,
+    // articles with titles starting alike, like the ones about a place and its landmarks
+    Title,
+}
+
+// the new doc id of every doc id in read order, index 0 is unused. None when the documents
+// keep the doc ids they were read with. Documents comparing equal keep their read order
+pub fn reassign_doc_ids(
+    doc_id_order: DocIdOrder,
+    document_metadata: &HashMap<u32, DocumentMetadata>,
+) -> Option<Vec<u32>> {
+    let sort_key: fn(&DocumentMetadata) -> String = match doc_id_order {
+        DocIdOrder::ReadOrder => return None,
+        DocIdOrder::Url => |metadata| metadata.doc_url.clone(),
+        DocIdOrder::Title => |metadata| metadata.doc_name.to_lowercase(),
+    };
+    let mut doc_ids: Vec<(String, u32)> = document_metadata
+        .iter()
+        .map(|(doc_id, metadata)| (sort_key(metadata), *doc_id))
+        .collect();
+    doc_ids.sort();
+    let no_of_docs = doc_ids.iter().map(|(_, doc_id)| *doc_id).max().unwrap_or(0);
+    let mut new_doc_ids = vec![0; no_of_docs as usize + 1];
+    for (new_doc_id, (_, doc_id)) in doc_ids.into_iter().enumerate() {
+        new_doc_ids[doc_id as usize] = new_doc_id as u32 + 1;
+    }
+    Some(new_doc_ids)
+}
+
+// the postings under their new doc ids, sorted again
+pub fn remap_postings(postings: Vec<Posting>, new_doc_ids: &[u32]) -> Vec<Posting> {
+    let mut postings: Vec<Posting> = postings
+        .into_iter()
+        .map(|posting| Posting {
+            doc_id: new_doc_ids[posting.doc_id as usize],
+            positions: posting.positions,
+        })
+        .collect();
+    postings.sort_by_key(|posting| posting.doc_id);
+    postings
+}
+
+// estimated bytes of the doc id stream of the sorted doc ids in the chunks of final.idx
+// when encoded with the codec. Only the doc ids are counted, and not the chunks cut short
+// at the end of a block
+pub fn doc_id_stream_bytes(doc_ids: impl Iterator<Item = u32>, posting_codec: PostingCodecId) -> u64 {
+    let doc_ids: Vec<u32> = doc_ids.collect();
+    let mut bytes = Vec::new();
+    for chunk_doc_ids in doc_ids.chunks(MAX_POSTINGS_PER_CHUNK) {
+        let doc_id_gaps = encode_doc_id_gaps(chunk_doc_ids.iter().copied());
+        encode_with_fallback(posting_codec, &doc_id_gaps, &mut bytes);
+    }
+    bytes.len() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_metadata(name: &str, url: &str) -> DocumentMetadata {
+        DocumentMetadata {
+            doc_external_id: name.to_string(),
+            doc_name: name.to_string(),
+            doc_url: url.to_string(),
+            doc_length: 1,
+            title_length: 1,
+            url_length: 1,
+            anchor_length: 0,
+            duplicate_of: 0,
+        }
+    }
+
+    #[test]
+    fn test_reassign_doc_ids() {
+        let document_metadata = HashMap::from([
+            (1, create_test_metadata("cherry", "https://x/fruit/cherry")),
+            (2, create_test_metadata("Apple", "https://x/tree/apple")),
+            (3, create_test_metadata("banana", "https://x/fruit/banana")),
+            (4, create_test_metadata("apple", "https://x/fruit/apple")),
+        ]);
+        assert_eq!(reassign_doc_ids(DocIdOrder::ReadOrder, &document_metadata), None);
+        assert_eq!(
+            reassign_doc_ids(DocIdOrder::Title, &document_metadata),
+            Some(vec![0, 4, 1, 3, 2])
+        );
+        assert_eq!(
+            reassign_doc_ids(DocIdOrder::Url, &document_metadata),
+            Some(vec![0, 3, 4, 2, 1])
+        );
+    }
+
+    #[test]
+    fn test_remap_postings() {
+        let postings = vec![
+            Posting { doc_id: 1, positions: vec![4] },
+            Posting { doc_id: 3, positions: vec![0, 2] },
+        ];
+        let remapped = remap_postings(postings, &[0, 3, 1, 2]);
+        assert_eq!(remapped.iter().map(|posting| posting.doc_id).collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(remapped[0].positions, vec![0, 2]);
+    }
+
+    #[test]
+    fn test_doc_id_bytes() {
+        let vb = PostingCodecId::VariableByte;
+        assert_eq!(doc_id_stream_bytes([1, 2, 3].into_iter(), vb), 3);
+        assert_eq!(doc_id_stream_bytes([1, 200].into_iter(), vb), 3);
+        assert_eq!(doc_id_stream_bytes([100_000].into_iter(), vb), 3);
+        // every chunk starts from 0 again
+        assert_eq!(doc_id_stream_bytes(1000..1129, vb), 2 + 127 + 2);
+        // consecutive doc ids take a bit each
+        assert_eq!(doc_id_stream_bytes(1..129, PostingCodecId::EliasGamma), 17);
+    }
+}
@@ -0,0 +1,152 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::indexer::helper::{read_string, read_u32, write_string, write_u32};
+
+pub const DOCUMENT_METADATA_FILE_NAME: &str = "documents.dat";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DocumentMetadata {
+    pub doc_external_id: String,
+    pub doc_name: String,
+    pub doc_url: String,
+    // number of tokens per field, doc_length is the length of the body
+    pub doc_length: u32,
+    pub title_length: u32,
+    pub url_length: u32,
+    // known only once every document of the index is parsed
+    pub anchor_length: u32,
+    // doc id of the document this one is a near-duplicate of, 0 if it is none
+    pub duplicate_of: u32,
+}
+
+/*
+File Layout->
+no_of_documents
+doc_id external_id name url length title_length url_length anchor_length duplicate_of
+.
+.
+.
+The documents are written in doc id order.
+ */
+pub fn write_document_metadata(
+    path: &Path,
+    document_metadata: &HashMap<u32, DocumentMetadata>,
+) -> io::Result<()> {
+    let file = File::create(path)?;
+    let mut writer = BufWriter::new(file);
+    let mut doc_ids: Vec<&u32> = document_metadata.keys().collect();
+    doc_ids.sort();
+
+    write_u32(&mut writer, doc_ids.len() as u32)?;
+    for doc_id in doc_ids {
+        write_document_metadata_record(&mut writer, *doc_id, &document_metadata[doc_id])?;
+    }
+    writer.flush()
+}
+
+pub fn write_document_metadata_record<W: Write>(
+    writer: &mut W,
+    doc_id: u32,
+    metadata: &DocumentMetadata,
+) -> io::Result<()> {
+    write_u32(writer, doc_id)?;
+    write_string(writer, &metadata.doc_external_id)?;
+    write_string(writer, &metadata.doc_name)?;
+    write_string(writer, &metadata.doc_url)?;
+    write_u32(writer, metadata.doc_length)?;
+    write_u32(writer, metadata.title_length)?;
+    write_u32(writer, metadata.url_length)?;
+    write_u32(writer, metadata.anchor_length)?;
+    write_u32(writer, metadata.duplicate_of)
+}
+
+pub fn read_document_metadata_record<R: Read>(reader: &mut R) -> io::Result<(u32, DocumentMetadata)> {
+    let doc_id = read_u32(reader)?;
+    let metadata = DocumentMetadata {
+        doc_external_id: read_string(reader)?,
+        doc_name: read_string(reader)?,
+        doc_url: read_string(reader)?,
+        doc_length: read_u32(reader)?,
+        title_length: read_u32(reader)?,
+        url_length: read_u32(reader)?,
+        anchor_length: read_u32(reader)?,
+        duplicate_of: read_u32(reader)?,
+    };
+    Ok((doc_id, metadata))
+}
+
+pub fn read_document_metadata(path: &Path) -> io::Result<HashMap<u32, DocumentMetadata>> {
+    let file = File::open(path)?;
+    let mut reader = BufReader::new(file);
+    let no_of_documents = read_u32(&mut reader)?;
+
+    let mut document_metadata = HashMap::with_capacity(no_of_documents as usize);
+    for _ in 0..no_of_documents {
+        let (doc_id, metadata) = read_document_metadata_record(&mut reader)?;
+        document_metadata.insert(doc_id, metadata);
+    }
+    Ok(document_metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn create_test_metadata(name: &str, length: u32) -> DocumentMetadata {
+        DocumentMetadata {
+            doc_external_id: format!("ext-{}", name),
+            doc_name: name.to_string(),
+            doc_url: format!("https://example.org/{}", name),
+            doc_length: length,
+            title_length: 1,
+            url_length: 3,
+            anchor_length: 5,
+            duplicate_of: 0,
+        }
+    }
+
+    #[test]
+    fn test_write_and_read_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(DOCUMENT_METADATA_FILE_NAME);
+
+        let mut document_metadata = HashMap::new();
+        document_metadata.insert(2, create_test_metadata("Zürich", 120));
+        document_metadata.insert(1, create_test_metadata("Anarchism", 4000));
+        document_metadata.insert(3, create_test_metadata("", 0));
+
+        write_document_metadata(&path, &document_metadata).unwrap();
+        let read_back = read_document_metadata(&path).unwrap();
+
+        assert_eq!(read_back, document_metadata);
+    }
+
+    #[test]
+    fn test_empty_metadata() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(DOCUMENT_METADATA_FILE_NAME);
+
+        write_document_metadata(&path, &HashMap::new()).unwrap();
+        assert!(read_document_metadata(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_truncated_file_is_an_error() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(DOCUMENT_METADATA_FILE_NAME);
+
+        let mut document_metadata = HashMap::new();
+        document_metadata.insert(1, create_test_metadata("Anarchism", 4000));
+        write_document_metadata(&path, &document_metadata).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 2]).unwrap();
+
+        assert!(read_document_metadata(&path).is_err());
+    }
+}
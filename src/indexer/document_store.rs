@@ -0,0 +1,419 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use bzip2::{Compression, read::BzDecoder, write::BzEncoder};
+
+use crate::{
+    document_source::Document,
+    indexer::helper::{read_string, read_u32, read_u64, write_string, write_u32, write_u64},
+};
+
+pub const DOCUMENT_STORE_FILE_NAME: &str = "stored_fields.dat";
+// documents are collected until their uncompressed size reaches this many bytes and then
+// compressed together, small documents compress a lot better in groups than on their own
+const DOCUMENT_STORE_BLOCK_SIZE: usize = 64 * 1024;
+
+/*
+Stores the original title, url and body of every document so they can be
+shown to users, the postings only know doc ids.
+
+File Layout->
+compressed_block
+.
+.
+.
+no_of_blocks
+first_doc_id block_offset compressed_block_length (one entry per block)
+block_index_offset (u64, the last 8 bytes of the file)
+
+A block holds documents with consecutive doc ids, every document is stored
+as doc_id external_id title url body before the block is compressed with
+bzip2. Looking up a document only decompresses the block holding it.
+ */
+pub struct DocumentStoreWriter {
+    writer: BufWriter<File>,
+    current_block: Vec<u8>,
+    current_block_first_doc_id: u32,
+    offset: u64,
+    block_index: Vec<BlockIndexEntry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct BlockIndexEntry {
+    first_doc_id: u32,
+    offset: u64,
+    length: u32,
+}
+
+// the blocks written up to a checkpoint, enough to continue writing the store after a crash
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentStoreCheckpoint {
+    offset: u64,
+    block_index: Vec<BlockIndexEntry>,
+}
+
+impl DocumentStoreCheckpoint {
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_u64(writer, self.offset)?;
+        write_block_index(writer, &self.block_index)
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self {
+            offset: read_u64(reader)?,
+            block_index: read_block_index(reader)?,
+        })
+    }
+}
+
+fn write_block_index<W: Write>(writer: &mut W, block_index: &[BlockIndexEntry]) -> io::Result<()> {
+    write_u32(writer, block_index.len() as u32)?;
+    for entry in block_index {
+        write_u32(writer, entry.first_doc_id)?;
+        write_u64(writer, entry.offset)?;
+        write_u32(writer, entry.length)?;
+    }
+    Ok(())
+}
+
+fn write_document_record<W: Write>(writer: &mut W, doc_id: u32, document: &Document) -> io::Result<()> {
+    write_u32(writer, doc_id)?;
+    write_string(writer, &document.id)?;
+    write_string(writer, &document.title)?;
+    write_string(writer, &document.url)?;
+    write_string(writer, &document.body)
+}
+
+fn read_document_record<R: Read>(reader: &mut R) -> io::Result<(u32, Document)> {
+    let doc_id = read_u32(reader)?;
+    let document = Document {
+        id: read_string(reader)?,
+        title: read_string(reader)?,
+        url: read_string(reader)?,
+        body: read_string(reader)?,
+        // links are not stored, they end up in the link graph of the segment
+        links: Vec::new(),
+    };
+    Ok((doc_id, document))
+}
+
+fn read_block_index<R: Read>(reader: &mut R) -> io::Result<Vec<BlockIndexEntry>> {
+    let no_of_blocks = read_u32(reader)?;
+    let mut block_index = Vec::with_capacity(no_of_blocks as usize);
+    for _ in 0..no_of_blocks {
+        block_index.push(BlockIndexEntry {
+            first_doc_id: read_u32(reader)?,
+            offset: read_u64(reader)?,
+            length: read_u32(reader)?,
+        });
+    }
+    Ok(block_index)
+}
+
+impl DocumentStoreWriter {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = File::create(path)?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+            current_block: Vec::new(),
+            current_block_first_doc_id: 0,
+            offset: 0,
+            block_index: Vec::new(),
+        })
+    }
+
+    // continues a store that was being written when a checkpoint was taken, whatever was
+    // written after the checkpoint is cut off
+    pub fn resume(path: &Path, checkpoint: &DocumentStoreCheckpoint) -> io::Result<Self> {
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        file.set_len(checkpoint.offset)?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+            current_block: Vec::new(),
+            current_block_first_doc_id: 0,
+            offset: checkpoint.offset,
+            block_index: checkpoint.block_index.clone(),
+        })
+    }
+
+    // writes out the documents added so far, afterwards they survive a crash
+    pub fn checkpoint(&mut self) -> io::Result<DocumentStoreCheckpoint> {
+        self.flush_block()?;
+        self.writer.flush()?;
+        self.writer.get_ref().sync_all()?;
+        Ok(DocumentStoreCheckpoint {
+            offset: self.offset,
+            block_index: self.block_index.clone(),
+        })
+    }
+
+    // documents have to be added in doc id order
+    pub fn add_document(&mut self, doc_id: u32, document: &Document) -> io::Result<()> {
+        if self.current_block.is_empty() {
+            self.current_block_first_doc_id = doc_id;
+        }
+        write_document_record(&mut self.current_block, doc_id, document)?;
+        if self.current_block.len() >= DOCUMENT_STORE_BLOCK_SIZE {
+            self.flush_block()?;
+        }
+        Ok(())
+    }
+
+    fn flush_block(&mut self) -> io::Result<()> {
+        if self.current_block.is_empty() {
+            return Ok(());
+        }
+        let mut encoder = BzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&self.current_block)?;
+        let compressed_block = encoder.finish()?;
+        self.writer.write_all(&compressed_block)?;
+        self.block_index.push(BlockIndexEntry {
+            first_doc_id: self.current_block_first_doc_id,
+            offset: self.offset,
+            length: compressed_block.len() as u32,
+        });
+        self.offset += compressed_block.len() as u64;
+        self.current_block.clear();
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.flush_block()?;
+        let block_index_offset = self.offset;
+        write_block_index(&mut self.writer, &self.block_index)?;
+        write_u64(&mut self.writer, block_index_offset)?;
+        self.writer.flush()
+    }
+}
+
+// only the block index is kept in memory, the blocks are read from disk on every lookup
+pub struct DocumentStoreReader {
+    path: PathBuf,
+    block_index: Vec<BlockIndexEntry>,
+}
+
+impl DocumentStoreReader {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        reader.seek(SeekFrom::End(-8))?;
+        let block_index_offset = read_u64(&mut reader)?;
+        reader.seek(SeekFrom::Start(block_index_offset))?;
+        let block_index = read_block_index(&mut reader)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            block_index,
+        })
+    }
+
+    pub fn get_no_of_blocks(&self) -> usize {
+        self.block_index.len()
+    }
+
+    // returns the documents of a block together with their doc ids, in doc id order
+    pub fn read_block(&self, block_number: usize) -> io::Result<Vec<(u32, Document)>> {
+        let entry = self.block_index[block_number];
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(entry.offset))?;
+        let mut compressed_block = vec![0u8; entry.length as usize];
+        file.read_exact(&mut compressed_block)?;
+
+        let mut block = Vec::new();
+        BzDecoder::new(compressed_block.as_slice()).read_to_end(&mut block)?;
+        let no_of_bytes = block.len() as u64;
+        let mut cursor = Cursor::new(block);
+        let mut documents = Vec::new();
+        while cursor.position() < no_of_bytes {
+            documents.push(read_document_record(&mut cursor)?);
+        }
+        Ok(documents)
+    }
+
+    /*
+    Writes the documents to a new store under new doc ids, new_doc_ids holds
+    the new doc id of every old one. The blocks of the new store have to be
+    written in new doc id order, so every document is first copied to a
+    scratch file next to the target and then read back from there in that
+    order, only one block is ever held in memory.
+     */
+    pub fn write_reordered(&self, target_path: &Path, new_doc_ids: &[u32]) -> io::Result<()> {
+        let scratch_path = target_path.with_extension("scratch");
+        let mut scratch_writer = BufWriter::new(File::create(&scratch_path)?);
+        // offset and length of every document in the scratch file, by new doc id
+        let mut records = vec![None; new_doc_ids.len()];
+        let mut offset = 0;
+        for block_number in 0..self.get_no_of_blocks() {
+            for (doc_id, document) in self.read_block(block_number)? {
+                let new_doc_id = new_doc_ids[doc_id as usize];
+                let mut record = Vec::new();
+                write_document_record(&mut record, new_doc_id, &document)?;
+                scratch_writer.write_all(&record)?;
+                records[new_doc_id as usize] = Some((offset, record.len()));
+                offset += record.len() as u64;
+            }
+        }
+        scratch_writer.flush()?;
+        drop(scratch_writer);
+
+        let mut scratch_file = File::open(&scratch_path)?;
+        let mut writer = DocumentStoreWriter::create(target_path)?;
+        let mut record = Vec::new();
+        for (offset, length) in records.into_iter().flatten() {
+            record.resize(length, 0);
+            scratch_file.seek(SeekFrom::Start(offset))?;
+            scratch_file.read_exact(&mut record)?;
+            let (doc_id, document) = read_document_record(&mut record.as_slice())?;
+            writer.add_document(doc_id, &document)?;
+        }
+        writer.finish()?;
+        fs::remove_file(scratch_path)
+    }
+
+    pub fn get_document(&self, doc_id: u32) -> io::Result<Option<Document>> {
+        // the last block starting at or before the doc id is the only one that can hold it
+        let block_number = self
+            .block_index
+            .partition_point(|entry| entry.first_doc_id <= doc_id);
+        if block_number == 0 {
+            return Ok(None);
+        }
+        Ok(self
+            .read_block(block_number - 1)?
+            .into_iter()
+            .find(|(stored_doc_id, _)| *stored_doc_id == doc_id)
+            .map(|(_, document)| document))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn create_test_document(i: u32) -> Document {
+        Document {
+            id: format!("ext-{}", i),
+            title: format!("Title {}", i),
+            url: format!("https://example.org/{}", i),
+            body: format!("body of document {} ", i).repeat(50),
+            links: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_get_document_across_blocks() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(DOCUMENT_STORE_FILE_NAME);
+
+        let mut writer = DocumentStoreWriter::create(&path).unwrap();
+        for doc_id in 1..=200 {
+            writer.add_document(doc_id, &create_test_document(doc_id)).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let reader = DocumentStoreReader::open(&path).unwrap();
+        assert!(reader.get_no_of_blocks() > 1);
+        for doc_id in [1, 57, 123, 200] {
+            assert_eq!(
+                reader.get_document(doc_id).unwrap(),
+                Some(create_test_document(doc_id))
+            );
+        }
+        assert_eq!(reader.get_document(0).unwrap(), None);
+        assert_eq!(reader.get_document(201).unwrap(), None);
+        // the documents are stored compressed
+        let uncompressed_size: usize = (1..=200)
+            .map(|doc_id| create_test_document(doc_id).body.len())
+            .sum();
+        assert!((std::fs::metadata(&path).unwrap().len() as usize) < uncompressed_size / 4);
+    }
+
+    #[test]
+    fn test_read_block_returns_documents_in_order() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(DOCUMENT_STORE_FILE_NAME);
+
+        let mut writer = DocumentStoreWriter::create(&path).unwrap();
+        writer.add_document(1, &create_test_document(1)).unwrap();
+        writer.add_document(3, &create_test_document(3)).unwrap();
+        writer.finish().unwrap();
+
+        let reader = DocumentStoreReader::open(&path).unwrap();
+        let doc_ids: Vec<u32> = reader
+            .read_block(0)
+            .unwrap()
+            .into_iter()
+            .map(|(doc_id, _)| doc_id)
+            .collect();
+        assert_eq!(doc_ids, vec![1, 3]);
+        assert_eq!(reader.get_document(2).unwrap(), None);
+    }
+
+    #[test]
+    fn test_resume_from_checkpoint() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(DOCUMENT_STORE_FILE_NAME);
+
+        let mut writer = DocumentStoreWriter::create(&path).unwrap();
+        writer.add_document(1, &create_test_document(1)).unwrap();
+        let checkpoint = writer.checkpoint().unwrap();
+        let mut checkpoint_bytes = Vec::new();
+        checkpoint.write(&mut checkpoint_bytes).unwrap();
+        // written after the checkpoint and lost in the crash
+        writer.add_document(2, &create_test_document(2)).unwrap();
+        writer.checkpoint().unwrap();
+        drop(writer);
+
+        let checkpoint = DocumentStoreCheckpoint::read(&mut checkpoint_bytes.as_slice()).unwrap();
+        let mut writer = DocumentStoreWriter::resume(&path, &checkpoint).unwrap();
+        writer.add_document(2, &create_test_document(20)).unwrap();
+        writer.finish().unwrap();
+
+        let reader = DocumentStoreReader::open(&path).unwrap();
+        assert_eq!(reader.get_document(1).unwrap(), Some(create_test_document(1)));
+        assert_eq!(reader.get_document(2).unwrap(), Some(create_test_document(20)));
+    }
+
+    #[test]
+    fn test_write_reordered() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(DOCUMENT_STORE_FILE_NAME);
+        let reordered_path = dir.path().join("reordered.dat");
+
+        let mut writer = DocumentStoreWriter::create(&path).unwrap();
+        for doc_id in 1..=200 {
+            writer.add_document(doc_id, &create_test_document(doc_id)).unwrap();
+        }
+        writer.finish().unwrap();
+        // reverses the doc ids
+        let new_doc_ids: Vec<u32> = (0..=200).map(|doc_id| (201 - doc_id) % 201).collect();
+        DocumentStoreReader::open(&path)
+            .unwrap()
+            .write_reordered(&reordered_path, &new_doc_ids)
+            .unwrap();
+
+        let reader = DocumentStoreReader::open(&reordered_path).unwrap();
+        assert!(reader.get_no_of_blocks() > 1);
+        for doc_id in [1, 57, 200] {
+            assert_eq!(
+                reader.get_document(201 - doc_id).unwrap(),
+                Some(create_test_document(doc_id))
+            );
+        }
+        assert!(!reordered_path.with_extension("scratch").exists());
+    }
+
+    #[test]
+    fn test_empty_store() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(DOCUMENT_STORE_FILE_NAME);
+
+        DocumentStoreWriter::create(&path).unwrap().finish().unwrap();
+        let reader = DocumentStoreReader::open(&path).unwrap();
+        assert_eq!(reader.get_no_of_blocks(), 0);
+        assert_eq!(reader.get_document(1).unwrap(), None);
+    }
+}
@@ -0,0 +1,96 @@
+/*
+The fields of a document that are indexed. Every field gets its own
+postings by prefixing the terms of the field with the field name in the
+lexicon, "title:rust" holds the documents with rust in their title. Body
+terms are not prefixed, they are by far the most common and keep the
+lexicon of an index built before fields existed valid.
+
+The anchor field of a document is not part of the document itself, it
+holds the text of the links pointing at the document from other documents
+of the index.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Field {
+    Title,
+    Body,
+    Url,
+    Anchor,
+}
+
+pub const ALL_FIELDS: [Field; 4] = [Field::Title, Field::Body, Field::Url, Field::Anchor];
+
+const FIELD_SEPARATOR: char = ':';
+
+impl Field {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Field::Title => "title",
+            Field::Body => "body",
+            Field::Url => "url",
+            Field::Anchor => "anchor",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Field> {
+        ALL_FIELDS.into_iter().find(|field| field.name() == name)
+    }
+
+    // the term the word is stored under in the lexicon when it occurs in this field
+    pub fn term(&self, word: &str) -> String {
+        match self {
+            Field::Body => word.to_string(),
+            _ => format!("{}{}{}", self.name(), FIELD_SEPARATOR, word),
+        }
+    }
+
+    // the tokenizer only splits on whitespace, a url is split on everything that is not
+    // alphanumeric so that its path segments become words of their own
+    pub fn prepare_text(&self, text: &str) -> String {
+        match self {
+            Field::Url => text.replace(|c: char| !c.is_alphanumeric(), " "),
+            _ => text.to_string(),
+        }
+    }
+
+    // splits a query word like "title:rust" into its field and the word, words without a
+    // known field name in front are returned as they are
+    pub fn split_qualified_word(word: &str) -> (Option<Field>, &str) {
+        if let Some((name, rest)) = word.split_once(FIELD_SEPARATOR)
+            && let Some(field) = Field::from_name(&name.to_lowercase())
+        {
+            return (Some(field), rest);
+        }
+        (None, word)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_term() {
+        assert_eq!(Field::Title.term("rust"), "title:rust");
+        assert_eq!(Field::Url.term("rust"), "url:rust");
+        assert_eq!(Field::Body.term("rust"), "rust");
+        assert_eq!(Field::Anchor.term("rust"), "anchor:rust");
+    }
+
+    #[test]
+    fn test_split_qualified_word() {
+        assert_eq!(Field::split_qualified_word("title:rust"), (Some(Field::Title), "rust"));
+        assert_eq!(Field::split_qualified_word("URL:wiki"), (Some(Field::Url), "wiki"));
+        assert_eq!(Field::split_qualified_word("anchor:rust"), (Some(Field::Anchor), "rust"));
+        assert_eq!(Field::split_qualified_word("rust"), (None, "rust"));
+        assert_eq!(Field::split_qualified_word("c++:lang"), (None, "c++:lang"));
+    }
+
+    #[test]
+    fn test_prepare_url() {
+        assert_eq!(
+            Field::Url.prepare_text("https://en.wikipedia.org/wiki/Rust").split_whitespace().collect::<Vec<_>>(),
+            vec!["https", "en", "wikipedia", "org", "wiki", "Rust"]
+        );
+        assert_eq!(Field::Title.prepare_text("Rust (language)"), "Rust (language)");
+    }
+}
@@ -1,8 +1,43 @@
+use std::io::{self, Read, Write};
+
 use crate::{
     compressors::vb_encode::{vb_decode, vb_encode},
     dictionary::Posting,
 };
 
+pub(crate) fn write_u32<W: Write>(writer: &mut W, value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+pub(crate) fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+pub(crate) fn write_u64<W: Write>(writer: &mut W, value: u64) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+pub(crate) fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+// strings are stored as their length in bytes followed by the utf-8 bytes
+pub(crate) fn write_string<W: Write>(writer: &mut W, value: &str) -> io::Result<()> {
+    write_u32(writer, value.len() as u32)?;
+    writer.write_all(value.as_bytes())
+}
+
+pub(crate) fn read_string<R: Read>(reader: &mut R) -> io::Result<String> {
+    let length = read_u32(reader)? as usize;
+    let mut string_buf = vec![0u8; length];
+    reader.read_exact(&mut string_buf)?;
+    String::from_utf8(string_buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
 pub(crate) fn vb_decode_positions(bytes: &[u8]) -> Vec<u32> {
     let mut positions = Vec::new();
     let mut offset = 0;
@@ -0,0 +1,275 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::{
+    compressors::posting_codec::PostingCodecId,
+    indexer::{
+        block::{BLOCK_CHECKSUM_SIZE, BLOCK_SIZE},
+        helper::{read_u32, read_u64, write_u32, write_u64},
+    },
+};
+
+const INDEX_FILE_MAGIC: [u8; 8] = *b"SRCHIDX\0";
+// raised whenever the layout of the blocks or chunks changes, a file of another version is
+// refused instead of being decoded as garbage
+pub const INDEX_FORMAT_VERSION: u32 = 4;
+pub const HEADER_SIZE: u64 = 32;
+pub const FOOTER_SIZE: u64 = 24;
+
+/*
+final.idx describes itself, the blocks are framed by a header and a footer.
+
+File Layout->
+header
+block (BLOCK_SIZE bytes) crc32 of the block (block_id from 0 to no_of_blocks - 1)
+.
+.
+.
+footer
+
+Header Layout->
+magic (8 bytes) version block_size doc_id_codec (u8) position_codec (u8) reserved (14 zero bytes)
+
+Footer Layout->
+no_of_blocks no_of_terms no_of_postings (u64) magic (8 bytes)
+
+doc_id_codec is the codec the index was built with, chunks whose doc ids
+it can not encode name their own codec. Positions are variable byte encoded.
+
+The footer is written last, a file cut short by a crash has no footer and
+is refused like a file of another format.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IndexFileHeader {
+    pub version: u32,
+    pub block_size: u32,
+    pub doc_id_codec: u8,
+    pub position_codec: u8,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct IndexFileFooter {
+    pub no_of_blocks: u32,
+    pub no_of_terms: u32,
+    pub no_of_postings: u64,
+}
+
+fn invalid_index_file(path: &Path, reason: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{} is not a readable index file: {}", path.display(), reason),
+    )
+}
+
+impl IndexFileHeader {
+    // the header of the files this build writes, with the default codec
+    pub fn current() -> Self {
+        Self {
+            version: INDEX_FORMAT_VERSION,
+            block_size: BLOCK_SIZE as u32,
+            doc_id_codec: PostingCodecId::default().to_u8(),
+            position_codec: PostingCodecId::VariableByte.to_u8(),
+        }
+    }
+
+    pub fn get_doc_id_codec(&self) -> PostingCodecId {
+        PostingCodecId::from_u8(self.doc_id_codec).unwrap_or_default()
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        header.extend_from_slice(&INDEX_FILE_MAGIC);
+        write_u32(&mut header, self.version)?;
+        write_u32(&mut header, self.block_size)?;
+        header.push(self.doc_id_codec);
+        header.push(self.position_codec);
+        header.resize(HEADER_SIZE as usize, 0);
+        writer.write_all(&header)
+    }
+
+    fn read<R: Read>(reader: &mut R) -> io::Result<Option<Self>> {
+        let mut header = [0u8; HEADER_SIZE as usize];
+        reader.read_exact(&mut header)?;
+        if header[..8] != INDEX_FILE_MAGIC {
+            return Ok(None);
+        }
+        let mut fields = &header[8..];
+        Ok(Some(Self {
+            version: read_u32(&mut fields)?,
+            block_size: read_u32(&mut fields)?,
+            doc_id_codec: fields[0],
+            position_codec: fields[1],
+        }))
+    }
+}
+
+impl IndexFileFooter {
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_u32(writer, self.no_of_blocks)?;
+        write_u32(writer, self.no_of_terms)?;
+        write_u64(writer, self.no_of_postings)?;
+        writer.write_all(&INDEX_FILE_MAGIC)
+    }
+
+    fn read<R: Read>(reader: &mut R) -> io::Result<Option<Self>> {
+        let footer = Self {
+            no_of_blocks: read_u32(reader)?,
+            no_of_terms: read_u32(reader)?,
+            no_of_postings: read_u64(reader)?,
+        };
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        Ok((magic == INDEX_FILE_MAGIC).then_some(footer))
+    }
+}
+
+// byte offset of a block in final.idx
+pub fn block_offset(block_id: u32) -> u64 {
+    HEADER_SIZE + block_id as u64 * (BLOCK_SIZE + BLOCK_CHECKSUM_SIZE) as u64
+}
+
+// reads the header and footer of an index file and checks that this build can read the
+// blocks between them
+pub fn read_index_file_info(path: &Path) -> io::Result<(IndexFileHeader, IndexFileFooter)> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let file_size = reader.get_ref().metadata()?.len();
+    if file_size < HEADER_SIZE + FOOTER_SIZE {
+        return Err(invalid_index_file(
+            path,
+            format!("{} bytes is too short for a header and a footer", file_size),
+        ));
+    }
+    let Some(header) = IndexFileHeader::read(&mut reader)? else {
+        return Err(invalid_index_file(
+            path,
+            "no header, it was written by an older version or is no index file, rebuild the index"
+                .to_string(),
+        ));
+    };
+    if header.version != INDEX_FORMAT_VERSION {
+        return Err(invalid_index_file(
+            path,
+            format!(
+                "format version {} but this build reads version {}, rebuild the index",
+                header.version, INDEX_FORMAT_VERSION
+            ),
+        ));
+    }
+    if header.block_size != BLOCK_SIZE as u32 {
+        return Err(invalid_index_file(
+            path,
+            format!(
+                "blocks of {} bytes but this build reads blocks of {}",
+                header.block_size, BLOCK_SIZE
+            ),
+        ));
+    }
+    if PostingCodecId::from_u8(header.doc_id_codec).is_none() {
+        return Err(invalid_index_file(
+            path,
+            format!("unknown codec id {}", header.doc_id_codec),
+        ));
+    }
+    if header.position_codec != PostingCodecId::VariableByte.to_u8() {
+        return Err(invalid_index_file(
+            path,
+            format!("unknown codec id {}", header.position_codec),
+        ));
+    }
+
+    reader.seek(SeekFrom::End(-(FOOTER_SIZE as i64)))?;
+    let Some(footer) = IndexFileFooter::read(&mut reader)? else {
+        return Err(invalid_index_file(
+            path,
+            "no footer, the file was not written completely".to_string(),
+        ));
+    };
+    let expected_size = block_offset(footer.no_of_blocks) + FOOTER_SIZE;
+    if file_size != expected_size {
+        return Err(invalid_index_file(
+            path,
+            format!(
+                "{} bytes but {} blocks take {} bytes",
+                file_size, footer.no_of_blocks, expected_size
+            ),
+        ));
+    }
+    Ok((header, footer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn write_index_file(path: &Path, header: IndexFileHeader, no_of_blocks: u32) {
+        let mut bytes = Vec::new();
+        header.write(&mut bytes).unwrap();
+        bytes.resize(block_offset(no_of_blocks) as usize, 0);
+        IndexFileFooter {
+            no_of_blocks,
+            no_of_terms: 3,
+            no_of_postings: 7,
+        }
+        .write(&mut bytes)
+        .unwrap();
+        fs::write(path, bytes).unwrap();
+    }
+
+    fn error_message(path: &Path) -> String {
+        read_index_file_info(path).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_read_index_file_info() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("final.idx");
+        write_index_file(&path, IndexFileHeader::current(), 2);
+
+        let (header, footer) = read_index_file_info(&path).unwrap();
+        assert_eq!(header, IndexFileHeader::current());
+        assert_eq!(
+            footer,
+            IndexFileFooter {
+                no_of_blocks: 2,
+                no_of_terms: 3,
+                no_of_postings: 7,
+            }
+        );
+    }
+
+    #[test]
+    fn test_mismatching_index_files_are_refused() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("final.idx");
+
+        // a file from before the header was introduced starts with a block
+        fs::write(&path, vec![1u8; BLOCK_SIZE]).unwrap();
+        assert!(error_message(&path).contains("no header"));
+
+        let header = IndexFileHeader::current();
+        write_index_file(&path, IndexFileHeader { version: 7, ..header }, 1);
+        assert!(error_message(&path).contains("format version 7"));
+        write_index_file(&path, IndexFileHeader { block_size: 4096, ..header }, 1);
+        assert!(error_message(&path).contains("blocks of 4096 bytes"));
+        write_index_file(&path, IndexFileHeader { position_codec: 9, ..header }, 1);
+        assert!(error_message(&path).contains("unknown codec id 9"));
+        write_index_file(&path, IndexFileHeader { doc_id_codec: 0, ..header }, 1);
+        assert!(error_message(&path).contains("unknown codec id 0"));
+        let simple_16 = PostingCodecId::Simple16.to_u8();
+        write_index_file(&path, IndexFileHeader { doc_id_codec: simple_16, ..header }, 1);
+        let (read_header, _) = read_index_file_info(&path).unwrap();
+        assert_eq!(read_header.get_doc_id_codec(), PostingCodecId::Simple16);
+
+        write_index_file(&path, header, 2);
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(error_message(&path).contains("no footer"));
+        fs::write(&path, &bytes[..HEADER_SIZE as usize]).unwrap();
+        assert!(error_message(&path).contains("too short"));
+    }
+}
@@ -1,23 +1,34 @@
 use std::{
     fs::File,
-    io::{self, Read, Seek},
+    io::{self, BufReader, Read, Seek, SeekFrom},
 };
 
-use crate::{dictionary::Posting, indexer::helper::vb_decode_posting_list};
+use crate::{
+    dictionary::Posting,
+    indexer::{
+        checksum::{CorruptionError, Crc32},
+        helper::vb_decode_posting_list,
+        run_file_writer::RUN_FILE_TRAILER_SIZE,
+    },
+};
 
+// reads a run file written by RunFileWriter term by term. The whole run is checked against
+// its checksum when the iterator is initialized, before any record is decoded
 pub struct IndexMergeIterator {
     no_of_terms: u32,
-    file: File,
+    file: BufReader<File>,
+    file_length: u64,
     current_term_no: u32,
     pub current_term: Option<String>,
     pub current_postings: Option<Vec<Posting>>,
-    current_offset: u32,
+    current_offset: u64,
 }
 
 impl IndexMergeIterator {
     pub fn new(file: File) -> IndexMergeIterator {
         IndexMergeIterator {
-            file: file,
+            file: BufReader::new(file),
+            file_length: 0,
             no_of_terms: 0,
             current_term_no: 0,
             current_term: None,
@@ -26,20 +37,88 @@ impl IndexMergeIterator {
         }
     }
 
+    // a length read from a run can only be wrong if the run was changed after the checksum
+    // was checked, it is still checked against what is left of the file before anything is
+    // allocated for it
+    fn read_record_bytes(&mut self, length: usize) -> io::Result<Vec<u8>> {
+        if self.current_offset + length as u64 + RUN_FILE_TRAILER_SIZE > self.file_length {
+            return Err(CorruptionError::InvalidRunFile {
+                reason: format!(
+                    "a record of {} bytes at offset {} runs past the end",
+                    length, self.current_offset
+                ),
+            }
+            .into());
+        }
+        let mut bytes = vec![0u8; length];
+        self.file.read_exact(&mut bytes)?;
+        self.current_offset += length as u64;
+        Ok(bytes)
+    }
+
+    fn read_record_u32(&mut self) -> io::Result<u32> {
+        let bytes = self.read_record_bytes(4)?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    // reads the records once to sum them up and compares them with the trailer, then goes
+    // back to the first record
+    fn verify_checksum(&mut self) -> io::Result<()> {
+        let records_end = self.file_length - RUN_FILE_TRAILER_SIZE;
+        let mut crc = Crc32::new();
+        let mut buf = [0u8; 64 * 1024];
+        let mut offset = 4;
+        while offset < records_end {
+            let length = buf.len().min((records_end - offset) as usize);
+            self.file.read_exact(&mut buf[..length])?;
+            crc.update(&buf[..length]);
+            offset += length as u64;
+        }
+        let mut trailer = [0u8; RUN_FILE_TRAILER_SIZE as usize];
+        self.file.read_exact(&mut trailer)?;
+        let no_of_terms = u32::from_le_bytes(trailer[0..4].try_into().unwrap());
+        let stored_checksum = u32::from_le_bytes(trailer[4..8].try_into().unwrap());
+        if no_of_terms != self.no_of_terms {
+            return Err(CorruptionError::InvalidRunFile {
+                reason: format!(
+                    "the header counts {} terms, the trailer {}",
+                    self.no_of_terms, no_of_terms
+                ),
+            }
+            .into());
+        }
+        if stored_checksum != crc.finish() {
+            return Err(CorruptionError::RunFileChecksumMismatch {
+                stored: stored_checksum,
+                computed: crc.finish(),
+            }
+            .into());
+        }
+        self.file.seek(SeekFrom::Start(4))?;
+        Ok(())
+    }
+
     pub fn get_current_term(&mut self) -> u32 {
         self.current_term_no
     }
 
     pub fn init(&mut self) -> io::Result<()> {
-        self.file.seek(std::io::SeekFrom::Start(0))?;
+        self.file_length = self.file.get_ref().metadata()?.len();
+        if self.file_length < 4 + RUN_FILE_TRAILER_SIZE {
+            return Err(CorruptionError::InvalidRunFile {
+                reason: format!("{} bytes is too short", self.file_length),
+            }
+            .into());
+        }
+        self.file.seek(SeekFrom::Start(0))?;
         let mut buf = [0u8; 4];
 
         self.file.read_exact(&mut buf)?;
 
         self.no_of_terms = u32::from_le_bytes(buf);
+        self.verify_checksum()?;
 
         self.current_offset = 4;
-
         self.next()?;
 
         Ok(())
@@ -51,31 +130,31 @@ impl IndexMergeIterator {
             self.current_postings = None;
             return Ok(false);
         }
-        let mut buf = [0u8; 4];
-
-        self.file.read_exact(&mut buf)?;
-        let string_length = u32::from_le_bytes(buf) as usize;
-        self.current_offset += 4;
-
-        let mut string_buf = vec![0u8; string_length];
-        self.file.read_exact(&mut string_buf)?;
+        let string_length = self.read_record_u32()? as usize;
+        let string_buf = self.read_record_bytes(string_length)?;
         self.current_term = Some(
             String::from_utf8(string_buf)
                 .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?,
         );
-        self.current_offset += string_length as u32;
-
-        self.file.read_exact(&mut buf)?;
-        let postings_length = u32::from_le_bytes(buf) as usize;
-        self.current_offset += 4;
 
-        let mut postings_buf = vec![0u8; postings_length];
-        self.file.read_exact(&mut postings_buf)?;
+        let postings_length = self.read_record_u32()? as usize;
+        let postings_buf = self.read_record_bytes(postings_length)?;
         let posting_list = vb_decode_posting_list(&postings_buf);
         self.current_postings = Some(posting_list);
-        self.current_offset += postings_length as u32;
 
         self.current_term_no += 1;
+        if self.current_term_no == self.no_of_terms
+            && self.current_offset + RUN_FILE_TRAILER_SIZE != self.file_length
+        {
+            return Err(CorruptionError::InvalidRunFile {
+                reason: format!(
+                    "{} bytes follow the last of {} terms",
+                    self.file_length - RUN_FILE_TRAILER_SIZE - self.current_offset,
+                    self.no_of_terms
+                ),
+            }
+            .into());
+        }
 
         Ok(true)
     }
@@ -84,7 +163,7 @@ impl IndexMergeIterator {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{dictionary::Posting, indexer::helper::{vb_encode_positions, vb_encode_posting_list}};
+    use crate::{dictionary::Posting, indexer::{checksum::crc32, helper::{vb_encode_positions, vb_encode_posting_list}}};
     use std::io::{Seek, Write};
     use tempfile::NamedTempFile;
 
@@ -96,13 +175,20 @@ mod tests {
         file.write_all(&(terms.len() as u32).to_le_bytes()).unwrap();
 
         // Write each term and its postings
+        let no_of_terms = terms.len() as u32;
+        let mut records = Vec::new();
         for (term, postings) in terms {
-            file.write_all(&(term.len() as u32).to_le_bytes()).unwrap();
-            file.write_all(term.as_bytes()).unwrap();
+            records.extend((term.len() as u32).to_le_bytes());
+            records.extend(term.as_bytes());
             let encoded_posting_list = vb_encode_posting_list(&postings);
-            file.write_all(&(encoded_posting_list.len() as u32).to_le_bytes()).unwrap();
-            file.write_all(&encoded_posting_list).unwrap();
+            records.extend((encoded_posting_list.len() as u32).to_le_bytes());
+            records.extend(encoded_posting_list);
         }
+        file.write_all(&records).unwrap();
+
+        // Write the trailer with the checksum of the records
+        file.write_all(&no_of_terms.to_le_bytes()).unwrap();
+        file.write_all(&crc32(&records).to_le_bytes()).unwrap();
 
         file.flush().unwrap();
         file.seek(std::io::SeekFrom::Start(0)).unwrap();
@@ -5,8 +5,14 @@ use std::{
 };
 
 use crate::{
+    compressors::posting_codec::PostingCodecId,
     dictionary::Posting,
-    indexer::{block::Block, chunk::Chunk},
+    indexer::{
+        block::{BLOCK_SIZE, Block},
+        checksum::crc32,
+        chunk::{CHUNK_HEADER_SIZE, Chunk, MAX_POSTINGS_PER_CHUNK},
+        index_file::{IndexFileFooter, IndexFileHeader},
+    },
 };
 
 /*
@@ -36,10 +42,11 @@ Chunk2
 .
 ChunkN
 
-Chunk Layout->
+Chunk Layout-> (see chunk.rs)
 ChunkMetadata
-doc_ids
-posting_lists
+doc_ids (encoded with the codec of the chunk)
+term_frequencies (encoded with the codec of the chunk)
+positions
 
 */
 
@@ -81,6 +88,9 @@ pub struct MergedIndexBlockWriter {
     pub current_block: Block,
     file_writer: BufWriter<File>,
     pub max_block_size: u8, // in kb
+    header_written: bool,
+    // the codec the doc ids of every chunk are encoded with, where it can encode them
+    posting_codec: PostingCodecId,
 }
 
 impl MergedIndexBlockWriter {
@@ -94,11 +104,46 @@ impl MergedIndexBlockWriter {
                 Some(block_size) => block_size,
                 None => 64,
             },
+            header_written: false,
+            posting_codec: PostingCodecId::default(),
         }
     }
 
+    pub fn set_posting_codec(&mut self, posting_codec: PostingCodecId) {
+        self.posting_codec = posting_codec;
+    }
+
+    // writes the last block and the footer and syncs the file, an index without terms has
+    // no blocks
     pub fn finish(&mut self) -> io::Result<()> {
-        self.write_block_to_index_file()
+        if self.current_block_no > 0 || !self.current_block.terms.is_empty() {
+            self.write_block_to_index_file()?;
+        }
+        self.write_header()?;
+        IndexFileFooter {
+            no_of_blocks: self.current_block_no,
+            no_of_terms: self.term_metadata.len() as u32,
+            no_of_postings: self
+                .term_metadata
+                .values()
+                .map(|metadata| metadata.term_frequency as u64)
+                .sum(),
+        }
+        .write(&mut self.file_writer)?;
+        self.file_writer.flush()?;
+        self.file_writer.get_ref().sync_all()
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        if !self.header_written {
+            IndexFileHeader {
+                doc_id_codec: self.posting_codec.to_u8(),
+                ..IndexFileHeader::current()
+            }
+            .write(&mut self.file_writer)?;
+            self.header_written = true;
+        }
+        Ok(())
     }
 
     fn add_block_to_term_metadata(&mut self, term: u32, block_no: u32) {
@@ -120,18 +165,19 @@ impl MergedIndexBlockWriter {
             },
         );
     }
+    // number of blocks written to the index file so far
+    pub fn get_no_of_blocks(&self) -> u32 {
+        self.current_block_no
+    }
     pub fn get_term_metadata(&self, term: u32) -> Option<&TermMetadata> {
         self.term_metadata.get(&term)
     }
     pub fn add_term(&mut self, term: u32, postings: Vec<Posting>) -> io::Result<()> {
+        let max_block_size = (self.max_block_size as u32 * 1000).min(BLOCK_SIZE as u32);
         // if it is not possible to add a new chunk to the block then we will reset the block
-        // the minimum number of bytes necessary to add a new chunk is 14
-        // 6 bytes for term and term_offset
-        // 8 bytes for the chunk max_doc_id and no of postings
-        // we try to avoid empty chunks if possible
-        if self.current_block.current_block_size + 6 + 8
-            > ((self.max_block_size as u32 * 1000).into())
-        {
+        // a term takes 6 bytes for term and term_offset and its first chunk at least the
+        // chunk header, we try to avoid empty chunks if possible
+        if self.current_block.current_block_size + 6 + CHUNK_HEADER_SIZE > max_block_size {
             self.write_block_to_index_file()?;
             self.current_block.reset();
         }
@@ -141,68 +187,71 @@ impl MergedIndexBlockWriter {
         self.add_block_to_term_metadata(term, self.current_block_no);
         self.add_frequency_to_term_metadata(term, postings.len() as u32);
         self.current_block.add_term(term);
-        self.current_block.current_chunk = Chunk::new(term);
 
-        let mut i = 0;
+        // a term without postings still gets an empty chunk
+        let mut remaining: &[Posting] = &postings;
         loop {
-            if self.current_block.current_chunk.no_of_postings >= 128 {
-                self.current_block.current_chunk.finish();
-                self.current_block.add_current_chunk();
-                self.current_block.reset_current_chunk();
+            let no_of_postings = remaining.len().min(MAX_POSTINGS_PER_CHUNK);
+            let mut chunk =
+                Chunk::from_postings(term, &remaining[..no_of_postings], self.posting_codec);
+            if self.current_block.size_with(&chunk) > max_block_size {
+                match self.largest_chunk_that_fits(term, &remaining[..no_of_postings], max_block_size) {
+                    Some(smaller_chunk) => chunk = smaller_chunk,
+                    None if self.current_block.chunks.is_empty() && self.current_block.terms.len() == 1 => {
+                        return Err(io::Error::other(format!(
+                            "a posting of term id {} does not fit into an empty block",
+                            term
+                        )));
+                    }
+                    None => {
+                        // we start a new block and so we need to update the metadata for
+                        // this block
+                        self.write_block_to_index_file()?;
+                        self.current_block.reset();
+                        self.current_block.add_term(term);
+                        self.add_block_to_term_metadata(term, self.current_block_no);
+                        continue;
+                    }
+                }
             }
-            if i == postings.len() {
-                self.current_block.current_chunk.finish();
-                self.current_block.add_current_chunk();
-                self.current_block.reset_current_chunk();
+            remaining = &remaining[chunk.no_of_postings as usize..];
+            self.current_block.add_chunk(chunk);
+            if remaining.is_empty() {
                 return Ok(());
             }
+        }
+    }
 
-            let current_posting = &postings[i];
-            let mut encoded_doc_id = self
-                .current_block
-                .current_chunk
-                .encode_doc_id(current_posting.doc_id);
-            let encoded_positions = self
-                .current_block
-                .current_chunk
-                .encode_positions(&current_posting.positions);
-            let size_of_posting = encoded_doc_id.len() as u32 + encoded_positions.len() as u32;
-            if (self.current_block.space_used() + size_of_posting)
-                > (self.max_block_size as u32 * 1000).into()
-            {
-                self.current_block.current_chunk.finish();
-                self.current_block.add_current_chunk();
-                self.write_block_to_index_file()?;
-                self.current_block.reset_current_chunk();
-                self.current_block.reset();
-
-                // we start a new block and so we need to update the metadata for this block
-                self.current_block.add_term(term);
-                self.add_block_to_term_metadata(term, self.current_block_no);
-                encoded_doc_id = self
-                    .current_block
-                    .current_chunk
-                    .encode_doc_id(current_posting.doc_id);
+    // the chunk of the most leading postings that still fits into the current block, found
+    // by a binary search over the number of postings
+    fn largest_chunk_that_fits(
+        &self,
+        term: u32,
+        postings: &[Posting],
+        max_block_size: u32,
+    ) -> Option<Chunk<'static>> {
+        let mut chunk_that_fits = None;
+        let (mut low, mut high) = (1, postings.len());
+        while low < high {
+            let no_of_postings = (low + high) / 2;
+            let chunk = Chunk::from_postings(term, &postings[..no_of_postings], self.posting_codec);
+            if self.current_block.size_with(&chunk) <= max_block_size {
+                chunk_that_fits = Some(chunk);
+                low = no_of_postings + 1;
+            } else {
+                high = no_of_postings;
             }
-
-            self.current_block
-                .current_chunk
-                .set_max_doc_id(current_posting.doc_id);
-            self.current_block
-                .current_chunk
-                .add_encoded_doc_id(current_posting.doc_id, encoded_doc_id);
-            self.current_block
-                .current_chunk
-                .add_encoded_positions(encoded_positions);
-            self.current_block.current_chunk.no_of_postings += 1;
-            i += 1;
         }
+        chunk_that_fits
     }
 
     fn write_block_to_index_file(&mut self) -> io::Result<()> {
+        self.write_header()?;
         self.current_block.encode();
         self.file_writer
             .write_all(&self.current_block.block_bytes)?;
+        self.file_writer
+            .write_all(&crc32(&self.current_block.block_bytes).to_le_bytes())?;
         self.file_writer.flush()?;
         self.current_block_no += 1;
         Ok(())
@@ -212,7 +261,10 @@ impl MergedIndexBlockWriter {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::dictionary::Posting;
+    use crate::{
+        dictionary::Posting,
+        indexer::index_file::{FOOTER_SIZE, HEADER_SIZE, block_offset},
+    };
     use std::io::{Read, Seek, SeekFrom};
     use tempfile::NamedTempFile;
 
@@ -463,9 +515,11 @@ mod tests {
         // File should contain data
         assert!(buffer.len() > 0);
 
-        // First 4 bytes should be number of terms (at least 1)
-        let no_of_terms = u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]);
+        // First 4 bytes after the header should be number of terms (at least 1)
+        let offset = HEADER_SIZE as usize;
+        let no_of_terms = u32::from_le_bytes(buffer[offset..offset + 4].try_into().unwrap());
         assert!(no_of_terms >= 1);
+        assert_eq!(buffer.len() as u64, block_offset(1) + FOOTER_SIZE);
     }
 
     #[test]
@@ -1,4 +1,16 @@
-use crate::{in_memory_dict::map_in_memory_dict::{MapInMemoryDict, MapInMemoryDictPointer}, my_bk_tree::{self, BkTree}};
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    in_memory_dict::map_in_memory_dict::{MapInMemoryDict, MapInMemoryDictPointer},
+    indexer::helper::{read_string, read_u32, write_string, write_u32},
+    my_bk_tree::{self, BkTree},
+};
+
+pub const LEXICON_FILE_NAME: &str = "lexicon.dat";
 
 pub struct InMemoryIndexMetatdata {
     pub bk_tree: BkTree,
@@ -17,6 +29,10 @@ impl InMemoryIndexMetatdata {
         self.in_memory_dict.get_term_metadata(term)
     }
 
+    pub fn find_term_metadata(&self, term: &str) -> Option<&MapInMemoryDictPointer> {
+        self.in_memory_dict.find(term)
+    }
+
     pub fn get_all_terms(&self)->Vec<String>{
         self.in_memory_dict.get_terms()
     }
@@ -25,10 +41,10 @@ impl InMemoryIndexMetatdata {
         self.in_memory_dict.get_term_id(term)
     }
 
-    // pub fn add_term(&mut self,term:String,term_id:u32,block_ids:Vec<u32>,term_frequency:u32){
-    //     self.bk_tree.add(&term);
-    //     self.in_memory_dict.add_term(&term, block_ids, term_frequency, term_id);
-    // }
+    pub fn add_term(&mut self,term:String,term_id:u32,block_ids:Vec<u32>,term_frequency:u32){
+        self.bk_tree.add(&term);
+        self.in_memory_dict.add_term(&term, block_ids, term_frequency, term_id);
+    }
 
       pub fn add_term_to_bk_tree(&mut self,term:String){
         self.bk_tree.add(&term);
@@ -45,4 +61,89 @@ impl InMemoryIndexMetatdata {
     pub fn set_block_ids(&mut self,term:&str,block_ids:Vec<u32>){
         self.in_memory_dict.set_block_ids(term, block_ids);
     }
+
+    /*
+    Lexicon Layout->
+    no_of_terms
+    term term_id term_frequency no_of_block_ids block_ids
+    .
+    .
+    .
+    Terms are written in term id order, which is also their sorted order.
+    The bk tree is not stored, it is rebuilt from the terms when the lexicon is read.
+     */
+    pub fn write_to_disk(&self, path: &Path) -> io::Result<()> {
+        let file = File::create(path)?;
+        let mut writer = BufWriter::new(file);
+        let mut terms = self.get_all_terms();
+        terms.sort_by_key(|term| self.get_term_metadata(term).term_id);
+
+        write_u32(&mut writer, terms.len() as u32)?;
+        for term in &terms {
+            let term_metadata = self.get_term_metadata(term);
+            write_string(&mut writer, term)?;
+            write_u32(&mut writer, term_metadata.term_id)?;
+            write_u32(&mut writer, term_metadata.term_frequency)?;
+            write_u32(&mut writer, term_metadata.block_ids.len() as u32)?;
+            for block_id in &term_metadata.block_ids {
+                write_u32(&mut writer, *block_id)?;
+            }
+        }
+        writer.flush()
+    }
+
+    pub fn read_from_disk(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let mut reader = BufReader::new(file);
+        let mut in_memory_index_metadata = Self::new();
+
+        let no_of_terms = read_u32(&mut reader)?;
+        for _ in 0..no_of_terms {
+            let term = read_string(&mut reader)?;
+            let term_id = read_u32(&mut reader)?;
+            let term_frequency = read_u32(&mut reader)?;
+            let no_of_block_ids = read_u32(&mut reader)?;
+            let mut block_ids = Vec::with_capacity(no_of_block_ids as usize);
+            for _ in 0..no_of_block_ids {
+                block_ids.push(read_u32(&mut reader)?);
+            }
+            in_memory_index_metadata.add_term(term, term_id, block_ids, term_frequency);
+        }
+        Ok(in_memory_index_metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_lexicon_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(LEXICON_FILE_NAME);
+
+        let mut metadata = InMemoryIndexMetatdata::new();
+        metadata.add_term("apple".to_string(), 1, vec![0], 3);
+        metadata.add_term("banana".to_string(), 2, vec![0, 1, 2], 700);
+        metadata.add_term("café".to_string(), 3, vec![2], 1);
+        metadata.write_to_disk(&path).unwrap();
+
+        let mut read_back = InMemoryIndexMetatdata::read_from_disk(&path).unwrap();
+        assert_eq!(read_back.in_memory_dict, metadata.in_memory_dict);
+        assert_eq!(read_back.get_term_metadata("banana").block_ids, vec![0, 1, 2]);
+        assert!(read_back.find_term_metadata("cherry").is_none());
+        // the bk tree is rebuilt from the stored terms
+        assert!(read_back.bk_tree.find("aple", 1).contains(&"apple".to_string()));
+    }
+
+    #[test]
+    fn test_empty_lexicon() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(LEXICON_FILE_NAME);
+
+        InMemoryIndexMetatdata::new().write_to_disk(&path).unwrap();
+        let read_back = InMemoryIndexMetatdata::read_from_disk(&path).unwrap();
+        assert!(read_back.get_all_terms().is_empty());
+    }
 }
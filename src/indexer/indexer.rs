@@ -1,30 +1,42 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
     fs::{self, File},
-    io::{self, BufReader},
-    path::Path,
-    sync::mpsc::{self},
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, mpsc},
+    thread,
+    time::{Duration, Instant},
 };
 
 use crate::{
-    dictionary::{Posting, Term},
+    compressors::posting_codec::PostingCodecId,
+    dictionary::{DEFAULT_MEMORY_BUDGET, Posting, Term},
+    document_source::{Document, DocumentSource},
     in_memory_dict::map_in_memory_dict::{MapInMemoryDict, MapInMemoryDictPointer},
-    indexer::{index_metadata::InMemoryIndexMetatdata, spimi::Spmi},
+    indexer::{
+        build_statistics::{BUILD_STATISTICS_FILE_NAME, BuildStatistics},
+        checkpoint::{
+            CHECKPOINT_FILE_NAME, DOCUMENT_METADATA_LOG_FILE_NAME, DocumentMetadataLog,
+            IndexCheckpoint,
+        },
+        doc_id_order::{DocIdOrder, reassign_doc_ids},
+        document_metadata::{
+            DOCUMENT_METADATA_FILE_NAME, DocumentMetadata, write_document_metadata,
+        },
+        document_store::{DOCUMENT_STORE_FILE_NAME, DocumentStoreReader, DocumentStoreWriter},
+        field::{ALL_FIELDS, Field},
+        index_metadata::{InMemoryIndexMetatdata, LEXICON_FILE_NAME},
+        link_graph::{LINK_GRAPH_FILE_NAME, LINK_LOG_FILE_NAME, LinkGraph, LinkLog, TitleIndex},
+        near_duplicate::{
+            DEFAULT_SIMILARITY_THRESHOLD, DuplicateHandling, LshIndex, MinHashSignature,
+            SIGNATURE_LOG_FILE_NAME, SignatureLog,
+        },
+        spimi::{DEFAULT_MERGE_FAN_IN, FINAL_INDEX_FILE_NAME, SpimiMessage, Spmi},
+    },
     my_bk_tree::BkTree,
     query_parser::tokenizer::SearchTokenizer,
+    scoring::page_rank::{PAGE_RANK_FILE_NAME, compute_page_rank, write_page_rank},
 };
-use bzip2::read::BzDecoder;
-use regex::Regex;
-use serde::{Deserialize, Serialize};
-
-// Define the structure matching your JSON format
-#[derive(Debug, Deserialize, Serialize)]
-struct WikiArticle {
-    url: String,
-    text: Vec<Vec<String>>,
-    id: String,
-    title: String,
-}
 pub struct IndexMetadata {
     bk_tree: BkTree,
     in_memory_dictionary: MapInMemoryDict,
@@ -42,34 +54,129 @@ impl IndexMetadata {
     pub fn add_term(term: String) {}
 }
 
-#[derive(Clone, Debug)]
-pub struct DocumentMetadata {
-    pub doc_name: String,
-    pub doc_url: String,
-    pub doc_length: u32,
-}
 pub struct Indexer {
     doc_id: u32,
     document_metadata: HashMap<u32, DocumentMetadata>,
     index_metadata: InMemoryIndexMetatdata,
     index_directory_path: String,
-    search_tokenizer: SearchTokenizer,
+    // shared with the worker threads parsing the corpus files
+    search_tokenizer: Arc<SearchTokenizer>,
+    document_source: Arc<dyn DocumentSource>,
+    no_of_workers: usize,
+    // number of threads inverting the documents into run files
+    no_of_spimi_workers: usize,
+    // only set while index is running
+    document_store_writer: Option<DocumentStoreWriter>,
+    document_metadata_log: Option<DocumentMetadataLog>,
+    link_log: Option<LinkLog>,
+    signature_log: Option<SignatureLog>,
+    duplicate_handling: DuplicateHandling,
+    similarity_threshold: f64,
+    // signatures of the documents indexed so far that are no duplicates themselves, only
+    // set while index is running with a duplicate handling other than Keep
+    lsh_index: Option<LshIndex>,
+    no_of_duplicates: u32,
+    doc_id_order: DocIdOrder,
+    posting_codec: PostingCodecId,
+    // a checkpoint is taken after the first input file that ends at least this many
+    // documents after the previous checkpoint
+    checkpoint_interval: u32,
+    completed_files: HashSet<String>,
+    last_checkpoint_doc_id: u32,
+    // summed over the workers, for the build statistics
+    parse_time: Duration,
+    // bytes of postings the spimi thread holds before it flushes a run
+    memory_budget: usize,
+    merge_fan_in: usize,
+}
+
+const DEFAULT_CHECKPOINT_INTERVAL: u32 = 10_000;
+
+// a document as it comes out of a worker, tokenized but without a doc id yet
+struct ParsedDocument {
+    document: Document,
+    doc_postings: HashMap<String, Vec<u32>>,
+    field_lengths: HashMap<Field, u32>,
+    // of the body, only computed when near-duplicates are looked for
+    signature: Option<MinHashSignature>,
 }
 
-fn extract_plaintext(text: &Vec<Vec<String>>) -> String {
-    // Join all paragraphs and sentences
-    let full_text = text
-        .iter()
-        .map(|paragraph| paragraph.join(""))
-        .collect::<Vec<String>>()
-        .join("\n\n"); // Separate paragraphs with double newline
-
-    // Remove all HTML/XML tags using regex
-    let tag_regex = Regex::new(r"<[^>]*>").unwrap();
-    tag_regex.replace_all(&full_text, "").to_string()
+// every field of the document is tokenized on its own, the terms of a field are
+// stored under the field's name so each field gets its own postings
+fn parse_document(
+    search_tokenizer: &SearchTokenizer,
+    document: Document,
+    compute_signature: bool,
+) -> ParsedDocument {
+    let mut doc_postings: HashMap<String, Vec<u32>> = HashMap::new();
+    let mut field_lengths: HashMap<Field, u32> = HashMap::new();
+    let mut signature = None;
+    for field in ALL_FIELDS {
+        let text = match field {
+            Field::Title => &document.title,
+            Field::Body => &document.body,
+            Field::Url => &document.url,
+            // comes from the documents linking here, see index_links
+            Field::Anchor => continue,
+        };
+        let tokens = search_tokenizer.tokenize(field.prepare_text(text));
+        field_lengths.insert(field, tokens.len() as u32);
+        if compute_signature && field == Field::Body {
+            signature = MinHashSignature::from_tokens(&tokens);
+        }
+        for token in &tokens {
+            doc_postings
+                .entry(field.term(&token.word))
+                .or_default()
+                .push(token.position);
+        }
+    }
+    ParsedDocument {
+        document,
+        doc_postings,
+        field_lengths,
+        signature,
+    }
 }
+
+// hands the postings of the document to the spimi worker responsible for its doc id
+fn send_postings(
+    doc_id: u32,
+    doc_postings: HashMap<String, Vec<u32>>,
+    txs: &[mpsc::Sender<SpimiMessage>],
+) -> io::Result<()> {
+    let tx = &txs[doc_id as usize % txs.len()];
+    for (key, value) in doc_postings {
+        let term = Term {
+            posting: Posting {
+                doc_id,
+                positions: value,
+            },
+            term: key,
+        };
+        tx.send(SpimiMessage::Term(term))
+            .map_err(|_| io::Error::other("spimi worker stopped"))?;
+    }
+    Ok(())
+}
+
+fn parse_file(
+    document_source: &dyn DocumentSource,
+    search_tokenizer: &SearchTokenizer,
+    path: &Path,
+    compute_signatures: bool,
+) -> io::Result<Vec<ParsedDocument>> {
+    Ok(document_source
+        .read_file(path)?
+        .map(|document| parse_document(search_tokenizer, document, compute_signatures))
+        .collect())
+}
+
 impl Indexer {
-    pub fn new(search_tokenizer: SearchTokenizer) -> Result<Self, std::io::Error> {
+    pub fn new(
+        search_tokenizer: SearchTokenizer,
+        document_source: Box<dyn DocumentSource>,
+    ) -> Result<Self, std::io::Error> {
         // let search_tokenizer = SearchTokenizer::new()?;
         Ok(Self {
             doc_id: 0,
@@ -78,69 +185,137 @@ impl Indexer {
             // term_sender: tx,
             // term_receiver: rx,
             index_directory_path: String::new(),
-            search_tokenizer: search_tokenizer,
+            search_tokenizer: Arc::new(search_tokenizer),
+            document_source: Arc::from(document_source),
+            no_of_workers: thread::available_parallelism().map_or(1, |n| n.get()),
+            no_of_spimi_workers: 1,
+            document_store_writer: None,
+            document_metadata_log: None,
+            link_log: None,
+            signature_log: None,
+            duplicate_handling: DuplicateHandling::Keep,
+            similarity_threshold: DEFAULT_SIMILARITY_THRESHOLD,
+            lsh_index: None,
+            no_of_duplicates: 0,
+            doc_id_order: DocIdOrder::ReadOrder,
+            posting_codec: PostingCodecId::default(),
+            checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+            completed_files: HashSet::new(),
+            last_checkpoint_doc_id: 0,
+            parse_time: Duration::ZERO,
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            merge_fan_in: DEFAULT_MERGE_FAN_IN,
         })
     }
 
+    pub fn set_ram_budget_mb(&mut self, ram_mb: usize) {
+        self.memory_budget = ram_mb.max(1) * 1024 * 1024;
+    }
+
+    // number of threads reading and tokenizing corpus files, defaults to the number of cores
+    pub fn set_no_of_workers(&mut self, no_of_workers: usize) {
+        self.no_of_workers = no_of_workers.max(1);
+    }
+
+    // every spimi worker writes its own run files, more workers mean more runs to merge
+    pub fn set_no_of_spimi_workers(&mut self, no_of_spimi_workers: usize) {
+        self.no_of_spimi_workers = no_of_spimi_workers.max(1);
+    }
+
+    pub fn set_merge_fan_in(&mut self, merge_fan_in: usize) {
+        self.merge_fan_in = merge_fan_in;
+    }
+
+    pub fn set_checkpoint_interval(&mut self, checkpoint_interval: u32) {
+        self.checkpoint_interval = checkpoint_interval.max(1);
+    }
+
+    pub fn set_document_source(&mut self, document_source: Box<dyn DocumentSource>) {
+        self.document_source = Arc::from(document_source);
+    }
+
+    // a resumed build has to use the handling of the interrupted one, the documents indexed
+    // before the interruption are not checked again
+    pub fn set_duplicate_handling(&mut self, duplicate_handling: DuplicateHandling) {
+        self.duplicate_handling = duplicate_handling;
+    }
+
+    // estimated share of shingles two documents need in common to be near-duplicates
+    pub fn set_similarity_threshold(&mut self, similarity_threshold: f64) {
+        self.similarity_threshold = similarity_threshold.clamp(0.0, 1.0);
+    }
+
+    // the doc ids are handed out in read order while indexing and reassigned in this order
+    // before the final merge
+    pub fn set_doc_id_order(&mut self, doc_id_order: DocIdOrder) {
+        self.doc_id_order = doc_id_order;
+    }
+
+    // the codec the doc ids in final.idx are encoded with
+    pub fn set_posting_codec(&mut self, posting_codec: PostingCodecId) {
+        self.posting_codec = posting_codec;
+    }
+
     pub fn get_no_of_docs(&self) -> u32 {
         self.doc_id
     }
-    fn read_bz2_file(
+    // adds a document parsed by a worker under the next doc id and hands its postings to
+    // one of the spimi workers, the documents are dealt out in turn so every worker gets
+    // them in increasing doc id order. Near-duplicates of a document added before are
+    // skipped or marked depending on the duplicate handling
+    fn add_parsed_document(
         &mut self,
-        path: &Path,
-        tx: &mpsc::Sender<Term>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let file = File::open(path)?;
-        let decoder = BzDecoder::new(file);
-        let reader = BufReader::new(decoder);
-
-        // Create a streaming deserializer
-        let stream = serde_json::Deserializer::from_reader(reader).into_iter::<WikiArticle>();
-
-        // let mut articles = Vec::new();
-
-        for (i, result) in stream.enumerate() {
-            match result {
-                Ok(article) => {
-                    self.doc_id += 1;
-
-                    let plain_text = extract_plaintext(&article.text);
-                    let tokens = self.search_tokenizer.tokenize(plain_text);
-                    self.document_metadata.insert(
-                        self.doc_id,
-                        DocumentMetadata {
-                            doc_name: article.title,
-                            doc_url: article.url,
-                            doc_length: tokens.len() as u32,
-                        },
-                    );
-                    // articles.push(article);
-                    let mut doc_postings: HashMap<String, Vec<u32>> = HashMap::new();
-                    for token in &tokens {
-                        doc_postings
-                            .entry(token.word.clone())
-                            .or_insert(Vec::new())
-                            .push(token.position);
-                    }
-                    for (key, value) in doc_postings {
-                        let term = Term {
-                            posting: Posting {
-                                doc_id: self.doc_id,
-                                positions: value,
-                            },
-                            term: key,
-                        };
-                        tx.send(term).unwrap();
+        parsed_document: ParsedDocument,
+        txs: &[mpsc::Sender<SpimiMessage>],
+    ) -> io::Result<()> {
+        let ParsedDocument {
+            document,
+            doc_postings,
+            field_lengths,
+            signature,
+        } = parsed_document;
+        let mut duplicate_of = 0;
+        if let Some(lsh_index) = self.lsh_index.as_mut()
+            && let Some(signature) = signature
+        {
+            match lsh_index.find_duplicate(&signature) {
+                Some(original_doc_id) => {
+                    self.no_of_duplicates += 1;
+                    if self.duplicate_handling == DuplicateHandling::Skip {
+                        return Ok(());
                     }
+                    duplicate_of = original_doc_id;
                 }
-                Err(e) => {
-                    eprintln!("Error parsing object {}: {}", i + 1, e);
-                    // Optionally break or continue based on your needs
+                None => {
+                    if let Some(signature_log) = self.signature_log.as_mut() {
+                        signature_log.append(self.doc_id + 1, &signature)?;
+                    }
+                    lsh_index.insert(self.doc_id + 1, signature);
                 }
             }
         }
+        self.doc_id += 1;
+        if let Some(document_store_writer) = self.document_store_writer.as_mut() {
+            document_store_writer.add_document(self.doc_id, &document)?;
+        }
+        if let Some(link_log) = self.link_log.as_mut() {
+            link_log.append(self.doc_id, &document.links)?;
+        }
 
-        Ok(())
+        self.document_metadata.insert(
+            self.doc_id,
+            DocumentMetadata {
+                doc_external_id: document.id,
+                doc_name: document.title,
+                doc_url: document.url,
+                doc_length: field_lengths[&Field::Body],
+                title_length: field_lengths[&Field::Title],
+                url_length: field_lengths[&Field::Url],
+                anchor_length: 0,
+                duplicate_of,
+            },
+        );
+        send_postings(self.doc_id, doc_postings, txs)
     }
 
     fn scan_index_directory(&self, directory: &str) -> Result<Vec<File>, io::Error> {
@@ -165,51 +340,608 @@ impl Indexer {
         Ok(file_handles)
     }
 
+    // collects the supported files below dir_path in sorted order, the order decides the
+    // doc ids so a resumed or repeated build hands out the same ones
+    fn collect_files(&self, dir_path: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(dir_path)? {
+            paths.push(entry?.path());
+        }
+        paths.sort();
+        for path in paths {
+            if path.is_dir() {
+                // Recursively process subdirectories
+                self.collect_files(&path, files)?;
+            } else if self.document_source.is_supported_file(&path) {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    /*
+    Reading and tokenizing the files is spread over the worker threads, every
+    worker takes the next file, parses all of its documents and hands them
+    back. The parsed files are added in the order of the file list no matter
+    which worker finishes first, so the doc ids do not depend on the number
+    of workers or on timing. At most two files per worker are handed out
+    ahead of the file being added, which bounds the parsed documents waiting
+    in memory. Files a checkpoint recorded as consumed are skipped.
+     */
     fn process_directory(
         &mut self,
-        dir_path: &Path,
-        tx: &mpsc::Sender<Term>,
+        corpus_path: &Path,
+        txs: &[mpsc::Sender<SpimiMessage>],
     ) -> Result<u32, Box<dyn std::error::Error>> {
-        let mut number_of_articles: u32 = 0;
+        let mut files = Vec::new();
+        self.collect_files(corpus_path, &mut files)?;
+        files.retain(|path| !self.completed_files.contains(&path.display().to_string()));
+        if files.is_empty() {
+            return Ok(0);
+        }
+        let no_of_workers = self.no_of_workers.min(files.len());
+        let max_files_in_flight = 2 * no_of_workers;
+        let compute_signatures = self.lsh_index.is_some();
+
+        thread::scope(|scope| {
+            let (job_tx, job_rx) = mpsc::channel::<usize>();
+            let job_rx = Arc::new(Mutex::new(job_rx));
+            let (result_tx, result_rx) =
+                mpsc::channel::<(usize, io::Result<Vec<ParsedDocument>>, Duration)>();
+            for _ in 0..no_of_workers {
+                let job_rx = job_rx.clone();
+                let result_tx = result_tx.clone();
+                let document_source = self.document_source.clone();
+                let search_tokenizer = self.search_tokenizer.clone();
+                let files = &files;
+                scope.spawn(move || {
+                    loop {
+                        let job = job_rx
+                            .lock()
+                            .unwrap_or_else(|poisoned| poisoned.into_inner())
+                            .recv();
+                        let Ok(file_index) = job else {
+                            break;
+                        };
+                        let started = Instant::now();
+                        let result =
+                            parse_file(&*document_source, &search_tokenizer, &files[file_index], compute_signatures);
+                        if result_tx
+                            .send((file_index, result, started.elapsed()))
+                            .is_err()
+                        {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(result_tx);
+
+            let mut next_file_to_dispatch = 0;
+            while next_file_to_dispatch < files.len().min(max_files_in_flight) {
+                job_tx.send(next_file_to_dispatch)?;
+                next_file_to_dispatch += 1;
+            }
+            let mut parsed_files = HashMap::new();
+            let mut number_of_articles: u32 = 0;
+            for (file_index, path) in files.iter().enumerate() {
+                let parsed_documents = loop {
+                    if let Some(parsed_documents) = parsed_files.remove(&file_index) {
+                        break parsed_documents;
+                    }
+                    let (parsed_file_index, parsed_documents, parse_time) = result_rx
+                        .recv()
+                        .map_err(|_| io::Error::other("indexing workers stopped"))?;
+                    self.parse_time += parse_time;
+                    parsed_files.insert(parsed_file_index, parsed_documents);
+                };
+                if next_file_to_dispatch < files.len() {
+                    job_tx.send(next_file_to_dispatch)?;
+                    next_file_to_dispatch += 1;
+                }
 
-        for entry in std::fs::read_dir(dir_path)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_dir() {
-                // Recursively process subdirectories
-                number_of_articles += self.process_directory(&path, &tx)?;
-            } else if path.extension().and_then(|s| s.to_str()) == Some("bz2") {
                 println!("Processing: {:?}", path);
-                self.read_bz2_file(&path, tx)?;
+                for parsed_document in parsed_documents? {
+                    self.add_parsed_document(parsed_document, txs)?;
+                    number_of_articles += 1;
+                }
+                self.completed_files.insert(path.display().to_string());
+                if self.doc_id - self.last_checkpoint_doc_id >= self.checkpoint_interval {
+                    self.write_checkpoint(corpus_path, txs)?;
+                }
             }
+            Ok(number_of_articles)
+        })
+    }
+
+    // has every spimi worker flush what it holds into a run file, syncs the documents
+    // indexed since the last checkpoint and then records the checkpoint
+    fn write_checkpoint(
+        &mut self,
+        corpus_path: &Path,
+        txs: &[mpsc::Sender<SpimiMessage>],
+    ) -> io::Result<()> {
+        let (ack_tx, ack_rx) = mpsc::channel();
+        for tx in txs {
+            tx.send(SpimiMessage::Checkpoint(ack_tx.clone()))
+                .map_err(|_| io::Error::other("spimi worker stopped"))?;
         }
+        let mut no_of_runs = 0;
+        for _ in txs {
+            let worker_no_of_runs = ack_rx
+                .recv()
+                .map_err(|_| io::Error::other("spimi worker stopped"))??;
+            no_of_runs = no_of_runs.max(worker_no_of_runs);
+        }
+
+        let (
+            Some(document_metadata_log),
+            Some(link_log),
+            Some(signature_log),
+            Some(document_store_writer),
+        ) = (
+            self.document_metadata_log.as_mut(),
+            self.link_log.as_mut(),
+            self.signature_log.as_mut(),
+            self.document_store_writer.as_mut(),
+        ) else {
+            return Ok(());
+        };
+        for doc_id in self.last_checkpoint_doc_id + 1..=self.doc_id {
+            document_metadata_log.append(doc_id, &self.document_metadata[&doc_id])?;
+        }
+        let mut completed_files: Vec<String> = self.completed_files.iter().cloned().collect();
+        completed_files.sort();
+        let checkpoint = IndexCheckpoint {
+            corpus_path: corpus_path.display().to_string(),
+            no_of_docs: self.doc_id,
+            no_of_runs,
+            completed_files,
+            document_metadata_log_length: document_metadata_log.sync()?,
+            link_log_length: link_log.sync()?,
+            signature_log_length: signature_log.sync()?,
+            no_of_duplicates: self.no_of_duplicates,
+            document_store: document_store_writer.checkpoint()?,
+        };
+        checkpoint.write_to_disk(&PathBuf::from(&self.index_directory_path).join(CHECKPOINT_FILE_NAME))?;
+        self.last_checkpoint_doc_id = self.doc_id;
+        Ok(())
+    }
+
+    // picks up the state of an interrupted build of the same corpus from its checkpoint,
+    // or starts from scratch
+    fn start_or_resume(&mut self, corpus_path: &Path, spmi: &mut Spmi) -> io::Result<()> {
+        let index_directory = PathBuf::from(&self.index_directory_path);
+        let checkpoint_path = index_directory.join(CHECKPOINT_FILE_NAME);
+        let document_store_path = index_directory.join(DOCUMENT_STORE_FILE_NAME);
+        let document_metadata_log_path = index_directory.join(DOCUMENT_METADATA_LOG_FILE_NAME);
+        let link_log_path = index_directory.join(LINK_LOG_FILE_NAME);
+        let signature_log_path = index_directory.join(SIGNATURE_LOG_FILE_NAME);
+        self.lsh_index = match self.duplicate_handling {
+            DuplicateHandling::Keep => None,
+            _ => Some(LshIndex::new(self.similarity_threshold)),
+        };
+
+        if let Some(checkpoint) = IndexCheckpoint::read_from_disk(&checkpoint_path)?
+            && checkpoint.corpus_path == corpus_path.display().to_string()
+        {
+            println!("Resuming after document {}", checkpoint.no_of_docs);
+            spmi.resume_run_directory(checkpoint.no_of_runs)?;
+            let (document_metadata_log, document_metadata) = DocumentMetadataLog::resume(
+                &document_metadata_log_path,
+                checkpoint.document_metadata_log_length,
+            )?;
+            self.document_store_writer = Some(DocumentStoreWriter::resume(
+                &document_store_path,
+                &checkpoint.document_store,
+            )?);
+            self.document_metadata_log = Some(document_metadata_log);
+            self.link_log = Some(LinkLog::resume(&link_log_path, checkpoint.link_log_length)?);
+            let (signature_log, signatures) =
+                SignatureLog::resume(&signature_log_path, checkpoint.signature_log_length)?;
+            self.signature_log = Some(signature_log);
+            if let Some(lsh_index) = self.lsh_index.as_mut() {
+                for (doc_id, signature) in signatures {
+                    lsh_index.insert(doc_id, signature);
+                }
+            }
+            self.no_of_duplicates = checkpoint.no_of_duplicates;
+            self.document_metadata = document_metadata;
+            self.doc_id = checkpoint.no_of_docs;
+            self.last_checkpoint_doc_id = checkpoint.no_of_docs;
+            self.completed_files = checkpoint.completed_files.into_iter().collect();
+            return Ok(());
+        }
+
+        if checkpoint_path.exists() {
+            fs::remove_file(&checkpoint_path)?;
+        }
+        spmi.prepare_run_directory()?;
+        self.document_store_writer = Some(DocumentStoreWriter::create(&document_store_path)?);
+        self.document_metadata_log = Some(DocumentMetadataLog::create(&document_metadata_log_path)?);
+        self.link_log = Some(LinkLog::create(&link_log_path)?);
+        self.signature_log = Some(SignatureLog::create(&signature_log_path)?);
+        self.no_of_duplicates = 0;
+        self.document_metadata.clear();
+        self.doc_id = 0;
+        self.last_checkpoint_doc_id = 0;
+        self.completed_files.clear();
+        Ok(())
+    }
 
-        Ok(number_of_articles)
+    /*
+    The links point at titles, once every document is parsed they are
+    resolved to doc ids. The anchor text of the links is handed to the spimi
+    workers as the anchor field of the documents linked to. No document is
+    added after this, the anchor postings are sent in doc id order so the
+    postings of every anchor term stay sorted. The link graph is returned to
+    be written once the final doc ids are known.
+     */
+    fn index_links(
+        &mut self,
+        index_directory: &Path,
+        txs: &[mpsc::Sender<SpimiMessage>],
+    ) -> io::Result<LinkGraph> {
+        let links = match self.link_log.take() {
+            Some(link_log) => link_log.finish(&index_directory.join(LINK_LOG_FILE_NAME))?,
+            None => Vec::new(),
+        };
+        let title_index = TitleIndex::new(
+            self.document_metadata
+                .iter()
+                .map(|(doc_id, metadata)| (*doc_id, metadata.doc_name.as_str())),
+        );
+        let link_graph = LinkGraph::resolve(self.doc_id, &title_index, &links);
+
+        let mut anchor_texts: BTreeMap<u32, Vec<String>> = BTreeMap::new();
+        for (doc_id, links) in links {
+            for link in links {
+                if let Some(target_doc_id) = title_index.find(&link.target)
+                    && target_doc_id != doc_id
+                {
+                    anchor_texts.entry(target_doc_id).or_default().push(link.anchor_text);
+                }
+            }
+        }
+        for (doc_id, anchor_texts) in anchor_texts {
+            let mut doc_postings: HashMap<String, Vec<u32>> = HashMap::new();
+            let mut anchor_length = 0;
+            let mut first_position = 0;
+            for anchor_text in anchor_texts {
+                let tokens = self.search_tokenizer.tokenize(anchor_text);
+                for token in &tokens {
+                    doc_postings
+                        .entry(Field::Anchor.term(&token.word))
+                        .or_default()
+                        .push(first_position + token.position);
+                }
+                anchor_length += tokens.len() as u32;
+                // the gap keeps a phrase from matching across the text of two links
+                if let Some(last_token) = tokens.last() {
+                    first_position += last_token.position + 2;
+                }
+            }
+            if let Some(metadata) = self.document_metadata.get_mut(&doc_id) {
+                metadata.anchor_length = anchor_length;
+            }
+            send_postings(doc_id, doc_postings, txs)?;
+        }
+        Ok(link_graph)
     }
 
     pub fn set_index_directory(&mut self, index_directory_path: String) {
         self.index_directory_path = index_directory_path;
     }
-    pub fn index(&mut self) -> io::Result<()> {
-        let mut spmi = Spmi::new();
-        let (tx, rx) = mpsc::channel::<Term>();
-
-        let handle = std::thread::spawn(move || {
-            let _ = spmi.single_pass_in_memory_indexing(rx); // Use the moved variable
-        });
+    // reads every supported file below corpus_path and writes the run files and the merged
+    // index into the index directory. Checkpoints are written along the way, calling index
+    // again for the same corpus after it failed continues from the last one. The returned
+    // statistics are also written into the index directory
+    pub fn index(&mut self, corpus_path: &Path) -> io::Result<BuildStatistics> {
+        let started = Instant::now();
+        if !corpus_path.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("corpus directory {} does not exist", corpus_path.display()),
+            ));
+        }
+        let index_directory = PathBuf::from(&self.index_directory_path);
+        let mut spmi = Spmi::new(&index_directory);
+        spmi.set_merge_fan_in(self.merge_fan_in);
+        spmi.set_posting_codec(self.posting_codec);
+        self.start_or_resume(corpus_path, &mut spmi)?;
+        self.parse_time = Duration::ZERO;
+        // the budget is shared by the spimi workers, each holds its part in its dictionary
+        let no_of_spimi_workers = self.no_of_spimi_workers;
+        spmi.set_memory_budget(self.memory_budget / no_of_spimi_workers);
+        let mut txs = Vec::with_capacity(no_of_spimi_workers);
+        let mut handles = Vec::with_capacity(no_of_spimi_workers);
+        for _ in 0..no_of_spimi_workers {
+            let (tx, rx) = mpsc::channel::<SpimiMessage>();
+            let mut worker = spmi.new_worker();
+            handles.push(thread::spawn(move || -> io::Result<Duration> {
+                worker.single_pass_in_memory_indexing(rx)?;
+                Ok(worker.get_invert_time())
+            }));
+            txs.push(tx);
+        }
 
-        let wiki_dir = Path::new("enwiki-20171001-pages-meta-current-withlinks-processed");
-        let _ = self.process_directory(wiki_dir, &tx);
-        drop(tx);
-        handle.join().unwrap();
+        let process_result = self
+            .process_directory(corpus_path, &txs)
+            .and_then(|_| Ok(self.index_links(&index_directory, &txs)?));
+        drop(txs);
+        let mut invert_time = Duration::ZERO;
+        for handle in handles {
+            invert_time += handle
+                .join()
+                .map_err(|_| io::Error::other("spimi thread panicked"))??;
+        }
+        let document_store_writer = self.document_store_writer.take();
+        self.document_metadata_log = None;
+        self.link_log = None;
+        self.signature_log = None;
+        self.lsh_index = None;
+        let mut link_graph = process_result.map_err(|e| io::Error::other(e.to_string()))?;
+        if let Some(document_store_writer) = document_store_writer {
+            document_store_writer.finish()?;
+        }
 
-        spmi = Spmi::new();
-        let result = spmi.merge_index_files(64).unwrap();
+        let new_doc_ids = reassign_doc_ids(self.doc_id_order, &self.document_metadata);
+        spmi.set_doc_id_map(new_doc_ids.clone());
+        let no_of_runs = spmi.get_no_of_runs();
+        let merge_started = Instant::now();
+        let result = spmi.merge_index_files(64)?;
+        let merge_time = merge_started.elapsed();
         self.index_metadata = result;
-        Ok(())
+
+        // the final index is written under the new doc ids, everything else keyed by doc id
+        // follows it. The stored documents are rewritten next to the store and only replace
+        // it once the checkpoint is gone, a build resumed before that still finds the
+        // documents under the doc ids of its logs
+        let reordered_document_store_path = index_directory
+            .join(DOCUMENT_STORE_FILE_NAME)
+            .with_extension("reordered");
+        if let Some(new_doc_ids) = &new_doc_ids {
+            self.document_metadata = self
+                .document_metadata
+                .drain()
+                .map(|(doc_id, mut metadata)| {
+                    if metadata.duplicate_of != 0 {
+                        metadata.duplicate_of = new_doc_ids[metadata.duplicate_of as usize];
+                    }
+                    (new_doc_ids[doc_id as usize], metadata)
+                })
+                .collect();
+            link_graph = link_graph.remap(new_doc_ids)?;
+            DocumentStoreReader::open(&index_directory.join(DOCUMENT_STORE_FILE_NAME))?
+                .write_reordered(&reordered_document_store_path, new_doc_ids)?;
+        }
+        link_graph.write_to_disk(&index_directory.join(LINK_GRAPH_FILE_NAME))?;
+        write_page_rank(
+            &index_directory.join(PAGE_RANK_FILE_NAME),
+            &compute_page_rank(&link_graph),
+        )?;
+
+        // the lexicon and the document metadata are what a later process needs to query
+        // final.idx without rebuilding it
+        self.index_metadata
+            .write_to_disk(&index_directory.join(LEXICON_FILE_NAME))?;
+        write_document_metadata(
+            &index_directory.join(DOCUMENT_METADATA_FILE_NAME),
+            &self.document_metadata,
+        )?;
+
+        // the build is complete, there is nothing left to resume
+        self.completed_files.clear();
+        fs::remove_file(index_directory.join(DOCUMENT_METADATA_LOG_FILE_NAME))?;
+        fs::remove_file(index_directory.join(LINK_LOG_FILE_NAME))?;
+        fs::remove_file(index_directory.join(SIGNATURE_LOG_FILE_NAME))?;
+        let checkpoint_path = index_directory.join(CHECKPOINT_FILE_NAME);
+        if checkpoint_path.exists() {
+            fs::remove_file(&checkpoint_path)?;
+        }
+        if new_doc_ids.is_some() {
+            fs::rename(
+                &reordered_document_store_path,
+                index_directory.join(DOCUMENT_STORE_FILE_NAME),
+            )?;
+        }
+
+        let merge_statistics = spmi.get_merge_statistics();
+        let index_size_in_bytes = fs::metadata(index_directory.join(FINAL_INDEX_FILE_NAME))?.len();
+        let index_size_in_bytes_before_reordering = merge_statistics
+            .index_size_in_bytes_before_reordering
+            .unwrap_or(index_size_in_bytes);
+        if new_doc_ids.is_some() {
+            println!(
+                "final.idx in {:?} order takes {} bytes instead of {}, its doc id stream an estimated {} bytes instead of {}",
+                self.doc_id_order,
+                index_size_in_bytes,
+                index_size_in_bytes_before_reordering,
+                merge_statistics.doc_id_stream_bytes,
+                merge_statistics.doc_id_stream_bytes_before_reordering
+            );
+        }
+        let statistics = BuildStatistics {
+            no_of_documents: self.doc_id,
+            no_of_distinct_terms: merge_statistics.no_of_terms,
+            no_of_postings: merge_statistics.no_of_postings,
+            no_of_positions: merge_statistics.no_of_positions,
+            no_of_runs,
+            no_of_blocks: merge_statistics.no_of_blocks,
+            no_of_links: link_graph.get_no_of_links(),
+            no_of_duplicates: self.no_of_duplicates,
+            doc_id_order: self.doc_id_order,
+            posting_codec: self.posting_codec,
+            doc_id_stream_bytes: merge_statistics.doc_id_stream_bytes,
+            doc_id_stream_bytes_before_reordering: merge_statistics.doc_id_stream_bytes_before_reordering,
+            index_size_in_bytes,
+            index_size_in_bytes_before_reordering,
+            bytes_per_posting: if merge_statistics.no_of_postings > 0 {
+                index_size_in_bytes as f64 / merge_statistics.no_of_postings as f64
+            } else {
+                0.0
+            },
+            parse_seconds: self.parse_time.as_secs_f64(),
+            invert_seconds: invert_time.as_secs_f64(),
+            merge_seconds: merge_time.as_secs_f64(),
+            total_seconds: started.elapsed().as_secs_f64(),
+        };
+        statistics.write_to_disk(&index_directory.join(BUILD_STATISTICS_FILE_NAME))?;
+        Ok(statistics)
     }
 
     pub fn get_term_metadata(&self, term: &str) -> &MapInMemoryDictPointer {
         self.index_metadata.get_term_metadata(term)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        document_source::plain_text_source::PlainTextSource,
+    };
+    use tempfile::TempDir;
+
+    fn build_with_workers(
+        corpus_directory: &Path,
+        no_of_workers: usize,
+        no_of_spimi_workers: usize,
+    ) -> (Indexer, TempDir) {
+        let index_directory = TempDir::new().unwrap();
+        let mut indexer =
+            Indexer::new(SearchTokenizer::new().unwrap(), Box::new(PlainTextSource::new()))
+                .unwrap();
+        indexer.set_index_directory(index_directory.path().display().to_string());
+        indexer.set_no_of_workers(no_of_workers);
+        indexer.set_no_of_spimi_workers(no_of_spimi_workers);
+        indexer.index(corpus_directory).unwrap();
+        (indexer, index_directory)
+    }
+
+    fn create_test_corpus() -> TempDir {
+        let corpus_directory = TempDir::new().unwrap();
+        fs::create_dir(corpus_directory.path().join("nested")).unwrap();
+        for i in 0..20 {
+            let directory = if i % 3 == 0 {
+                corpus_directory.path().join("nested")
+            } else {
+                corpus_directory.path().to_path_buf()
+            };
+            let body = format!("document number {} ", i).repeat(i + 1);
+            fs::write(directory.join(format!("doc_{:02}.txt", i)), body).unwrap();
+        }
+        corpus_directory
+    }
+
+    fn assert_same_index_files(first_directory: &TempDir, second_directory: &TempDir) {
+        for file_name in [FINAL_INDEX_FILE_NAME, DOCUMENT_STORE_FILE_NAME] {
+            assert_eq!(
+                fs::read(first_directory.path().join(file_name)).unwrap(),
+                fs::read(second_directory.path().join(file_name)).unwrap(),
+                "{} differs",
+                file_name
+            );
+        }
+    }
+
+    #[test]
+    fn test_doc_ids_do_not_depend_on_number_of_workers() {
+        let corpus_directory = create_test_corpus();
+        let (single_indexer, single_directory) = build_with_workers(corpus_directory.path(), 1, 1);
+        let (parallel_indexer, parallel_directory) =
+            build_with_workers(corpus_directory.path(), 4, 1);
+        assert_eq!(single_indexer.get_no_of_docs(), 20);
+        assert_eq!(parallel_indexer.get_no_of_docs(), 20);
+        assert_eq!(single_indexer.document_metadata, parallel_indexer.document_metadata);
+        // files are added in sorted path order, nested/ sorts after the doc_ files
+        assert_eq!(single_indexer.document_metadata[&1].doc_name, "doc_01");
+        assert_eq!(single_indexer.document_metadata[&14].doc_name, "doc_00");
+        assert_same_index_files(&single_directory, &parallel_directory);
+    }
+
+    #[test]
+    fn test_spimi_workers_produce_the_same_index() {
+        let corpus_directory = create_test_corpus();
+        let (_, single_directory) = build_with_workers(corpus_directory.path(), 2, 1);
+        let (parallel_indexer, parallel_directory) =
+            build_with_workers(corpus_directory.path(), 2, 3);
+        assert_eq!(parallel_indexer.get_no_of_docs(), 20);
+        assert_same_index_files(&single_directory, &parallel_directory);
+        assert_eq!(
+            parallel_indexer.get_term_metadata("document").term_frequency,
+            20
+        );
+    }
+
+    fn build_with_duplicate_handling(
+        corpus_directory: &Path,
+        duplicate_handling: DuplicateHandling,
+    ) -> (Indexer, BuildStatistics, TempDir) {
+        let index_directory = TempDir::new().unwrap();
+        let mut indexer =
+            Indexer::new(SearchTokenizer::new().unwrap(), Box::new(PlainTextSource::new()))
+                .unwrap();
+        indexer.set_index_directory(index_directory.path().display().to_string());
+        indexer.set_no_of_workers(3);
+        indexer.set_duplicate_handling(duplicate_handling);
+        let statistics = indexer.index(corpus_directory).unwrap();
+        (indexer, statistics, index_directory)
+    }
+
+    #[test]
+    fn test_near_duplicates_are_skipped_or_marked() {
+        let text: String = (0..100).map(|i| format!("word{} ", i)).collect();
+        let corpus_directory = TempDir::new().unwrap();
+        for (name, body) in [
+            ("a", text.clone()),
+            ("b", (100..200).map(|i| format!("word{} ", i)).collect()),
+            ("c", text.replace("word50 ", "changed ")),
+            ("d", text.clone()),
+            ("e", String::new()),
+            ("f", String::new()),
+        ] {
+            fs::write(corpus_directory.path().join(format!("{}.txt", name)), body).unwrap();
+        }
+
+        let (indexer, statistics, _) =
+            build_with_duplicate_handling(corpus_directory.path(), DuplicateHandling::Keep);
+        assert_eq!(indexer.get_no_of_docs(), 6);
+        assert_eq!(statistics.no_of_duplicates, 0);
+
+        let (indexer, statistics, index_directory) =
+            build_with_duplicate_handling(corpus_directory.path(), DuplicateHandling::Skip);
+        assert_eq!(indexer.get_no_of_docs(), 4);
+        assert_eq!(statistics.no_of_duplicates, 2);
+        let titles: Vec<&str> = (1..=4)
+            .map(|doc_id| indexer.document_metadata[&doc_id].doc_name.as_str())
+            .collect();
+        assert_eq!(titles, vec!["a", "b", "e", "f"]);
+        assert!(!index_directory.path().join(SIGNATURE_LOG_FILE_NAME).exists());
+
+        let (indexer, statistics, _) =
+            build_with_duplicate_handling(corpus_directory.path(), DuplicateHandling::Mark);
+        assert_eq!(indexer.get_no_of_docs(), 6);
+        assert_eq!(statistics.no_of_duplicates, 2);
+        let duplicate_of: Vec<u32> = (1..=6)
+            .map(|doc_id| indexer.document_metadata[&doc_id].duplicate_of)
+            .collect();
+        assert_eq!(duplicate_of, vec![0, 0, 1, 1, 0, 0]);
+    }
+
+    #[test]
+    fn test_error_in_worker_fails_the_build() {
+        let corpus_directory = TempDir::new().unwrap();
+        fs::write(corpus_directory.path().join("a.txt"), "apple").unwrap();
+        // invalid utf-8 cannot be read as a plain text document
+        fs::write(corpus_directory.path().join("b.txt"), [0xff, 0xfe, 0xfd]).unwrap();
+
+        let index_directory = TempDir::new().unwrap();
+        let mut indexer =
+            Indexer::new(SearchTokenizer::new().unwrap(), Box::new(PlainTextSource::new()))
+                .unwrap();
+        indexer.set_index_directory(index_directory.path().display().to_string());
+        indexer.set_no_of_workers(2);
+        assert!(indexer.index(corpus_directory.path()).is_err());
+    }
+}
@@ -0,0 +1,388 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Cursor, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::{
+    document_source::Link,
+    indexer::helper::{read_string, read_u32, write_string, write_u32},
+};
+
+pub const LINK_GRAPH_FILE_NAME: &str = "link_graph.dat";
+pub const LINK_LOG_FILE_NAME: &str = "links.log";
+
+// titles are compared the way Wikipedia compares them, underscores and spaces are the same
+// and the first letter is case insensitive. A link to a section of an article links to the
+// article
+pub fn normalize_title(title: &str) -> String {
+    let title = title.split('#').next().unwrap_or_default();
+    let title = title.replace('_', " ");
+    let title = title.trim();
+    let mut chars = title.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+// finds the document a link points to by its title. With several documents of the same
+// title the one with the lowest doc id is linked to
+pub struct TitleIndex {
+    doc_ids_by_title: HashMap<String, u32>,
+}
+
+impl TitleIndex {
+    pub fn new<'a>(titles: impl Iterator<Item = (u32, &'a str)>) -> Self {
+        let mut doc_ids_by_title: HashMap<String, u32> = HashMap::new();
+        for (doc_id, title) in titles {
+            doc_ids_by_title
+                .entry(normalize_title(title))
+                .and_modify(|first_doc_id| *first_doc_id = (*first_doc_id).min(doc_id))
+                .or_insert(doc_id);
+        }
+        Self { doc_ids_by_title }
+    }
+
+    pub fn find(&self, title: &str) -> Option<u32> {
+        self.doc_ids_by_title.get(&normalize_title(title)).copied()
+    }
+}
+
+/*
+The links between the documents of a segment, by doc id.
+
+File Layout->
+no_of_docs
+no_of_links target_doc_id target_doc_id ... (one line per doc id, from 1 to no_of_docs)
+
+Links to titles that are not documents of the segment, links of a document
+to itself and repeated links to the same document are dropped.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkGraph {
+    // outgoing links of every doc id, index 0 is unused
+    links: Vec<Vec<u32>>,
+}
+
+impl LinkGraph {
+    pub fn new(no_of_docs: u32) -> Self {
+        Self {
+            links: vec![Vec::new(); no_of_docs as usize + 1],
+        }
+    }
+
+    // resolves the link targets, which are titles, to the doc ids of the documents with
+    // those titles
+    pub fn resolve(no_of_docs: u32, title_index: &TitleIndex, links: &[(u32, Vec<Link>)]) -> Self {
+        let mut link_graph = Self::new(no_of_docs);
+        for (doc_id, links) in links {
+            for link in links {
+                if let Some(target_doc_id) = title_index.find(&link.target) {
+                    link_graph.add_link(*doc_id, target_doc_id);
+                }
+            }
+        }
+        link_graph
+    }
+
+    pub fn get_no_of_docs(&self) -> u32 {
+        (self.links.len() - 1) as u32
+    }
+
+    pub fn get_no_of_links(&self) -> u64 {
+        self.links.iter().map(|links| links.len() as u64).sum()
+    }
+
+    pub fn add_link(&mut self, doc_id: u32, target_doc_id: u32) {
+        if doc_id == target_doc_id || target_doc_id as usize >= self.links.len() {
+            return;
+        }
+        let links = &mut self.links[doc_id as usize];
+        if !links.contains(&target_doc_id) {
+            links.push(target_doc_id);
+        }
+    }
+
+    pub fn get_links(&self, doc_id: u32) -> &[u32] {
+        self.links
+            .get(doc_id as usize)
+            .map(|links| links.as_slice())
+            .unwrap_or_default()
+    }
+
+    // the graph under new doc ids, new_doc_ids holds the new doc id of every old one. A
+    // doc id the map does not cover or maps outside the segment is refused
+    pub fn remap(&self, new_doc_ids: &[u32]) -> io::Result<Self> {
+        let no_of_docs = self.get_no_of_docs();
+        let new_doc_id = |doc_id: u32| -> io::Result<u32> {
+            match new_doc_ids.get(doc_id as usize) {
+                Some(new_doc_id) if (1..=no_of_docs).contains(new_doc_id) => Ok(*new_doc_id),
+                _ => Err(invalid_data(format!(
+                    "doc id {} has no new doc id in a segment of {} documents",
+                    doc_id, no_of_docs
+                ))),
+            }
+        };
+        let mut link_graph = Self::new(no_of_docs);
+        for (doc_id, links) in self.links.iter().enumerate().skip(1) {
+            link_graph.links[new_doc_id(doc_id as u32)? as usize] = links
+                .iter()
+                .map(|target_doc_id| new_doc_id(*target_doc_id))
+                .collect::<io::Result<_>>()?;
+        }
+        Ok(link_graph)
+    }
+
+    pub fn write_to_disk(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        write_u32(&mut writer, self.get_no_of_docs())?;
+        for links in &self.links[1..] {
+            write_u32(&mut writer, links.len() as u32)?;
+            for target_doc_id in links {
+                write_u32(&mut writer, *target_doc_id)?;
+            }
+        }
+        writer.flush()
+    }
+
+    // a segment written before links were extracted has no link graph. The counts are
+    // checked against the length of the file before anything is allocated for them
+    pub fn read_from_disk(path: &Path) -> io::Result<Option<Self>> {
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let mut reader = BufReader::new(File::open(path)?);
+        let file_length = reader.get_ref().metadata()?.len();
+        // every value of the file is a u32, a count can not be larger than the values after it
+        let check_count = |count: u32, values_read: u64, what: &str| -> io::Result<u32> {
+            if count as u64 > (file_length / 4).saturating_sub(values_read) {
+                return Err(invalid_data(format!(
+                    "{} {} in a link graph of {} bytes",
+                    count, what, file_length
+                )));
+            }
+            Ok(count)
+        };
+        let no_of_docs = check_count(read_u32(&mut reader)?, 1, "documents")?;
+        let mut values_read = 1;
+        let mut link_graph = Self::new(no_of_docs);
+        for doc_id in 1..=no_of_docs {
+            values_read += 1;
+            let no_of_links = check_count(read_u32(&mut reader)?, values_read, "links")?;
+            let mut links = Vec::with_capacity(no_of_links as usize);
+            for _ in 0..no_of_links {
+                let target_doc_id = read_u32(&mut reader)?;
+                if target_doc_id == 0 || target_doc_id > no_of_docs {
+                    return Err(invalid_data(format!(
+                        "doc id {} links to doc id {} in a segment of {} documents",
+                        doc_id, target_doc_id, no_of_docs
+                    )));
+                }
+                links.push(target_doc_id);
+            }
+            values_read += no_of_links as u64;
+            link_graph.links[doc_id as usize] = links;
+        }
+        Ok(Some(link_graph))
+    }
+}
+
+fn invalid_data(reason: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+// the links of the documents of a running build, they can only be resolved to doc ids once
+// every title is known. Appended to like the document metadata log, a checkpoint
+// records its synced length
+pub struct LinkLog {
+    writer: BufWriter<File>,
+    length: u64,
+}
+
+impl LinkLog {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            length: 0,
+        })
+    }
+
+    // cuts the log back to the length recorded by a checkpoint
+    pub fn resume(path: &Path, length: u64) -> io::Result<Self> {
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        file.set_len(length)?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+            length,
+        })
+    }
+
+    pub fn append(&mut self, doc_id: u32, links: &[Link]) -> io::Result<()> {
+        if links.is_empty() {
+            return Ok(());
+        }
+        let mut record = Vec::new();
+        write_u32(&mut record, doc_id)?;
+        write_u32(&mut record, links.len() as u32)?;
+        for link in links {
+            write_string(&mut record, &link.target)?;
+            write_string(&mut record, &link.anchor_text)?;
+        }
+        self.writer.write_all(&record)?;
+        self.length += record.len() as u64;
+        Ok(())
+    }
+
+    // syncs the appended records and returns the length to record in the checkpoint
+    pub fn sync(&mut self) -> io::Result<u64> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_all()?;
+        Ok(self.length)
+    }
+
+    // returns the links of every document that has links, in doc id order
+    pub fn finish(mut self, path: &Path) -> io::Result<Vec<(u32, Vec<Link>)>> {
+        self.writer.flush()?;
+        drop(self);
+        let mut cursor = Cursor::new(fs::read(path)?);
+        let length = cursor.get_ref().len() as u64;
+        let mut links = Vec::new();
+        while cursor.position() < length {
+            let doc_id = read_u32(&mut cursor)?;
+            let no_of_links = read_u32(&mut cursor)?;
+            let mut document_links = Vec::with_capacity(no_of_links as usize);
+            for _ in 0..no_of_links {
+                document_links.push(Link {
+                    target: read_string(&mut cursor)?,
+                    anchor_text: read_string(&mut cursor)?,
+                });
+            }
+            links.push((doc_id, document_links));
+        }
+        Ok(links)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn link(target: &str) -> Link {
+        Link {
+            target: target.to_string(),
+            anchor_text: format!("about {}", target),
+        }
+    }
+
+    #[test]
+    fn test_normalize_title() {
+        assert_eq!(normalize_title("rust_(programming_language)"), "Rust (programming language)");
+        assert_eq!(normalize_title("Rust#History"), "Rust");
+        assert_eq!(normalize_title(" ängström "), "Ängström");
+        assert_eq!(normalize_title("#Section"), "");
+    }
+
+    #[test]
+    fn test_resolve_links_by_title() {
+        let title_index = TitleIndex::new(
+            vec![(4, "Rust"), (2, "Cargo"), (3, "Crate"), (1, "Rust")].into_iter(),
+        );
+        assert_eq!(title_index.find("rust#Syntax"), Some(1));
+        assert_eq!(title_index.find("Missing"), None);
+        let links = vec![
+            (1, vec![link("cargo"), link("Crate"), link("Missing")]),
+            // links to itself and repeated links are dropped
+            (2, vec![link("Cargo"), link("Rust"), link("rust")]),
+        ];
+        let link_graph = LinkGraph::resolve(4, &title_index, &links);
+        assert_eq!(link_graph.get_links(1), &[2, 3]);
+        assert_eq!(link_graph.get_links(2), &[1]);
+        assert!(link_graph.get_links(3).is_empty());
+        assert!(link_graph.get_links(9).is_empty());
+        assert_eq!(link_graph.get_no_of_links(), 3);
+    }
+
+    #[test]
+    fn test_remap_link_graph() {
+        let mut link_graph = LinkGraph::new(3);
+        link_graph.add_link(1, 3);
+        link_graph.add_link(3, 1);
+        link_graph.add_link(3, 2);
+        let link_graph = link_graph.remap(&[0, 2, 3, 1]).unwrap();
+        assert_eq!(link_graph.get_links(2), &[1]);
+        assert_eq!(link_graph.get_links(1), &[2, 3]);
+        assert!(link_graph.get_links(3).is_empty());
+    }
+
+    #[test]
+    fn test_link_graph_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(LINK_GRAPH_FILE_NAME);
+        assert_eq!(LinkGraph::read_from_disk(&path).unwrap(), None);
+
+        let mut link_graph = LinkGraph::new(3);
+        link_graph.add_link(1, 3);
+        link_graph.add_link(3, 1);
+        link_graph.add_link(3, 2);
+        link_graph.write_to_disk(&path).unwrap();
+        assert_eq!(LinkGraph::read_from_disk(&path).unwrap(), Some(link_graph));
+    }
+
+    #[test]
+    fn test_remap_refuses_doc_ids_outside_the_map() {
+        let mut link_graph = LinkGraph::new(3);
+        link_graph.add_link(1, 3);
+        assert!(link_graph.remap(&[0, 2, 1]).is_err());
+        assert!(link_graph.remap(&[0, 2, 4, 1]).is_err());
+    }
+
+    #[test]
+    fn test_corrupt_link_graph_is_invalid_data() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(LINK_GRAPH_FILE_NAME);
+        let write_values = |values: &[u32]| {
+            let bytes: Vec<u8> = values.iter().flat_map(|value| value.to_le_bytes()).collect();
+            fs::write(&path, bytes).unwrap();
+        };
+        let error_kind = || LinkGraph::read_from_disk(&path).unwrap_err().kind();
+
+        // a link count that would need gigabytes is refused before allocating it
+        write_values(&[1, u32::MAX]);
+        assert_eq!(error_kind(), io::ErrorKind::InvalidData);
+        write_values(&[u32::MAX, 0]);
+        assert_eq!(error_kind(), io::ErrorKind::InvalidData);
+        // a link to a doc id past the segment
+        write_values(&[2, 1, 7, 0]);
+        assert_eq!(error_kind(), io::ErrorKind::InvalidData);
+        write_values(&[2, 1, 2, 0]);
+        assert_eq!(LinkGraph::read_from_disk(&path).unwrap().unwrap().get_links(1), &[2]);
+    }
+
+    #[test]
+    fn test_link_log_resume_drops_unsynced_links() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(LINK_LOG_FILE_NAME);
+
+        let mut link_log = LinkLog::create(&path).unwrap();
+        link_log.append(1, &[link("Cargo")]).unwrap();
+        link_log.append(2, &[]).unwrap();
+        let length = link_log.sync().unwrap();
+        link_log.append(3, &[link("Lost")]).unwrap();
+        link_log.sync().unwrap();
+        drop(link_log);
+
+        let mut link_log = LinkLog::resume(&path, length).unwrap();
+        link_log.append(3, &[link("Rust"), link("Crate")]).unwrap();
+        assert_eq!(
+            link_log.finish(&path).unwrap(),
+            vec![
+                (1, vec![link("Cargo")]),
+                (3, vec![link("Rust"), link("Crate")]),
+            ]
+        );
+    }
+}
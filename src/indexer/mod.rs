@@ -5,4 +5,15 @@ pub mod index_merge_iterator;
 pub mod index_merge_writer;
 pub mod index_metadata;
 pub mod chunk;
-pub mod block;
\ No newline at end of file
+pub mod block;
+pub mod index_file;
+pub mod checksum;
+pub mod document_metadata;
+pub mod document_store;
+pub mod field;
+pub mod run_file_writer;
+pub mod checkpoint;
+pub mod build_statistics;
+pub mod link_graph;
+pub mod near_duplicate;
+pub mod doc_id_order;
@@ -0,0 +1,305 @@
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Cursor, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::{
+    indexer::helper::{read_u32, write_u32},
+    query_parser::tokenizer::Token,
+};
+
+pub const SIGNATURE_LOG_FILE_NAME: &str = "signatures.log";
+pub const DEFAULT_SIMILARITY_THRESHOLD: f64 = 0.8;
+
+// number of consecutive words hashed together
+const SHINGLE_SIZE: usize = 3;
+const NO_OF_HASHES: usize = 64;
+// two signatures become candidates when all rows of one band are equal, with 16 bands of 4
+// rows documents of similarity 0.8 are found with a probability of more than 0.99
+const NO_OF_BANDS: usize = 16;
+const ROWS_PER_BAND: usize = NO_OF_HASHES / NO_OF_BANDS;
+
+// what a build does with a document that is a near-duplicate of one indexed before it in
+// the same build
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateHandling {
+    // no signatures are computed, every document is indexed
+    #[default]
+    Keep,
+    // the document is left out of the index
+    Skip,
+    // the document is indexed and records the doc id of the document it duplicates
+    Mark,
+}
+
+/*
+MinHash signature of the body of a document, the shingles are the runs of
+SHINGLE_SIZE consecutive tokens and every one of the NO_OF_HASHES hash
+functions keeps the smallest hash over all shingles. The share of equal
+minima of two signatures estimates the Jaccard similarity of the shingle
+sets of the two documents.
+
+The hash functions are fixed, so the signature of a document is the same
+in every build.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct MinHashSignature {
+    min_hashes: [u32; NO_OF_HASHES],
+}
+
+// FNV-1a, std's hashers are not guaranteed to hash the same across releases
+fn hash_bytes(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+// splitmix64 finalizer, turns the shingle hash into the hash of one of the hash functions
+fn mix(mut value: u64) -> u64 {
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+    value ^ (value >> 31)
+}
+
+impl MinHashSignature {
+    // None for a document without tokens, empty documents are not duplicates of each other.
+    // A document shorter than a shingle is a single shingle
+    pub fn from_tokens(tokens: &[Token]) -> Option<Self> {
+        if tokens.is_empty() {
+            return None;
+        }
+        let mut min_hashes = [u32::MAX; NO_OF_HASHES];
+        for shingle in tokens.windows(SHINGLE_SIZE.min(tokens.len())) {
+            let shingle_hash = shingle.iter().fold(0xcbf29ce484222325, |hash, token| {
+                hash_bytes(hash_bytes(hash, token.word.as_bytes()), &[0xff])
+            });
+            for (i, min_hash) in min_hashes.iter_mut().enumerate() {
+                let hash = mix(shingle_hash ^ (i as u64).wrapping_mul(0x9e3779b97f4a7c15)) as u32;
+                *min_hash = (*min_hash).min(hash);
+            }
+        }
+        Some(Self { min_hashes })
+    }
+
+    // estimated Jaccard similarity of the shingles of the two documents
+    pub fn similarity(&self, other: &Self) -> f64 {
+        let no_of_equal_hashes = self
+            .min_hashes
+            .iter()
+            .zip(&other.min_hashes)
+            .filter(|(first, second)| first == second)
+            .count();
+        no_of_equal_hashes as f64 / NO_OF_HASHES as f64
+    }
+
+    fn band_keys(&self) -> impl Iterator<Item = u64> + '_ {
+        self.min_hashes
+            .chunks(ROWS_PER_BAND)
+            .map(|band| band.iter().fold(0xcbf29ce484222325, |hash, min_hash| {
+                hash_bytes(hash, &min_hash.to_le_bytes())
+            }))
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for min_hash in &self.min_hashes {
+            write_u32(writer, *min_hash)?;
+        }
+        Ok(())
+    }
+
+    fn read<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let mut min_hashes = [0; NO_OF_HASHES];
+        for min_hash in min_hashes.iter_mut() {
+            *min_hash = read_u32(reader)?;
+        }
+        Ok(Self { min_hashes })
+    }
+}
+
+/*
+Locality sensitive hashing over the signatures of the documents indexed so
+far. The signature is cut into NO_OF_BANDS bands and every band is a bucket
+key, documents sharing a bucket in any band are candidates and a candidate
+is a duplicate if its estimated similarity reaches the threshold.
+
+Every signature is kept in memory for the comparison, 256 bytes per
+document that is not a duplicate itself.
+
+Only the documents of one build are compared. The signatures are dropped
+once the build is done and segments do not keep them, so a near-duplicate
+of a document in a segment written by an earlier build is indexed like any
+other document. Whether two near-duplicates are caught depends on them
+being added in the same batch.
+ */
+pub struct LshIndex {
+    buckets: Vec<HashMap<u64, Vec<u32>>>,
+    signatures: HashMap<u32, MinHashSignature>,
+    similarity_threshold: f64,
+}
+
+impl LshIndex {
+    pub fn new(similarity_threshold: f64) -> Self {
+        Self {
+            buckets: vec![HashMap::new(); NO_OF_BANDS],
+            signatures: HashMap::new(),
+            similarity_threshold,
+        }
+    }
+
+    pub fn insert(&mut self, doc_id: u32, signature: MinHashSignature) {
+        for (band, key) in signature.band_keys().enumerate() {
+            self.buckets[band].entry(key).or_default().push(doc_id);
+        }
+        self.signatures.insert(doc_id, signature);
+    }
+
+    // the lowest doc id of the documents the signature is a near-duplicate of
+    pub fn find_duplicate(&self, signature: &MinHashSignature) -> Option<u32> {
+        signature
+            .band_keys()
+            .enumerate()
+            .filter_map(|(band, key)| self.buckets[band].get(&key))
+            .flatten()
+            .filter(|doc_id| {
+                signature.similarity(&self.signatures[doc_id]) >= self.similarity_threshold
+            })
+            .min()
+            .copied()
+    }
+}
+
+// the signatures in the lsh index of a running build, a resumed build needs them to find
+// duplicates of the documents indexed before the interruption. Appended to like the
+// document metadata log, a checkpoint records its synced length
+pub struct SignatureLog {
+    writer: BufWriter<File>,
+    length: u64,
+}
+
+impl SignatureLog {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            length: 0,
+        })
+    }
+
+    // cuts the log back to the length recorded by a checkpoint and returns the signatures
+    // it holds
+    pub fn resume(path: &Path, length: u64) -> io::Result<(Self, Vec<(u32, MinHashSignature)>)> {
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        file.set_len(length)?;
+
+        let mut log = Vec::with_capacity(length as usize);
+        (&file).read_to_end(&mut log)?;
+        let mut cursor = Cursor::new(log);
+        let mut signatures = Vec::new();
+        while cursor.position() < length {
+            let doc_id = read_u32(&mut cursor)?;
+            signatures.push((doc_id, MinHashSignature::read(&mut cursor)?));
+        }
+
+        file.seek(SeekFrom::End(0))?;
+        Ok((
+            Self {
+                writer: BufWriter::new(file),
+                length,
+            },
+            signatures,
+        ))
+    }
+
+    pub fn append(&mut self, doc_id: u32, signature: &MinHashSignature) -> io::Result<()> {
+        let mut record = Vec::new();
+        write_u32(&mut record, doc_id)?;
+        signature.write(&mut record)?;
+        self.writer.write_all(&record)?;
+        self.length += record.len() as u64;
+        Ok(())
+    }
+
+    // syncs the appended records and returns the length to record in the checkpoint
+    pub fn sync(&mut self) -> io::Result<u64> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_all()?;
+        Ok(self.length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn tokens(text: &str) -> Vec<Token> {
+        text.split_whitespace()
+            .enumerate()
+            .map(|(position, word)| Token {
+                position: position as u32,
+                word: word.to_string(),
+            })
+            .collect()
+    }
+
+    fn signature(text: &str) -> MinHashSignature {
+        MinHashSignature::from_tokens(&tokens(text)).unwrap()
+    }
+
+    fn numbered_text(from: u32, to: u32) -> String {
+        (from..to).map(|i| format!("word{} ", i)).collect()
+    }
+
+    #[test]
+    fn test_similarity_of_signatures() {
+        let text = numbered_text(0, 200);
+        assert_eq!(signature(&text).similarity(&signature(&text)), 1.0);
+        // one word changed changes three of about two hundred shingles
+        let edited = text.replace("word100 ", "changed ");
+        assert!(signature(&text).similarity(&signature(&edited)) > 0.9);
+        assert!(signature(&text).similarity(&signature(&numbered_text(200, 400))) < 0.1);
+        assert_eq!(MinHashSignature::from_tokens(&[]), None);
+        assert_eq!(signature("two words").similarity(&signature("two words")), 1.0);
+    }
+
+    #[test]
+    fn test_lsh_index_finds_first_near_duplicate() {
+        let text = numbered_text(0, 200);
+        let mut lsh_index = LshIndex::new(DEFAULT_SIMILARITY_THRESHOLD);
+        lsh_index.insert(3, signature(&numbered_text(300, 500)));
+        lsh_index.insert(5, signature(&text));
+        lsh_index.insert(2, signature(&text.replace("word10 ", "other ")));
+
+        assert_eq!(lsh_index.find_duplicate(&signature(&text.replace("word150 ", "x "))), Some(2));
+        assert_eq!(lsh_index.find_duplicate(&signature(&numbered_text(320, 520))), Some(3));
+        assert_eq!(lsh_index.find_duplicate(&signature(&numbered_text(600, 800))), None);
+        // half of the shingles in common is not a near-duplicate
+        assert_eq!(lsh_index.find_duplicate(&signature(&numbered_text(400, 600))), None);
+    }
+
+    #[test]
+    fn test_signature_log_resume_drops_unsynced_signatures() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(SIGNATURE_LOG_FILE_NAME);
+
+        let mut signature_log = SignatureLog::create(&path).unwrap();
+        signature_log.append(1, &signature("alpha beta gamma")).unwrap();
+        signature_log.append(4, &signature("delta epsilon")).unwrap();
+        let length = signature_log.sync().unwrap();
+        signature_log.append(5, &signature("lost")).unwrap();
+        signature_log.sync().unwrap();
+        drop(signature_log);
+
+        let (mut signature_log, signatures) = SignatureLog::resume(&path, length).unwrap();
+        assert_eq!(
+            signatures,
+            vec![(1, signature("alpha beta gamma")), (4, signature("delta epsilon"))]
+        );
+        signature_log.append(5, &signature("kept")).unwrap();
+        let length = signature_log.sync().unwrap();
+        drop(signature_log);
+        let (_, signatures) = SignatureLog::resume(&path, length).unwrap();
+        assert_eq!(signatures.len(), 3);
+    }
+}
@@ -0,0 +1,176 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::{
+    dictionary::Posting,
+    indexer::{checksum::Crc32, helper::vb_encode_posting_list},
+};
+
+// no_of_terms and the checksum at the end of a run file
+pub const RUN_FILE_TRAILER_SIZE: u64 = 8;
+
+/*
+Writes a run file in the layout read back by IndexMergeIterator->
+
+no_of_terms
+term_length term posting_list_length vb_encoded_posting_list
+.
+.
+.
+no_of_terms crc32
+
+Terms have to be added in sorted order. The number of terms is only known
+once every term has been added, so it is patched into the header by finish.
+The checksum covers the term records, the header is checked against the
+count repeated in the trailer.
+ */
+pub struct RunFileWriter {
+    writer: BufWriter<File>,
+    no_of_terms: u32,
+    crc: Crc32,
+}
+
+impl RunFileWriter {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = File::create(path)?;
+        let mut writer = BufWriter::new(file);
+        writer.write_all(&0u32.to_le_bytes())?;
+        Ok(Self {
+            writer,
+            no_of_terms: 0,
+            crc: Crc32::new(),
+        })
+    }
+
+    pub fn add_term(&mut self, term: &str, posting_list: &Vec<Posting>) -> io::Result<()> {
+        let encoded_posting_list = vb_encode_posting_list(posting_list);
+        for bytes in [
+            &(term.len() as u32).to_le_bytes()[..],
+            term.as_bytes(),
+            &(encoded_posting_list.len() as u32).to_le_bytes(),
+            &encoded_posting_list,
+        ] {
+            self.writer.write_all(bytes)?;
+            self.crc.update(bytes);
+        }
+        self.no_of_terms += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<u32> {
+        self.writer.write_all(&self.no_of_terms.to_le_bytes())?;
+        self.writer.write_all(&self.crc.finish().to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&self.no_of_terms.to_le_bytes())?;
+        self.writer.flush()?;
+        // a checkpoint can record the run as complete right after this, so it has to be on disk
+        self.writer.get_ref().sync_all()?;
+        Ok(self.no_of_terms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::{
+        checksum::{CorruptionError, as_corruption_error},
+        index_merge_iterator::IndexMergeIterator,
+    };
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_written_run_is_readable_by_merge_iterator() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("run_000001.run");
+
+        let mut run_file_writer = RunFileWriter::create(&path).unwrap();
+        run_file_writer
+            .add_term("apple", &vec![Posting::new(1, vec![0, 3]), Posting::new(4, vec![2])])
+            .unwrap();
+        run_file_writer
+            .add_term("banana", &vec![Posting::new(2, vec![7])])
+            .unwrap();
+        assert_eq!(run_file_writer.finish().unwrap(), 2);
+
+        let mut iterator = IndexMergeIterator::new(File::open(&path).unwrap());
+        iterator.init().unwrap();
+        assert_eq!(iterator.current_term, Some("apple".to_string()));
+        assert_eq!(iterator.current_postings.as_ref().unwrap().len(), 2);
+        assert!(iterator.next().unwrap());
+        assert_eq!(iterator.current_term, Some("banana".to_string()));
+        assert!(!iterator.next().unwrap());
+    }
+
+    #[test]
+    fn test_corrupt_run_is_detected() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("run_000001.run");
+        let mut run_file_writer = RunFileWriter::create(&path).unwrap();
+        run_file_writer
+            .add_term("apple", &vec![Posting::new(1, vec![0, 3])])
+            .unwrap();
+        run_file_writer
+            .add_term("banana", &vec![Posting::new(2, vec![7])])
+            .unwrap();
+        run_file_writer.finish().unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+
+        let read_run = |bytes: &[u8]| -> io::Result<()> {
+            std::fs::write(&path, bytes).unwrap();
+            let mut iterator = IndexMergeIterator::new(File::open(&path).unwrap());
+            iterator.init()?;
+            while iterator.next()? {}
+            Ok(())
+        };
+        assert!(read_run(&bytes).is_ok());
+
+        // a flipped bit in the positions of apple
+        let mut corrupt_bytes = bytes.clone();
+        corrupt_bytes[18] ^= 0x01;
+        let error = read_run(&corrupt_bytes).unwrap_err();
+        assert!(matches!(
+            as_corruption_error(&error),
+            Some(CorruptionError::RunFileChecksumMismatch { .. })
+        ));
+
+        // a run cut short
+        let error = read_run(&bytes[..bytes.len() - 3]).unwrap_err();
+        assert!(matches!(
+            as_corruption_error(&error),
+            Some(CorruptionError::InvalidRunFile { .. })
+        ));
+        // a length running past the end, or postings that would overflow when decoded, are
+        // caught by the checksum before the records are decoded
+        let mut corrupt_bytes = bytes.clone();
+        corrupt_bytes[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write(&path, &corrupt_bytes).unwrap();
+        let mut iterator = IndexMergeIterator::new(File::open(&path).unwrap());
+        let error = iterator.init().unwrap_err();
+        assert!(matches!(
+            as_corruption_error(&error),
+            Some(CorruptionError::RunFileChecksumMismatch { .. })
+        ));
+        assert_eq!(iterator.current_term, None);
+        let mut corrupt_bytes = bytes.clone();
+        corrupt_bytes[17..21].copy_from_slice(&[0x7f; 4]);
+        let error = read_run(&corrupt_bytes).unwrap_err();
+        assert!(matches!(
+            as_corruption_error(&error),
+            Some(CorruptionError::RunFileChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_empty_run() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("run_000001.run");
+
+        assert_eq!(RunFileWriter::create(&path).unwrap().finish().unwrap(), 0);
+        let mut iterator = IndexMergeIterator::new(File::open(&path).unwrap());
+        iterator.init().unwrap();
+        assert!(iterator.current_term.is_none());
+    }
+}
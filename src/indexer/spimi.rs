@@ -1,48 +1,231 @@
 use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
     fs::{self, File},
-    io::{self, BufReader, BufWriter, Read, Write},
-    sync::mpsc,
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, mpsc,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::{Duration, Instant},
 };
 
 use crate::{
+    compressors::posting_codec::PostingCodecId,
     dictionary::{Dictionary, Posting, Term},
     indexer::{
-        helper::{vb_decode_posting_list, vb_encode_posting_list},
+        doc_id_order,
         index_merge_iterator::IndexMergeIterator,
         index_merge_writer::MergedIndexBlockWriter,
         index_metadata::InMemoryIndexMetatdata,
+        run_file_writer::RunFileWriter,
     },
-    positional_intersect::merge_postings,
+    positional_intersect::merge_posting_lists,
 };
 
+pub const FINAL_INDEX_FILE_NAME: &str = "final.idx";
+const RUN_DIRECTORY_NAME: &str = "runs";
+const RUN_FILE_EXTENSION: &str = "run";
+// the most run files merged at once, every run being merged holds an open file
+pub const DEFAULT_MERGE_FAN_IN: usize = 64;
+
+// what the indexer hands to the spimi thread, a checkpoint asks for everything received so
+// far to be flushed into a run file and is answered with the number of run files written.
+// With several workers the largest answer covers the runs of all of them, once every worker
+// has answered
+pub enum SpimiMessage {
+    Term(Term),
+    Checkpoint(mpsc::Sender<io::Result<u32>>),
+}
+
 pub struct Spmi {
     dictionary: Dictionary,
+    index_directory: PathBuf,
+    run_directory: PathBuf,
+    // shared with the workers created from this Spmi, so every run file of a build gets its
+    // own number
+    no_of_runs: Arc<AtomicU32>,
+    merge_fan_in: usize,
+    // time spent building and flushing the dictionary
+    invert_time: Duration,
+    merge_statistics: MergeStatistics,
+    // new doc id of every doc id of the runs, applied by the final merge
+    doc_id_map: Option<Vec<u32>>,
+    // the codec the doc ids in final.idx are encoded with
+    posting_codec: PostingCodecId,
+}
+
+// what the last call of merge_index_files wrote into final.idx
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MergeStatistics {
+    pub no_of_terms: u32,
+    pub no_of_postings: u64,
+    pub no_of_positions: u64,
+    pub no_of_blocks: u32,
+    // estimated bytes of the doc id stream of final.idx, and what it would have taken with
+    // the doc ids of the runs, see BuildStatistics
+    pub doc_id_stream_bytes: u64,
+    pub doc_id_stream_bytes_before_reordering: u64,
+    // the size final.idx has with the doc ids of the runs, only written out to measure it
+    // when a doc id map was applied
+    pub index_size_in_bytes_before_reordering: Option<u64>,
 }
 
 impl Spmi {
-    pub fn new() -> Self {
+    // run files are written to <index_directory>/runs and the merged index to
+    // <index_directory>/final.idx
+    pub fn new(index_directory: &Path) -> Self {
         Self {
             dictionary: Dictionary::new(),
+            index_directory: index_directory.to_path_buf(),
+            run_directory: index_directory.join(RUN_DIRECTORY_NAME),
+            no_of_runs: Arc::new(AtomicU32::new(0)),
+            merge_fan_in: DEFAULT_MERGE_FAN_IN,
+            invert_time: Duration::ZERO,
+            merge_statistics: MergeStatistics::default(),
+            doc_id_map: None,
+            posting_codec: PostingCodecId::default(),
+        }
+    }
+
+    /*
+    Creates a worker for a build inverting documents on several threads. A
+    worker has a dictionary of its own, with the memory budget of this Spmi,
+    and writes run files into the same run directory, numbered from the same
+    counter. Each worker has to receive the documents in increasing doc id
+    order, the runs of all workers are combined by merge_index_files.
+     */
+    pub fn new_worker(&self) -> Self {
+        Self {
+            dictionary: Dictionary::with_memory_budget(self.dictionary.max_size()),
+            index_directory: self.index_directory.clone(),
+            run_directory: self.run_directory.clone(),
+            no_of_runs: self.no_of_runs.clone(),
+            merge_fan_in: self.merge_fan_in,
+            invert_time: Duration::ZERO,
+            merge_statistics: MergeStatistics::default(),
+            doc_id_map: None,
+            posting_codec: self.posting_codec,
         }
     }
+
+    // renumbers the documents in final.idx, the run files keep the doc ids they were
+    // written with
+    pub fn set_doc_id_map(&mut self, doc_id_map: Option<Vec<u32>>) {
+        self.doc_id_map = doc_id_map;
+    }
+
+    pub fn set_posting_codec(&mut self, posting_codec: PostingCodecId) {
+        self.posting_codec = posting_codec;
+    }
+
+    pub fn get_invert_time(&self) -> Duration {
+        self.invert_time
+    }
+
+    pub fn get_merge_statistics(&self) -> &MergeStatistics {
+        &self.merge_statistics
+    }
+
+    pub fn get_no_of_runs(&self) -> u32 {
+        self.no_of_runs.load(Ordering::SeqCst)
+    }
+
+    // with more run files than this, groups of runs are first merged into intermediate
+    // runs until few enough are left for the final merge
+    pub fn set_merge_fan_in(&mut self, merge_fan_in: usize) {
+        self.merge_fan_in = merge_fan_in.max(2);
+    }
+
+    // the number of bytes of postings held in memory before they are flushed into a run
+    pub fn set_memory_budget(&mut self, memory_budget: usize) {
+        self.dictionary.set_max_size(memory_budget);
+    }
+
+    // removes the run files of a previous build that did not finish, so that they do not
+    // end up in this build's merge
+    pub fn prepare_run_directory(&mut self) -> io::Result<()> {
+        if self.run_directory.exists() {
+            fs::remove_dir_all(&self.run_directory)?;
+        }
+        fs::create_dir_all(&self.run_directory)?;
+        self.no_of_runs.store(0, Ordering::SeqCst);
+        Ok(())
+    }
+
+    // keeps the first no_of_runs run files, which a checkpoint recorded as complete, and
+    // removes the ones written after the checkpoint
+    pub fn resume_run_directory(&mut self, no_of_runs: u32) -> io::Result<()> {
+        fs::create_dir_all(&self.run_directory)?;
+        let kept_run_files: Vec<PathBuf> =
+            (1..=no_of_runs).map(|run| self.run_file_path(run)).collect();
+        for entry in fs::read_dir(&self.run_directory)? {
+            let path = entry?.path();
+            if !kept_run_files.contains(&path) {
+                fs::remove_file(&path)?;
+            }
+        }
+        for path in &kept_run_files {
+            if !path.is_file() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("run file {} recorded by the checkpoint is missing", path.display()),
+                ));
+            }
+        }
+        self.no_of_runs.store(no_of_runs, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn run_file_path(&self, run: u32) -> PathBuf {
+        self.run_directory
+            .join(format!("run_{:06}.{}", run, RUN_FILE_EXTENSION))
+    }
+
+    fn next_run_file_path(&mut self) -> PathBuf {
+        let run = self.no_of_runs.fetch_add(1, Ordering::SeqCst) + 1;
+        self.run_file_path(run)
+    }
+
+    fn flush_dictionary(&mut self) -> io::Result<()> {
+        let sorted_terms = self.dictionary.sort_terms();
+        let run_file_path = self.next_run_file_path();
+        self.write_dictionary_to_disk(&run_file_path, &sorted_terms, &self.dictionary)?;
+        self.dictionary.clear();
+        Ok(())
+    }
     pub fn single_pass_in_memory_indexing(
         &mut self,
-        rx: mpsc::Receiver<Term>,
+        rx: mpsc::Receiver<SpimiMessage>,
     ) -> Result<(), std::io::Error> {
-        while let Ok(term) = rx.recv() {
-            let does_term_already_exist = self.dictionary.does_term_already_exist(&term.term);
+        while let Ok(message) = rx.recv() {
+            // only the time spent on the messages counts, not the time waiting for them
+            let started = Instant::now();
+            let term = match message {
+                SpimiMessage::Term(term) => term,
+                SpimiMessage::Checkpoint(ack) => {
+                    let result = if !self.dictionary.is_empty() {
+                        self.flush_dictionary().map(|_| self.get_no_of_runs())
+                    } else {
+                        Ok(self.get_no_of_runs())
+                    };
+                    let _ = ack.send(result);
+                    self.invert_time += started.elapsed();
+                    continue;
+                }
+            };
+            // flushing first, the term has to be added again to the emptied dictionary
             if self.dictionary.size() >= self.dictionary.max_size() {
-                let sorted_terms = self.dictionary.sort_terms();
-                self.write_dictionary_to_disk("", &sorted_terms, &self.dictionary)?;
-                self.dictionary.clear();
-            }
-            if !does_term_already_exist {
-                self.dictionary.add_term(&term.term);
+                self.flush_dictionary()?;
             }
+            self.dictionary.add_term(&term.term);
             self.dictionary.append_to_term(&term.term, term.posting);
+            self.invert_time += started.elapsed();
         }
-        let sorted_terms = self.dictionary.sort_terms();
-        self.write_dictionary_to_disk("", &sorted_terms, &self.dictionary)?;
+        let started = Instant::now();
+        self.flush_dictionary()?;
+        self.invert_time += started.elapsed();
 
         Ok(())
     }
@@ -52,49 +235,136 @@ impl Spmi {
         block_size: u8,
     ) -> Result<InMemoryIndexMetatdata, io::Error> {
         let mut in_memory_index_metadata: InMemoryIndexMetatdata = InMemoryIndexMetatdata::new();
-        let final_index_file = File::create("final.idx")?;
-        let mut merge_iterators = Self::scan_and_create_iterators("index_directory")?;
-        if merge_iterators.is_empty() {
+        self.merge_statistics = MergeStatistics::default();
+        // written under a temporary name and renamed once complete and synced, final.idx
+        // is never seen half written
+        let final_index_path = self.index_directory.join(FINAL_INDEX_FILE_NAME);
+        let temporary_index_path = self
+            .index_directory
+            .join(format!("{}.tmp", FINAL_INDEX_FILE_NAME));
+        let final_index_file = File::create(&temporary_index_path)?;
+        let run_files = Self::scan_run_files(&self.run_directory)?;
+        if run_files.is_empty() {
+            // still a valid index, just one without blocks
+            let mut index_merge_writer =
+                MergedIndexBlockWriter::new(final_index_file, Some(block_size));
+            index_merge_writer.set_posting_codec(self.posting_codec);
+            index_merge_writer.finish()?;
+            fs::rename(&temporary_index_path, &final_index_path)?;
             return Ok(in_memory_index_metadata);
         }
+
+        // the runs written by the indexer are only removed once final.idx is complete, a
+        // checkpoint of an interrupted build may still refer to them
+        let mut merge_inputs = run_files.clone();
+        let mut intermediate_run_files: Vec<PathBuf> = Vec::new();
+        while merge_inputs.len() > self.merge_fan_in {
+            let mut merged_runs = Vec::new();
+            for group in merge_inputs.chunks(self.merge_fan_in) {
+                if group.len() == 1 {
+                    merged_runs.push(group[0].clone());
+                    continue;
+                }
+                let run_file_path = self.next_run_file_path();
+                let mut run_file_writer = RunFileWriter::create(&run_file_path)?;
+                Self::merge_runs(group, |term, postings| {
+                    run_file_writer.add_term(&term, &postings)
+                })?;
+                run_file_writer.finish()?;
+                merged_runs.push(run_file_path);
+            }
+            for path in &intermediate_run_files {
+                if !merged_runs.contains(path) {
+                    fs::remove_file(path)?;
+                }
+            }
+            intermediate_run_files = merged_runs
+                .iter()
+                .filter(|path| !run_files.contains(path))
+                .cloned()
+                .collect();
+            merge_inputs = merged_runs;
+        }
+
         let mut no_of_terms: u32 = 0;
+        let mut no_of_postings: u64 = 0;
+        let mut no_of_positions: u64 = 0;
+        let mut doc_id_stream_bytes: u64 = 0;
+        let mut doc_id_stream_bytes_before_reordering: u64 = 0;
         let mut index_merge_writer: MergedIndexBlockWriter =
             MergedIndexBlockWriter::new(final_index_file, Some(block_size));
-        loop {
-            // Find the smallest current term among all iterators that still have terms
-            let smallest_term = merge_iterators
-                .iter()
-                .filter_map(|it| it.current_term.as_ref())
-                .min()
-                .cloned();
-
-            // Stop if there are no more terms
-            let Some(term) = smallest_term else {
-                break;
-            };
-
-            no_of_terms = no_of_terms + 1;
-
-            let mut posting_lists: Vec<Vec<Posting>> = Vec::new();
-            for it in merge_iterators.iter_mut() {
-                if let Some(curr_term) = &it.current_term {
-                    if curr_term == &term {
-                        if let Some(postings) = &it.current_postings {
-                            posting_lists.push(postings.clone());
-                        }
-                        it.next()?;
+        index_merge_writer.set_posting_codec(self.posting_codec);
+        let posting_codec = self.posting_codec;
+        let doc_id_map = self.doc_id_map.as_deref();
+        // with a doc id map the postings are written a second time in the order of the runs,
+        // into a file that is only kept until its size is known
+        let unordered_index_path = self
+            .index_directory
+            .join(format!("{}.unordered.tmp", FINAL_INDEX_FILE_NAME));
+        let mut unordered_index_writer = match doc_id_map {
+            Some(_) => {
+                let mut writer = MergedIndexBlockWriter::new(
+                    File::create(&unordered_index_path)?,
+                    Some(block_size),
+                );
+                writer.set_posting_codec(posting_codec);
+                Some(writer)
+            }
+            None => None,
+        };
+        Self::merge_runs(&merge_inputs, |term, postings| {
+            no_of_terms += 1;
+            let postings = match doc_id_map {
+                Some(doc_id_map) => {
+                    doc_id_stream_bytes_before_reordering += doc_id_order::doc_id_stream_bytes(
+                        postings.iter().map(|posting| posting.doc_id),
+                        posting_codec,
+                    );
+                    if let Some(writer) = &mut unordered_index_writer {
+                        writer.add_term(no_of_terms, postings.clone())?;
                     }
+                    doc_id_order::remap_postings(postings, doc_id_map)
                 }
+                None => postings,
+            };
+            let term_doc_id_stream_bytes = doc_id_order::doc_id_stream_bytes(
+                postings.iter().map(|posting| posting.doc_id),
+                posting_codec,
+            );
+            doc_id_stream_bytes += term_doc_id_stream_bytes;
+            if doc_id_map.is_none() {
+                doc_id_stream_bytes_before_reordering += term_doc_id_stream_bytes;
             }
-
-            let mut final_merged = Vec::new();
-            for postings in posting_lists {
-                final_merged = merge_postings(&final_merged, &postings);
-            }
-            index_merge_writer.add_term(no_of_terms, final_merged)?;
+            no_of_postings += postings.len() as u64;
+            no_of_positions += postings
+                .iter()
+                .map(|posting| posting.positions.len() as u64)
+                .sum::<u64>();
+            index_merge_writer.add_term(no_of_terms, postings)?;
             in_memory_index_metadata.set_term_id(&term, no_of_terms);
             in_memory_index_metadata.add_term_to_bk_tree(term);
+            Ok(())
+        })?;
+        index_merge_writer.finish()?;
+        fs::rename(&temporary_index_path, &final_index_path)?;
+        let mut index_size_in_bytes_before_reordering = None;
+        if let Some(mut writer) = unordered_index_writer {
+            writer.finish()?;
+            index_size_in_bytes_before_reordering =
+                Some(fs::metadata(&unordered_index_path)?.len());
+            fs::remove_file(&unordered_index_path)?;
         }
+        self.merge_statistics = MergeStatistics {
+            no_of_terms,
+            no_of_postings,
+            no_of_positions,
+            no_of_blocks: index_merge_writer.get_no_of_blocks(),
+            doc_id_stream_bytes,
+            doc_id_stream_bytes_before_reordering,
+            index_size_in_bytes_before_reordering,
+        };
+        intermediate_run_files.extend(run_files);
+        Self::remove_run_files(&self.run_directory, &intermediate_run_files)?;
 
         for term in in_memory_index_metadata.get_all_terms() {
             let term_id = in_memory_index_metadata.get_term_id(term.clone());
@@ -107,74 +377,424 @@ impl Spmi {
             }
         }
 
-        // for doc_id in 1..doc_lengths.len() + 1 {
-        //     let mut doc_length: f32 = 0.0;
-        //     if let Some(tf_idfs) = doc_lengths.get(&(doc_id as u32)) {
-        //         for tf_idf in tf_idfs {
-        //             doc_length = doc_length + (tf_idf * tf_idf);
-        //         }
-        //     }
-        //     doc_lengths_final.push(doc_length.sqrt());
-        // }
-
         Ok(in_memory_index_metadata)
     }
 
-    fn scan_and_create_iterators(directory: &str) -> io::Result<Vec<IndexMergeIterator>> {
-        let mut iterators = Vec::new();
+    // k-way merge of the run files, hands every term to add_term once in sorted order
+    // together with the postings of all runs merged by doc id. A heap keyed by the current
+    // term of every run finds the smallest term without looking at every run
+    fn merge_runs(
+        run_files: &[PathBuf],
+        mut add_term: impl FnMut(String, Vec<Posting>) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let mut merge_iterators = Vec::with_capacity(run_files.len());
+        for path in run_files {
+            let mut merge_iterator = IndexMergeIterator::new(File::open(path)?);
+            merge_iterator.init()?;
+            merge_iterators.push(merge_iterator);
+        }
+
+        let mut heap = BinaryHeap::new();
+        for (run, merge_iterator) in merge_iterators.iter_mut().enumerate() {
+            if let Some(term) = merge_iterator.current_term.take() {
+                heap.push(Reverse((term, run)));
+            }
+        }
+        // takes the postings of the run's current term and moves the run on to its next term
+        let mut advance = |heap: &mut BinaryHeap<Reverse<(String, usize)>>,
+                           run: usize|
+         -> io::Result<Vec<Posting>> {
+            let merge_iterator = &mut merge_iterators[run];
+            let postings = merge_iterator.current_postings.take().unwrap_or_default();
+            merge_iterator.next()?;
+            if let Some(term) = merge_iterator.current_term.take() {
+                heap.push(Reverse((term, run)));
+            }
+            Ok(postings)
+        };
+
+        while let Some(Reverse((term, run))) = heap.pop() {
+            let mut posting_lists = vec![advance(&mut heap, run)?];
+            while let Some(Reverse((next_term, _))) = heap.peek()
+                && *next_term == term
+            {
+                let Some(Reverse((_, run))) = heap.pop() else {
+                    break;
+                };
+                posting_lists.push(advance(&mut heap, run)?);
+            }
+            add_term(term, merge_posting_lists(posting_lists))?;
+        }
+        Ok(())
+    }
 
-        // Read directory entries
+    fn scan_run_files(directory: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut run_files = Vec::new();
         for entry in fs::read_dir(directory)? {
-            let entry = entry?;
-            let path = entry.path();
-
-            // Check for .idx files
-            if path.is_file() {
-                if let Some(ext) = path.extension() {
-                    if ext == "idx" {
-                        let file = File::open(&path)?;
-                        let mut merge_iter = IndexMergeIterator::new(file);
-                        merge_iter.init()?; // Initialize the iterator
-                        iterators.push(merge_iter);
-                        println!("Created iterator for: {}", path.display());
-                    }
-                }
+            let path = entry?.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == RUN_FILE_EXTENSION) {
+                run_files.push(path);
             }
         }
+        // keeps the merge order independent of the order the file system lists the runs in
+        run_files.sort();
+        Ok(run_files)
+    }
 
-        Ok(iterators)
+    // run files are only intermediates, once the final index has been written they are removed
+    fn remove_run_files(directory: &Path, run_files: &[PathBuf]) -> io::Result<()> {
+        for path in run_files {
+            fs::remove_file(path)?;
+        }
+        if fs::read_dir(directory)?.next().is_none() {
+            fs::remove_dir(directory)?;
+        }
+        Ok(())
     }
 
     fn write_dictionary_to_disk(
         &self,
-        filename: &str,
+        filename: &Path,
         sorted_terms: &Vec<String>,
         dict: &Dictionary,
     ) -> Result<(), std::io::Error> {
-        let file = File::create(filename)?;
-        let mut writer = BufWriter::new(file);
-        writer.write_all(&(sorted_terms.len() as u32).to_le_bytes())?;
+        let mut run_file_writer = RunFileWriter::create(filename)?;
         for term in sorted_terms {
             if let Some(posting_list) = dict.get_postings(term) {
-                self.write_term_to_disk(&mut writer, term, &posting_list)?;
+                run_file_writer.add_term(term, &posting_list)?;
             }
         }
-
-        writer.flush()?;
+        run_file_writer.finish()?;
         return Ok(());
     }
 
-    fn write_term_to_disk(
-        &self,
-        writer: &mut BufWriter<File>,
-        term: &str,
-        posting_list: &Vec<Posting>,
-    ) -> Result<(), std::io::Error> {
-        writer.write_all(&(term.len() as u32).to_le_bytes())?;
-        writer.write_all(term.as_bytes())?;
-        let encoded_posting_list = vb_encode_posting_list(posting_list);
-        writer.write_all(&(encoded_posting_list.len() as u32).to_le_bytes())?;
-        writer.write_all(&encoded_posting_list)?;
-        Ok(())
+    // hands out a writer for a new, uniquely named run file, for callers that produce
+    // sorted runs without going through the in memory dictionary
+    pub fn create_run_file(&mut self) -> io::Result<RunFileWriter> {
+        let run_file_path = self.next_run_file_path();
+        RunFileWriter::create(&run_file_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::block::Block;
+    use std::io::BufReader;
+    use tempfile::TempDir;
+
+    fn send_terms(terms: Vec<(&str, u32, Vec<u32>)>) -> mpsc::Receiver<SpimiMessage> {
+        let (tx, rx) = mpsc::channel::<SpimiMessage>();
+        for (term, doc_id, positions) in terms {
+            tx.send(SpimiMessage::Term(Term {
+                term: term.to_string(),
+                posting: Posting::new(doc_id, positions),
+            }))
+            .unwrap();
+        }
+        rx
+    }
+
+    #[test]
+    fn test_run_files_are_written_under_index_directory() {
+        let index_directory = TempDir::new().unwrap();
+        let mut spmi = Spmi::new(index_directory.path());
+        spmi.prepare_run_directory().unwrap();
+
+        spmi.dictionary.add_term("apple");
+        spmi.dictionary.append_to_term("apple", Posting::new(1, vec![0]));
+        spmi.flush_dictionary().unwrap();
+        spmi.dictionary.add_term("banana");
+        spmi.dictionary.append_to_term("banana", Posting::new(2, vec![3]));
+        spmi.flush_dictionary().unwrap();
+
+        let run_directory = index_directory.path().join(RUN_DIRECTORY_NAME);
+        assert!(run_directory.join("run_000001.run").is_file());
+        assert!(run_directory.join("run_000002.run").is_file());
+    }
+
+    #[test]
+    fn test_prepare_run_directory_removes_stale_runs() {
+        let index_directory = TempDir::new().unwrap();
+        let run_directory = index_directory.path().join(RUN_DIRECTORY_NAME);
+        fs::create_dir_all(&run_directory).unwrap();
+        fs::write(run_directory.join("run_000007.run"), b"stale").unwrap();
+
+        let mut spmi = Spmi::new(index_directory.path());
+        spmi.prepare_run_directory().unwrap();
+
+        assert!(run_directory.is_dir());
+        assert_eq!(fs::read_dir(&run_directory).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_checkpoint_flushes_a_run() {
+        let index_directory = TempDir::new().unwrap();
+        let mut spmi = Spmi::new(index_directory.path());
+        spmi.prepare_run_directory().unwrap();
+
+        let (tx, rx) = mpsc::channel::<SpimiMessage>();
+        let (ack_tx, ack_rx) = mpsc::channel();
+        tx.send(SpimiMessage::Term(Term {
+            term: "rust".to_string(),
+            posting: Posting::new(1, vec![0]),
+        }))
+        .unwrap();
+        tx.send(SpimiMessage::Checkpoint(ack_tx.clone())).unwrap();
+        // nothing new arrived since the last checkpoint, so no empty run is written
+        tx.send(SpimiMessage::Checkpoint(ack_tx)).unwrap();
+        drop(tx);
+        spmi.single_pass_in_memory_indexing(rx).unwrap();
+
+        assert_eq!(ack_rx.recv().unwrap().unwrap(), 1);
+        assert_eq!(ack_rx.recv().unwrap().unwrap(), 1);
+        let run_directory = index_directory.path().join(RUN_DIRECTORY_NAME);
+        assert!(run_directory.join("run_000001.run").is_file());
+    }
+
+    #[test]
+    fn test_resume_run_directory_removes_runs_after_checkpoint() {
+        let index_directory = TempDir::new().unwrap();
+        let run_directory = index_directory.path().join(RUN_DIRECTORY_NAME);
+        fs::create_dir_all(&run_directory).unwrap();
+        for run in ["run_000001.run", "run_000002.run", "run_000003.run"] {
+            fs::write(run_directory.join(run), b"run").unwrap();
+        }
+
+        let mut spmi = Spmi::new(index_directory.path());
+        spmi.resume_run_directory(2).unwrap();
+        assert!(run_directory.join("run_000002.run").is_file());
+        assert!(!run_directory.join("run_000003.run").exists());
+        assert_eq!(spmi.next_run_file_path(), run_directory.join("run_000003.run"));
+
+        assert!(spmi.resume_run_directory(5).is_err());
+    }
+
+    #[test]
+    fn test_merge_writes_final_index_and_removes_runs() {
+        let index_directory = TempDir::new().unwrap();
+        let mut spmi = Spmi::new(index_directory.path());
+        spmi.prepare_run_directory().unwrap();
+
+        let rx = send_terms(vec![
+            ("rust", 1, vec![0, 4]),
+            ("language", 1, vec![1]),
+            ("rust", 2, vec![2]),
+        ]);
+        spmi.single_pass_in_memory_indexing(rx).unwrap();
+        let metadata = spmi.merge_index_files(64).unwrap();
+
+        let final_index_path = index_directory.path().join(FINAL_INDEX_FILE_NAME);
+        assert!(final_index_path.is_file());
+        assert!(!index_directory.path().join(RUN_DIRECTORY_NAME).exists());
+
+        let rust_metadata = metadata.get_term_metadata("rust");
+        assert_eq!(rust_metadata.term_frequency, 2);
+        assert_eq!(
+            spmi.get_merge_statistics(),
+            &MergeStatistics {
+                no_of_terms: 2,
+                no_of_postings: 3,
+                no_of_positions: 4,
+                no_of_blocks: 1,
+                doc_id_stream_bytes: 3,
+                doc_id_stream_bytes_before_reordering: 3,
+                index_size_in_bytes_before_reordering: None,
+            }
+        );
+
+        let mut file = File::open(&final_index_path).unwrap();
+        let mut reader = BufReader::new(&mut file);
+        let mut block = Block::new(rust_metadata.block_ids[0]);
+        block.init(&mut reader).unwrap();
+        let term_index = block.check_if_term_exists(rust_metadata.term_id);
+        let chunks = block.decode_chunks_for_term(rust_metadata.term_id, term_index as usize).unwrap();
+        assert_eq!(chunks[0].get_doc_ids().unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_final_merge_applies_doc_id_map() {
+        let index_directory = TempDir::new().unwrap();
+        let mut spmi = Spmi::new(index_directory.path());
+        spmi.prepare_run_directory().unwrap();
+        let rx = send_terms(vec![
+            ("rust", 1, vec![0, 4]),
+            ("rust", 2, vec![2]),
+            ("rust", 300, vec![7]),
+        ]);
+        spmi.single_pass_in_memory_indexing(rx).unwrap();
+        let mut doc_id_map = vec![0; 301];
+        doc_id_map[1] = 3;
+        doc_id_map[2] = 2;
+        doc_id_map[300] = 1;
+        spmi.set_doc_id_map(Some(doc_id_map));
+        let metadata = spmi.merge_index_files(64).unwrap();
+
+        let statistics = spmi.get_merge_statistics();
+        assert_eq!(statistics.doc_id_stream_bytes_before_reordering, 4);
+        assert_eq!(statistics.doc_id_stream_bytes, 3);
+        // the same size here, both orders fit into one block, which is padded to its full size
+        let index_size_in_bytes =
+            fs::metadata(index_directory.path().join(FINAL_INDEX_FILE_NAME)).unwrap().len();
+        assert_eq!(statistics.index_size_in_bytes_before_reordering, Some(index_size_in_bytes));
+        assert!(!index_directory.path().join("final.idx.unordered.tmp").exists());
+        let rust_metadata = metadata.get_term_metadata("rust");
+        let mut file = File::open(index_directory.path().join(FINAL_INDEX_FILE_NAME)).unwrap();
+        let mut reader = BufReader::new(&mut file);
+        let mut block = Block::new(rust_metadata.block_ids[0]);
+        block.init(&mut reader).unwrap();
+        let term_index = block.check_if_term_exists(rust_metadata.term_id);
+        let chunks = block.decode_chunks_for_term(rust_metadata.term_id, term_index as usize).unwrap();
+        assert_eq!(chunks[0].get_doc_ids().unwrap(), vec![1, 2, 3]);
+        assert_eq!(chunks[0].get_posting_list(0).unwrap(), vec![7]);
+        assert_eq!(chunks[0].get_posting_list(2).unwrap(), vec![0, 4]);
+    }
+
+    #[test]
+    fn test_memory_budget_flushes_runs_without_losing_postings() {
+        let index_directory = TempDir::new().unwrap();
+        let mut spmi = Spmi::new(index_directory.path());
+        spmi.prepare_run_directory().unwrap();
+        // every term fills the budget, so a run is flushed before each following term
+        spmi.set_memory_budget(1);
+
+        let rx = send_terms(vec![
+            ("rust", 1, vec![0]),
+            ("language", 1, vec![1]),
+            ("rust", 2, vec![2]),
+            ("rust", 3, vec![0, 5]),
+        ]);
+        spmi.single_pass_in_memory_indexing(rx).unwrap();
+        assert_eq!(spmi.get_no_of_runs(), 4);
+
+        let metadata = spmi.merge_index_files(64).unwrap();
+        let rust_metadata = metadata.get_term_metadata("rust");
+        assert_eq!(rust_metadata.term_frequency, 3);
+
+        let final_index_path = index_directory.path().join(FINAL_INDEX_FILE_NAME);
+        let mut file = File::open(&final_index_path).unwrap();
+        let mut reader = BufReader::new(&mut file);
+        let mut block = Block::new(rust_metadata.block_ids[0]);
+        block.init(&mut reader).unwrap();
+        let term_index = block.check_if_term_exists(rust_metadata.term_id);
+        let chunks = block.decode_chunks_for_term(rust_metadata.term_id, term_index as usize).unwrap();
+        assert_eq!(chunks[0].get_doc_ids().unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_multi_pass_merge_with_small_fan_in() {
+        let index_directory = TempDir::new().unwrap();
+        let mut spmi = Spmi::new(index_directory.path());
+        spmi.prepare_run_directory().unwrap();
+        spmi.set_memory_budget(1);
+        spmi.set_merge_fan_in(2);
+
+        // one run per term, five runs need three passes with a fan in of two
+        let rx = send_terms(vec![
+            ("rust", 1, vec![0]),
+            ("zebra", 2, vec![1]),
+            ("rust", 3, vec![2]),
+            ("apple", 4, vec![0]),
+            ("rust", 5, vec![4, 7]),
+        ]);
+        spmi.single_pass_in_memory_indexing(rx).unwrap();
+        assert_eq!(spmi.get_no_of_runs(), 5);
+
+        let metadata = spmi.merge_index_files(64).unwrap();
+        assert!(!index_directory.path().join(RUN_DIRECTORY_NAME).exists());
+        // term ids are handed out in sorted term order
+        assert_eq!(metadata.get_term_metadata("apple").term_id, 1);
+        assert_eq!(metadata.get_term_metadata("rust").term_id, 2);
+        assert_eq!(metadata.get_term_metadata("zebra").term_id, 3);
+
+        let rust_metadata = metadata.get_term_metadata("rust");
+        assert_eq!(rust_metadata.term_frequency, 3);
+        let final_index_path = index_directory.path().join(FINAL_INDEX_FILE_NAME);
+        let mut file = File::open(&final_index_path).unwrap();
+        let mut reader = BufReader::new(&mut file);
+        let mut block = Block::new(rust_metadata.block_ids[0]);
+        block.init(&mut reader).unwrap();
+        let term_index = block.check_if_term_exists(rust_metadata.term_id);
+        let chunks = block.decode_chunks_for_term(rust_metadata.term_id, term_index as usize).unwrap();
+        assert_eq!(chunks[0].get_doc_ids().unwrap(), vec![1, 3, 5]);
+    }
+
+    #[test]
+    fn test_merge_runs_merges_equal_terms_in_sorted_order() {
+        let run_directory = TempDir::new().unwrap();
+        let mut run_files = Vec::new();
+        for (run, terms) in [
+            vec![("b", 2), ("d", 2)],
+            vec![("a", 1), ("b", 1)],
+            vec![("b", 3), ("c", 3)],
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let path = run_directory.path().join(format!("run_{}.run", run));
+            let mut run_file_writer = RunFileWriter::create(&path).unwrap();
+            for (term, doc_id) in terms {
+                run_file_writer
+                    .add_term(term, &vec![Posting::new(doc_id, vec![0])])
+                    .unwrap();
+            }
+            run_file_writer.finish().unwrap();
+            run_files.push(path);
+        }
+
+        let mut merged = Vec::new();
+        Spmi::merge_runs(&run_files, |term, postings| {
+            let doc_ids: Vec<u32> = postings.iter().map(|posting| posting.doc_id).collect();
+            merged.push((term, doc_ids));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(
+            merged,
+            vec![
+                ("a".to_string(), vec![1]),
+                ("b".to_string(), vec![1, 2, 3]),
+                ("c".to_string(), vec![3]),
+                ("d".to_string(), vec![2]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_workers_write_distinct_runs_that_merge_into_one_index() {
+        let index_directory = TempDir::new().unwrap();
+        let mut spmi = Spmi::new(index_directory.path());
+        spmi.prepare_run_directory().unwrap();
+
+        // odd doc ids go to the first worker, even ones to the second
+        let workers: Vec<Spmi> = (0..2).map(|_| spmi.new_worker()).collect();
+        let receivers = [
+            send_terms(vec![("rust", 1, vec![0]), ("apple", 3, vec![1])]),
+            send_terms(vec![("rust", 2, vec![0]), ("rust", 4, vec![3])]),
+        ];
+        let handles: Vec<_> = workers
+            .into_iter()
+            .zip(receivers)
+            .map(|(mut worker, rx)| {
+                std::thread::spawn(move || worker.single_pass_in_memory_indexing(rx))
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap().unwrap();
+        }
+        assert_eq!(spmi.get_no_of_runs(), 2);
+
+        let metadata = spmi.merge_index_files(64).unwrap();
+        assert_eq!(metadata.get_term_metadata("apple").term_frequency, 1);
+        let rust_metadata = metadata.get_term_metadata("rust");
+        assert_eq!(rust_metadata.term_frequency, 3);
+        let final_index_path = index_directory.path().join(FINAL_INDEX_FILE_NAME);
+        let mut file = File::open(&final_index_path).unwrap();
+        let mut reader = BufReader::new(&mut file);
+        let mut block = Block::new(rust_metadata.block_ids[0]);
+        block.init(&mut reader).unwrap();
+        let term_index = block.check_if_term_exists(rust_metadata.term_id);
+        let chunks = block.decode_chunks_for_term(rust_metadata.term_id, term_index as usize).unwrap();
+        assert_eq!(chunks[0].get_doc_ids().unwrap(), vec![1, 2, 4]);
     }
 }
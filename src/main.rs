@@ -1,12 +1,15 @@
 mod compressors;
 mod dictionary;
+pub mod document_source;
 mod helpers;
+mod highlighter;
 mod in_memory_dict;
 mod indexer;
 mod my_bk_tree;
 mod positional_intersect;
 mod query_parser;
 mod scoring;
+mod segment;
 pub mod search_engine;
 mod query_processor;
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{cmp::Reverse, collections::{BinaryHeap, HashMap}};
 use crate::{dictionary::Posting};
 
 #[derive(Debug, Clone, PartialEq)]
@@ -105,28 +105,39 @@ pub fn find_documents_optimized(
 }
 
 
-pub fn merge_postings(p1: &[Posting], p2: &[Posting]) -> Vec<Posting> {
-    let mut merged = Vec::with_capacity(p1.len() + p2.len());
-    let mut i = 0;
-    let mut j = 0;
-
-    while i < p1.len() && j < p2.len() {
-        if p1[i].doc_id < p2[j].doc_id {
-            merged.push(p1[i].clone());
-            i += 1;
-        } else {
-            merged.push(p2[j].clone());
-            j += 1;
+// the postings of a term from several runs sorted by doc id. Runs of one worker hold
+// increasing doc ids and are appended, the runs of parallel workers interleave and are
+// merged through a heap of their next doc ids. Postings are moved, never cloned
+pub fn merge_posting_lists(mut posting_lists: Vec<Vec<Posting>>) -> Vec<Posting> {
+    posting_lists.retain(|postings| !postings.is_empty());
+    posting_lists.sort_by_key(|postings| postings[0].doc_id);
+    let total: usize = posting_lists.iter().map(|postings| postings.len()).sum();
+    let mut merged = Vec::with_capacity(total);
+    let are_disjoint = posting_lists
+        .windows(2)
+        .all(|pair| pair[0].last().unwrap().doc_id < pair[1][0].doc_id);
+    if are_disjoint {
+        for mut postings in posting_lists {
+            merged.append(&mut postings);
         }
+        return merged;
     }
 
-    if i < p1.len() {
-        merged.extend_from_slice(&p1[i..]);
+    let mut cursors: Vec<_> = posting_lists.into_iter().map(|postings| postings.into_iter()).collect();
+    let mut heap = BinaryHeap::new();
+    let mut next_postings: Vec<Option<Posting>> = cursors.iter_mut().map(|cursor| cursor.next()).collect();
+    for (list, posting) in next_postings.iter().enumerate() {
+        if let Some(posting) = posting {
+            heap.push(Reverse((posting.doc_id, list)));
+        }
     }
-    if j < p2.len() {
-        merged.extend_from_slice(&p2[j..]);
+    while let Some(Reverse((_, list))) = heap.pop() {
+        merged.extend(next_postings[list].take());
+        next_postings[list] = cursors[list].next();
+        if let Some(posting) = &next_postings[list] {
+            heap.push(Reverse((posting.doc_id, list)));
+        }
     }
-
     merged
 }
 
@@ -135,11 +146,28 @@ pub fn merge_postings(p1: &[Posting], p2: &[Posting]) -> Vec<Posting> {
 mod merge_postings_test {
     use super::*;
 
+    #[test]
+    fn test_merge_posting_lists_appends_or_interleaves() {
+        let postings = |doc_ids: &[u32]| -> Vec<Posting> {
+            doc_ids.iter().map(|doc_id| Posting::new(*doc_id, vec![*doc_id])).collect()
+        };
+        let doc_ids = |postings: Vec<Posting>| -> Vec<u32> {
+            postings.iter().map(|posting| posting.doc_id).collect()
+        };
+        assert_eq!(
+            doc_ids(merge_posting_lists(vec![postings(&[5, 6]), postings(&[1, 2]), vec![]])),
+            vec![1, 2, 5, 6]
+        );
+        let merged = merge_posting_lists(vec![postings(&[2, 4, 6]), postings(&[1, 3, 5]), postings(&[7])]);
+        assert_eq!(merged[4].positions, vec![5]);
+        assert_eq!(doc_ids(merged), vec![1, 2, 3, 4, 5, 6, 7]);
+    }
+
     #[test]
     fn test_merge_both_empty() {
         let p1: Vec<Posting> = vec![];
         let p2: Vec<Posting> = vec![];
-        let result = merge_postings(&p1, &p2);
+        let result = merge_posting_lists(vec![p1, p2]);
         assert_eq!(result, vec![]);
     }
 
@@ -156,7 +184,7 @@ mod merge_postings_test {
                 positions: vec![30],
             },
         ];
-        let result = merge_postings(&p1, &p2);
+        let result = merge_posting_lists(vec![p1, p2]);
         let expected = vec![
             Posting {
                 doc_id: 1,
@@ -200,7 +228,7 @@ mod merge_postings_test {
                 positions: vec![60, 65],
             },
         ];
-        let result = merge_postings(&p1, &p2);
+        let result = merge_posting_lists(vec![p1, p2]);
         let expected = vec![
             Posting {
                 doc_id: 1,
@@ -252,7 +280,7 @@ mod merge_postings_test {
                 positions: vec![60],
             },
         ];
-        let result = merge_postings(&p1, &p2);
+        let result = merge_posting_lists(vec![p1, p2]);
         let expected = vec![
             Posting {
                 doc_id: 1,
@@ -296,7 +324,7 @@ mod merge_postings_test {
                 positions: vec![20],
             },
         ];
-        let result = merge_postings(&p1, &p2);
+        let result = merge_posting_lists(vec![p1, p2]);
         let expected = vec![
             Posting {
                 doc_id: 1,
@@ -0,0 +1,85 @@
+use crate::{
+    indexer::field::{ALL_FIELDS, Field},
+    query_parser::tokenizer::{SearchTokenizer, TokenizationError},
+};
+
+// a word of a query and the field it has to occur in, a word without a field can occur in
+// any of them
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryTerm {
+    pub field: Option<Field>,
+    pub word: String,
+}
+
+impl QueryTerm {
+    pub fn new(field: Option<Field>, word: &str) -> Self {
+        Self {
+            field,
+            word: word.to_string(),
+        }
+    }
+
+    // the lexicon terms that satisfy this query term, a document has to contain one of them
+    pub fn get_lexicon_terms(&self) -> Vec<String> {
+        match self.field {
+            Some(field) => vec![field.term(&self.word)],
+            None => ALL_FIELDS.iter().map(|field| field.term(&self.word)).collect(),
+        }
+    }
+}
+
+// tokenizes a query like "title:rust memory safety", a field name followed by a colon
+// restricts the word right after it to that field
+pub fn parse_field_query(
+    search_tokenizer: &SearchTokenizer,
+    query: &str,
+) -> Result<Vec<QueryTerm>, TokenizationError> {
+    if query.trim().is_empty() {
+        return Err(TokenizationError::EmptyInput);
+    }
+    let mut query_terms = Vec::new();
+    for word in query.split_whitespace() {
+        let (field, word) = Field::split_qualified_word(word);
+        for token in search_tokenizer.tokenize(word.to_string()) {
+            query_terms.push(QueryTerm::new(field, &token.word));
+        }
+    }
+    Ok(query_terms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_field_query() {
+        let search_tokenizer = SearchTokenizer::new().unwrap();
+        let query_terms = parse_field_query(&search_tokenizer, "title:Rust URL:wiki compiler").unwrap();
+        assert_eq!(
+            query_terms,
+            vec![
+                QueryTerm::new(Some(Field::Title), "rust"),
+                QueryTerm::new(Some(Field::Url), "wiki"),
+                QueryTerm::new(None, "compiler"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_empty_query_is_an_error() {
+        let search_tokenizer = SearchTokenizer::new().unwrap();
+        assert!(parse_field_query(&search_tokenizer, "   ").is_err());
+    }
+
+    #[test]
+    fn test_lexicon_terms() {
+        assert_eq!(
+            QueryTerm::new(Some(Field::Title), "rust").get_lexicon_terms(),
+            vec!["title:rust"]
+        );
+        assert_eq!(
+            QueryTerm::new(None, "rust").get_lexicon_terms(),
+            vec!["title:rust", "rust", "url:rust", "anchor:rust"]
+        );
+    }
+}
@@ -1 +1,2 @@
+pub mod field_query;
 pub mod tokenizer;
\ No newline at end of file
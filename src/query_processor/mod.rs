@@ -1 +1 @@
-mod query_processor;
\ No newline at end of file
+pub mod query_processor;
\ No newline at end of file
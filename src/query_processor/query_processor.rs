@@ -1,6 +1,6 @@
-use std::{collections::{HashMap, HashSet}, fs::File, io::BufReader, u32};
+use std::{collections::{HashMap, HashSet}, fs::File, io::{self, BufReader}, u32};
 
-use crate::{in_memory_dict::map_in_memory_dict::MapInMemoryDictPointer, indexer::{block::Block, indexer::DocumentMetadata}};
+use crate::{in_memory_dict::map_in_memory_dict::MapInMemoryDictPointer, indexer::{block::Block, chunk::{Chunk, MAX_POSTINGS_PER_CHUNK}}, scoring::{get_inverse_document_frequency, get_tf_idf_weight}, segment::live_docs::LiveDocs};
 
 pub struct QueryProcessor {
     inverted_index_file: File,
@@ -13,73 +13,155 @@ impl QueryProcessor {
         }
     }
 
-    fn get_doc_ids_for_term(&mut self, block_ids: &[u32], term_id: u32) -> HashSet<u32> {
+    // reads every block the term is spread across together with the index of the term in it
+    fn read_blocks_for_term(&mut self, block_ids: &[u32], term_id: u32) -> io::Result<Vec<(Block, usize)>> {
         let mut reader: BufReader<&mut File> = BufReader::new(&mut self.inverted_index_file);
 
-        let mut doc_ids = HashSet::new();
-        for i in 0..block_ids.len() {
-            let mut block = Block::new(block_ids[i]);
-            block.init(&mut reader).unwrap();
+        let mut blocks = Vec::new();
+        for block_id in block_ids {
+            let mut block = Block::new(*block_id);
+            block.init(&mut reader)?;
             let term_index = block.check_if_term_exists(term_id);
-            let chunks = block.decode_chunks_for_term(term_id, term_index as usize);
-            for chunk in chunks {
-                doc_ids.extend(&mut chunk.get_doc_ids().into_iter());
+            if term_index == -1 {
+                continue;
             }
+            blocks.push((block, term_index as usize));
         }
-        doc_ids
+        Ok(blocks)
     }
 
-    fn intersect(&mut self, block_ids: &[u32], term_id: u32, doc_ids: &mut HashSet<u32>) {
-        let mut reader: BufReader<&mut File> = BufReader::new(&mut self.inverted_index_file);
-        for i in 0..block_ids.len() {
-            let mut block = Block::new(block_ids[i]);
-            block.init(&mut reader).unwrap();
-            let term_index = block.check_if_term_exists(term_id);
-            if term_index==-1{
-                continue;
-            }
-            let chunks = block.decode_chunks_for_term(term_id, term_index as usize);
+    // decodes every chunk of the term from its blocks, the positions stay in the blocks
+    fn get_chunks_for_term(blocks: &[(Block, usize)], term_id: u32) -> io::Result<Vec<Chunk<'_>>> {
+        let mut chunks = Vec::new();
+        for (block, term_index) in blocks {
+            chunks.extend(block.decode_chunks_for_term(term_id, *term_index)?);
+        }
+        Ok(chunks)
+    }
+
+    fn get_doc_ids_for_term(&mut self, block_ids: &[u32], term_id: u32) -> io::Result<HashSet<u32>> {
+        let blocks = self.read_blocks_for_term(block_ids, term_id)?;
+        let mut doc_ids = HashSet::new();
+        let mut chunk_doc_ids = [0; MAX_POSTINGS_PER_CHUNK];
+        for chunk in Self::get_chunks_for_term(&blocks, term_id)? {
+            let no_of_postings = chunk.read_doc_ids(&mut chunk_doc_ids)?;
+            doc_ids.extend(&chunk_doc_ids[..no_of_postings]);
+        }
+        Ok(doc_ids)
+    }
+
+    // keeps the doc ids that are in at least one of the terms
+    fn intersect(&mut self, terms: &[&MapInMemoryDictPointer], doc_ids: &mut HashSet<u32>) -> io::Result<()> {
+        let mut blocks_per_term = Vec::with_capacity(terms.len());
+        for term in terms {
+            blocks_per_term.push(self.read_blocks_for_term(&term.block_ids, term.term_id)?);
+        }
+        let mut chunks_per_term = Vec::with_capacity(terms.len());
+        for (term, blocks) in terms.iter().zip(&blocks_per_term) {
+            chunks_per_term.push(Self::get_chunks_for_term(blocks, term.term_id)?);
+        }
+        let block = Block::new(0);
+        let mut chunk_doc_ids = [0; MAX_POSTINGS_PER_CHUNK];
 
-            doc_ids.retain(|doc_id| {
-                if let Some(chunk) = block.get_chunk_for_doc(*doc_id, &chunks) {
-                    let chunk_doc_ids = chunk.get_doc_ids();
-                    chunk_doc_ids.contains(&doc_id)
-                } else {
-                    false // Remove if chunk not found
+        let mut matching_doc_ids = HashSet::new();
+        for doc_id in doc_ids.iter() {
+            for chunks in &chunks_per_term {
+                // a doc id without a chunk is not in the term
+                if let Some(chunk) = block.get_chunk_for_doc(*doc_id, chunks) {
+                    let no_of_postings = chunk.read_doc_ids(&mut chunk_doc_ids)?;
+                    if chunk_doc_ids[..no_of_postings].contains(doc_id) {
+                        matching_doc_ids.insert(*doc_id);
+                        break;
+                    }
                 }
-            });
+            }
         }
+        *doc_ids = matching_doc_ids;
+        Ok(())
     }
 
-    pub fn score_docs(& mut self,doc_metadata:&HashMap<u32,DocumentMetadata>){
+    // returns the positions of the term in the document, empty if the document does not
+    // contain the term. Only the positions of the one posting are decoded
+    pub fn get_positions(&mut self, block_ids: &[u32], term_id: u32, doc_id: u32) -> io::Result<Vec<u32>> {
+        let blocks = self.read_blocks_for_term(block_ids, term_id)?;
+        let chunks = Self::get_chunks_for_term(&blocks, term_id)?;
+        let block = Block::new(0);
+        if let Some(chunk) = block.get_chunk_for_doc(doc_id, &chunks)
+            && let Some(index) = chunk.get_doc_ids()?.iter().position(|id| *id == doc_id)
+        {
+            return chunk.get_posting_list(index as u32);
+        }
+        Ok(Vec::new())
+    }
 
+    // the tf-idf score of every doc id, in the same order, summed over every alternative of
+    // every query term. The term frequencies are read from their own section of the chunks,
+    // no position is decoded
+    pub fn score_docs(
+        &mut self,
+        query_metadata: &[Vec<&MapInMemoryDictPointer>],
+        no_of_docs: u32,
+        doc_ids: &[u32],
+    ) -> io::Result<Vec<f32>> {
+        let doc_indices: HashMap<u32, usize> =
+            doc_ids.iter().enumerate().map(|(index, doc_id)| (*doc_id, index)).collect();
+        let mut scores = vec![0.0; doc_ids.len()];
+        let mut chunk_doc_ids = [0; MAX_POSTINGS_PER_CHUNK];
+        let mut term_frequencies = [0; MAX_POSTINGS_PER_CHUNK];
+        for term in query_metadata.iter().flatten() {
+            let inverse_document_frequency =
+                get_inverse_document_frequency(term.term_frequency as f32, no_of_docs);
+            let blocks = self.read_blocks_for_term(&term.block_ids, term.term_id)?;
+            for chunk in Self::get_chunks_for_term(&blocks, term.term_id)? {
+                let no_of_postings = chunk.read_doc_ids(&mut chunk_doc_ids)?;
+                chunk.read_term_frequencies(&mut term_frequencies)?;
+                for (doc_id, term_frequency) in
+                    chunk_doc_ids[..no_of_postings].iter().zip(&term_frequencies)
+                {
+                    if let Some(index) = doc_indices.get(doc_id) {
+                        scores[*index] +=
+                            get_tf_idf_weight(*term_frequency as f32, inverse_document_frequency);
+                    }
+                }
+            }
+        }
+        Ok(scores)
     }
+
+    // every entry of query_metadata holds the alternatives for one query term, usually the
+    // same word in different fields. Returns the sorted doc ids of the documents containing
+    // at least one alternative of every query term, documents that have been deleted are
+    // never returned
     pub fn process_query(
         &mut self,
-        query_terms: Vec<String>,
-        query_metadata: Vec<&MapInMemoryDictPointer>,
-    ) {
-        let mut min_frequency_term_index = query_terms.len();
+        query_metadata: Vec<Vec<&MapInMemoryDictPointer>>,
+        live_docs: &LiveDocs,
+    ) -> io::Result<Vec<u32>> {
+        if query_metadata.is_empty() || query_metadata.iter().any(|terms| terms.is_empty()) {
+            return Ok(Vec::new());
+        }
+        let mut min_frequency_term_index = 0;
         let mut min_doc_frequency = u32::MAX;
         for i in 0..query_metadata.len() {
-            if query_metadata[i].term_frequency < min_doc_frequency {
+            let doc_frequency: u32 = query_metadata[i].iter().map(|term| term.term_frequency).sum();
+            if doc_frequency < min_doc_frequency {
                 min_frequency_term_index = i;
-                min_doc_frequency = query_metadata[i].term_frequency;
+                min_doc_frequency = doc_frequency;
             }
         }
 
-        let mut doc_ids = self.get_doc_ids_for_term(
-            &query_metadata[min_frequency_term_index].block_ids,
-            query_metadata[min_frequency_term_index].term_id,
-        );
+        let mut doc_ids = HashSet::new();
+        for term in &query_metadata[min_frequency_term_index] {
+            doc_ids.extend(self.get_doc_ids_for_term(&term.block_ids, term.term_id)?);
+        }
+        doc_ids.retain(|doc_id| live_docs.is_live(*doc_id));
         for i in 0..query_metadata.len() {
             if i != min_frequency_term_index {
-                self.intersect(
-                    &query_metadata[i].block_ids,
-                    query_metadata[i].term_id,
-                    &mut doc_ids,
-                )
+                self.intersect(&query_metadata[i], &mut doc_ids)?
             }
         }
+        let mut doc_ids: Vec<u32> = doc_ids.into_iter().collect();
+        doc_ids.sort();
+        Ok(doc_ids)
     }
 }
@@ -1,3 +1,5 @@
+pub mod page_rank;
+
 use crate::dictionary::Posting;
 
 use std::collections::BinaryHeap;
@@ -35,10 +37,6 @@ pub fn get_document_frequency(posting:&Vec<Posting>)->f32{
     posting.len() as f32
 }
 // can get during processing
-pub fn get_term_frequency(posting:&Posting)->f32{
-    posting.positions.len() as f32
-}
-// can get during processing
 pub fn get_inverse_document_frequency(document_frequency:f32,total_documents:u32)->f32{
     f32::log10((total_documents as f32)/(document_frequency as f32))
 }
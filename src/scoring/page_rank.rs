@@ -0,0 +1,140 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::indexer::{
+    helper::{read_u32, write_u32},
+    link_graph::LinkGraph,
+};
+
+pub const PAGE_RANK_FILE_NAME: &str = "page_rank.dat";
+
+const DAMPING_FACTOR: f64 = 0.85;
+const MAX_ITERATIONS: u32 = 100;
+// the iteration stops once the scores change by less than this in total
+const TOLERANCE: f64 = 1e-9;
+
+/*
+Static score of every document by the links pointing to it, returned by
+doc id with index 0 unused.
+
+Every iteration a document passes DAMPING_FACTOR of its rank on to the
+documents it links to, split evenly among them, and the rest is spread over
+all documents. The rank of documents without links is spread over all
+documents as well, so no rank is lost. The scores are scaled to an average
+of 1, a document nobody links to ends up below 1 and well linked documents
+above it, independent of the number of documents in the segment.
+ */
+pub fn compute_page_rank(link_graph: &LinkGraph) -> Vec<f32> {
+    let no_of_docs = link_graph.get_no_of_docs() as usize;
+    if no_of_docs == 0 {
+        return vec![0.0];
+    }
+    let mut ranks = vec![1.0 / no_of_docs as f64; no_of_docs + 1];
+    ranks[0] = 0.0;
+    for _ in 0..MAX_ITERATIONS {
+        let mut dangling_rank = 0.0;
+        let mut next_ranks = vec![0.0; no_of_docs + 1];
+        for (doc_id, rank) in ranks.iter().enumerate().skip(1) {
+            let links = link_graph.get_links(doc_id as u32);
+            if links.is_empty() {
+                dangling_rank += rank;
+                continue;
+            }
+            let share = rank / links.len() as f64;
+            for target_doc_id in links {
+                next_ranks[*target_doc_id as usize] += share;
+            }
+        }
+        let base_rank = ((1.0 - DAMPING_FACTOR) + DAMPING_FACTOR * dangling_rank) / no_of_docs as f64;
+        let mut change = 0.0;
+        for (next_rank, rank) in next_ranks.iter_mut().zip(&ranks).skip(1) {
+            *next_rank = base_rank + DAMPING_FACTOR * *next_rank;
+            change += (*next_rank - rank).abs();
+        }
+        ranks = next_ranks;
+        if change < TOLERANCE {
+            break;
+        }
+    }
+    ranks
+        .into_iter()
+        .map(|rank| (rank * no_of_docs as f64) as f32)
+        .collect()
+}
+
+/*
+File Layout->
+no_of_docs
+score (f32, one per doc id from 1 to no_of_docs)
+ */
+pub fn write_page_rank(path: &Path, scores: &[f32]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_u32(&mut writer, scores.len().saturating_sub(1) as u32)?;
+    for score in scores.iter().skip(1) {
+        writer.write_all(&score.to_le_bytes())?;
+    }
+    writer.flush()
+}
+
+// returns the scores by doc id, None for a segment written before links were extracted
+pub fn read_page_rank(path: &Path) -> io::Result<Option<Vec<f32>>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let mut reader = BufReader::new(File::open(path)?);
+    let no_of_docs = read_u32(&mut reader)?;
+    let mut scores = Vec::with_capacity(no_of_docs as usize + 1);
+    scores.push(0.0);
+    let mut buf = [0u8; 4];
+    for _ in 0..no_of_docs {
+        reader.read_exact(&mut buf)?;
+        scores.push(f32::from_le_bytes(buf));
+    }
+    Ok(Some(scores))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_linked_documents_rank_higher() {
+        // 2, 3 and 4 all link to 1, 1 links back to 2
+        let mut link_graph = LinkGraph::new(4);
+        link_graph.add_link(2, 1);
+        link_graph.add_link(3, 1);
+        link_graph.add_link(4, 1);
+        link_graph.add_link(1, 2);
+        let scores = compute_page_rank(&link_graph);
+
+        assert!(scores[1] > scores[2]);
+        assert!(scores[2] > scores[3]);
+        assert!((scores[3] - scores[4]).abs() < 1e-6);
+        let average: f32 = scores[1..].iter().sum::<f32>() / 4.0;
+        assert!((average - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_without_links_every_document_scores_one() {
+        let scores = compute_page_rank(&LinkGraph::new(3));
+        for score in &scores[1..] {
+            assert!((score - 1.0).abs() < 1e-6);
+        }
+        assert_eq!(compute_page_rank(&LinkGraph::new(0)), vec![0.0]);
+    }
+
+    #[test]
+    fn test_page_rank_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(PAGE_RANK_FILE_NAME);
+        assert_eq!(read_page_rank(&path).unwrap(), None);
+
+        let scores = vec![0.0, 1.5, 0.25, 1.25];
+        write_page_rank(&path, &scores).unwrap();
+        assert_eq!(read_page_rank(&path).unwrap(), Some(scores));
+    }
+}
@@ -1,17 +1,44 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    fs,
     io::{self, Error, ErrorKind},
     path::Path,
+    sync::Arc,
+    thread::{self, JoinHandle},
 };
 
 use crate::{
-    dictionary::{ Posting},
-    indexer::indexer::Indexer,
-    query_parser::tokenizer::SearchTokenizer,
+    compressors::posting_codec::PostingCodecId,
+    document_source::{Document, DocumentSource, wikipedia_source::WikipediaBz2Source},
+    highlighter::Highlighter,
+    indexer::{
+        build_statistics::BuildStatistics, checkpoint::CHECKPOINT_FILE_NAME, checksum::as_corruption_error, doc_id_order::DocIdOrder, document_metadata::DocumentMetadata, field::Field,
+        indexer::Indexer, near_duplicate::DuplicateHandling,
+    },
+    query_parser::{
+        field_query::{QueryTerm, parse_field_query},
+        tokenizer::SearchTokenizer,
+    },
+    segment::{
+        merge_policy::TieredMergePolicy,
+        segment_reader::{SegmentReader, VerifyReport},
+        segment_set::SegmentSet,
+    },
 };
 
+// how much the PageRank of a document adds to its tf-idf score, the static scores average 1
+// per segment so with the default an average document gains 0.5
+pub const DEFAULT_STATIC_SCORE_WEIGHT: f32 = 0.5;
+
 pub struct QueryResult {
-    doc_ids: Vec<u32>,
+    // matching doc ids, best scoring first
+    pub doc_ids: Vec<u32>,
+    // ranking score of every doc id in doc_ids, in the same order: the tf-idf score, with
+    // the document frequencies of the segment holding the document, plus the static score
+    // weighted by the static score weight of the engine
+    pub scores: Vec<f32>,
+    // highlighted excerpt of the body for the top ranked results, keyed by doc id
+    pub snippets: HashMap<u32, String>,
 }
 
 pub struct SearchEngine {
@@ -19,32 +46,197 @@ pub struct SearchEngine {
     query_parser: SearchTokenizer,
     indexer: Indexer,
     index_directory_path: String,
+    // shared with the background merge thread
+    segment_set: Arc<SegmentSet>,
+    merge_policy: TieredMergePolicy,
+    highlighter: Highlighter,
+    // leaves out of query results the near-duplicates of documents that are results as well
+    collapse_duplicates: bool,
+    static_score_weight: f32,
 }
 
 impl SearchEngine {
-    pub fn new(index_directory_path: String) -> Result<Self, Error> {
+    // the index directory stays locked until the engine is dropped, a second engine on the
+    // same directory, in this process or another one, fails with ErrorKind::WouldBlock
+    pub fn new(
+        index_directory_path: String,
+        document_source: Box<dyn DocumentSource>,
+    ) -> Result<Self, Error> {
         let path = Path::new(&index_directory_path);
         if !path.exists() || !path.is_dir() {
             return Err(Error::new(ErrorKind::Other, "index directory path does not exist, please initialize it "));
         }
         let query_parser=SearchTokenizer::new()?;
-        let mut indexer=Indexer::new(query_parser.clone())?;
-        indexer.set_index_directory(index_directory_path.clone());
+        let indexer=Indexer::new(query_parser.clone(), document_source)?;
+        let segment_set = Arc::new(SegmentSet::open(path)?);
 
         Ok(Self {
             index_directory_path:index_directory_path,
             query_parser:query_parser,
             indexer:indexer,
+            segment_set,
+            merge_policy: TieredMergePolicy::default(),
+            highlighter: Highlighter::default(),
+            collapse_duplicates: false,
+            static_score_weight: DEFAULT_STATIC_SCORE_WEIGHT,
         })
     }
 
-    pub fn build_index(&mut self) -> Result<(), io::Error> {
-        self.indexer.index()?;
-        Ok(())
+    // opens an index built by a previous process, only the lexicons and the document
+    // metadata are loaded into memory, the postings are read from the segments per query
+    pub fn open(index_directory_path: String) -> Result<Self, Error> {
+        let search_engine =
+            Self::new(index_directory_path, Box::new(WikipediaBz2Source::new()))?;
+        if search_engine.get_no_of_segments() == 0 {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("no index found in {}", search_engine.index_directory_path),
+            ));
+        }
+        Ok(search_engine)
+    }
+
+    // builds the index from scratch, every existing segment is removed first
+    pub fn build_index(
+        &mut self,
+        corpus_directory_path: &Path,
+    ) -> Result<BuildStatistics, io::Error> {
+        self.segment_set.clear()?;
+        self.add_documents(corpus_directory_path)
     }
 
-    pub fn set_index_directory_path(& mut self,index_directory_path: String){
+    // indexes the documents of the corpus directory into a new segment, the existing
+    // segments are left untouched
+    // the segment is built under a temporary name, a build that fails after writing a
+    // checkpoint leaves it behind and the next call continues it if it is for the same corpus
+    pub fn add_documents(
+        &mut self,
+        corpus_directory_path: &Path,
+    ) -> Result<BuildStatistics, io::Error> {
+        let resumable_directory = self.segment_set.find_resumable_segment()?;
+        let segment_number = self.segment_set.allocate_segment_number();
+        let temporary_directory = resumable_directory
+            .unwrap_or_else(|| self.segment_set.get_temporary_segment_directory(segment_number));
+        fs::create_dir_all(&temporary_directory)?;
+
+        self.indexer
+            .set_index_directory(temporary_directory.display().to_string());
+        let statistics = match self.indexer.index(corpus_directory_path) {
+            Ok(statistics) => statistics,
+            Err(e) => {
+                if !temporary_directory.join(CHECKPOINT_FILE_NAME).is_file() {
+                    let _ = fs::remove_dir_all(&temporary_directory);
+                }
+                return Err(e);
+            }
+        };
+        if self.indexer.get_no_of_docs() == 0 {
+            // nothing to search in, so there is no point in keeping an empty segment around
+            fs::remove_dir_all(&temporary_directory)?;
+            return Ok(statistics);
+        }
+        // a resumed segment takes the new number rather than its old one, so it stays
+        // after every segment added since it was started
+        let segment_directory = self.segment_set.get_segment_directory(segment_number);
+        fs::rename(&temporary_directory, &segment_directory)?;
+        self.segment_set.add_segment(&segment_directory)?;
+        Ok(statistics)
+    }
+
+    // selects how the corpus files passed to build_index and add_documents are read
+    pub fn set_document_source(&mut self, document_source: Box<dyn DocumentSource>) {
+        self.indexer.set_document_source(document_source);
+    }
+
+    pub fn set_index_directory_path(& mut self,index_directory_path: String) -> Result<(), io::Error> {
+        self.segment_set = Arc::new(SegmentSet::open(Path::new(&index_directory_path))?);
         self.index_directory_path=index_directory_path;
+        Ok(())
+    }
+
+    // how many megabytes of postings a build holds in memory before it writes them to a run
+    // file, larger budgets mean fewer runs to merge
+    pub fn set_ram_budget_mb(&mut self, ram_mb: usize) {
+        self.indexer.set_ram_budget_mb(ram_mb);
+    }
+
+    // number of threads reading and tokenizing corpus files during a build
+    pub fn set_no_of_workers(&mut self, no_of_workers: usize) {
+        self.indexer.set_no_of_workers(no_of_workers);
+    }
+
+    // number of threads inverting documents into run files during a build, they share the
+    // ram budget
+    pub fn set_no_of_spimi_workers(&mut self, no_of_spimi_workers: usize) {
+        self.indexer.set_no_of_spimi_workers(no_of_spimi_workers);
+    }
+
+    // the most run files a build merges at once, also bounds the files it keeps open
+    pub fn set_merge_fan_in(&mut self, merge_fan_in: usize) {
+        self.indexer.set_merge_fan_in(merge_fan_in);
+    }
+
+    // number of documents indexed between two checkpoints of a build
+    pub fn set_checkpoint_interval(&mut self, checkpoint_interval: u32) {
+        self.indexer.set_checkpoint_interval(checkpoint_interval);
+    }
+
+    // whether a build looks for near-duplicates of the documents it indexed before, and
+    // skips them or marks them as duplicates. Documents of other builds, segments already
+    // in the index included, are never compared
+    pub fn set_duplicate_handling(&mut self, duplicate_handling: DuplicateHandling) {
+        self.indexer.set_duplicate_handling(duplicate_handling);
+    }
+
+    pub fn set_similarity_threshold(&mut self, similarity_threshold: f64) {
+        self.indexer.set_similarity_threshold(similarity_threshold);
+    }
+
+    // the order the documents of a build are numbered in, similar documents with close doc
+    // ids make the posting lists smaller
+    pub fn set_doc_id_order(&mut self, doc_id_order: DocIdOrder) {
+        self.indexer.set_doc_id_order(doc_id_order);
+    }
+
+    // the codec the doc ids of new segments are encoded with, build with each one to compare
+    // their doc_id_stream_bytes and index_size_in_bytes on a corpus. A merge keeps the codec
+    // of the newest segment it merges
+    pub fn set_posting_codec(&mut self, posting_codec: PostingCodecId) {
+        self.indexer.set_posting_codec(posting_codec);
+    }
+
+    // with collapsing, a document marked as near-duplicate is not returned when the document
+    // it duplicates is returned by the same query
+    pub fn set_collapse_duplicates(&mut self, collapse_duplicates: bool) {
+        self.collapse_duplicates = collapse_duplicates;
+    }
+
+    // how much the PageRank of a document counts in the scores of handle_query next to tf-idf,
+    // 0 ranks by tf-idf alone. Documents of segments without a link graph get no static score
+    pub fn set_static_score_weight(&mut self, static_score_weight: f32) {
+        self.static_score_weight = static_score_weight;
+    }
+
+    pub fn set_merge_policy(&mut self, merge_policy: TieredMergePolicy) {
+        self.merge_policy = merge_policy;
+    }
+
+    // sets the markers, the passage length and for how many results snippets are built
+    pub fn set_highlighter(&mut self, highlighter: Highlighter) {
+        self.highlighter = highlighter;
+    }
+
+    pub fn get_no_of_segments(&self) -> usize {
+        self.segment_set.get_no_of_segments()
+    }
+
+    // number of documents that can be returned by queries, deleted documents not included
+    pub fn get_no_of_docs(&self) -> u32 {
+        self.segment_set
+            .get_segments()
+            .iter()
+            .map(|segment| segment.get_live_docs().get_no_of_live_docs())
+            .sum()
     }
 
     // pub fn get_postings_from_index(
@@ -54,162 +246,1133 @@ impl SearchEngine {
 
     // }
 
-    fn get_scores_for_docs(&self,no_of_docs:u32, query_terms: &HashMap<String, (u16, Vec<Posting>)>) -> Vec<f32> {
-        let scores: Vec<f32> = vec![0.0; no_of_docs as usize];
-        // for (_, (_, posting_list)) in query_terms {
-        //     let df: f32 = get_document_frequency(posting_list);
-        //     for posting in posting_list {
-        //         let tf = get_term_frequency(posting);
-        //         let idf = get_inverse_document_frequency(df, self.no_of_docs);
-        //         let weight = get_tf_idf_weight(tf, idf);
-        //         scores[posting.doc_id as usize] = scores[posting.doc_id as usize] + weight;
-        //     }
-        // }
-        scores
-    }
-    pub fn handle_query(&self, query: String) -> Result<(), io::Error> {
-        let token_query_result = self.query_parser.tokenize_query(query);
-        if token_query_result.is_err() {
+    // returns the documents that contain every term of the query, searching every segment.
+    // A term can be restricted to a field, "title:rust" only matches documents with rust in
+    // their title, a term without a field matches in any field
+    pub fn handle_query(&self, query: String) -> Result<QueryResult, io::Error> {
+        let query_result = parse_field_query(&self.query_parser, &query);
+        if query_result.is_err() {
             return Err(io::Error::new(io::ErrorKind::Unsupported, "error"));
         }
 
-        let tokens = token_query_result.unwrap();
-
-        // let mut unigram_posting_offsets: Vec<PostingOffset> = Vec::new();
-        // let mut bigram_posting_offsets: Vec<PostingOffset> = Vec::new();
-
-        // let mut unigram_words = Vec::new();
-        // let mut bigram_words = Vec::new();
-
-        // for token in &tokens.unigram {
-        //     // let posting_offset = self.in_memory_index.lock().unwrap().find(&token.word);
-        //     let word = &token.word;
-        //     unigram_words.push(word.clone());
-        //     // unigram_posting_offsets.push(PostingOffset {
-        //     //     word: word.clone(),
-        //     //     posting_offset: posting_offset,
-        //     // });
-        // }
-
-        // for token in &tokens.bigram {
-        //     // let posting_offset = self.in_memory_index.lock().unwrap().find(&token.word);
-        //     let word = &token.word;
-        //     bigram_words.push(word.clone());
-        //     // bigram_posting_offsets.push(PostingOffset {
-        //     //     word: word.clone(),
-        //     //     posting_offset: posting_offset,
-        //     // });
-        // }
-
-        // let unigram_postings_list: HashMap<String, (u16, Vec<Posting>)> =
-        //     self.get_postings_from_index(&unigram_posting_offsets)?;
-        // let bigram_postings_list: HashMap<String, (u16, Vec<Posting>)> =
-        //     self.get_postings_from_index(&bigram_posting_offsets)?;
-
-        // let unigram_postings_offsets_copy: Vec<PostingOffset> = unigram_posting_offsets.clone();
-        // let unigram_postings_list_copy: HashMap<String, (u16, Vec<Posting>)> =
-        //     unigram_postings_list.clone();
-
-        // let unigram_scores = self.get_scores_for_docs(&unigram_postings_list);
-        // let unigram_scores_copy = unigram_scores.clone();
-
-        // let bigram_scores = self.get_scores_for_docs(&bigram_postings_list);
-
-        // // Create channels for communication with each thread
-        // let (tx1, rx1) = mpsc::channel();
-        // let (tx2, rx2) = mpsc::channel();
-        // let (tx3, rx3) = mpsc::channel();
-
-        // // Thread 1: Top-K scoring with BinaryHeap
-        // let handle1 = thread::spawn(move || {
-        //     let docs =
-        //         find_documents_optimized(unigram_words, &unigram_postings_list, true);
-
-        //     let mut max_heap: BinaryHeap<ScoredDoc> = BinaryHeap::new();
-        //     for doc_id in docs {
-        //         max_heap.push(ScoredDoc {
-        //             doc_id: doc_id,
-        //             score: unigram_scores[(doc_id - 1) as usize],
-        //         });
-        //     }
-
-        //     let k = 2;
-        //     let mut top_k = Vec::new();
-        //     for _ in 0..k.min(max_heap.len()) {
-        //         if let Some(item) = max_heap.pop() {
-        //             top_k.push((item.doc_id, item.score));
-        //         }
-        //     }
-
-        //     tx1.send(top_k).unwrap();
-        // });
-
-        // // Thread 2: Simple sorting approach (alternative ranking)
-        // let handle2 = thread::spawn(move || {
-        //     let docs =
-        //         find_documents_optimized(bigram_words, &bigram_postings_list, false);
-
-        //     let mut max_heap: BinaryHeap<ScoredDoc> = BinaryHeap::new();
-        //     for doc_id in docs {
-        //         max_heap.push(ScoredDoc {
-        //             doc_id: doc_id,
-        //             score: bigram_scores[(doc_id - 1) as usize],
-        //         });
-        //     }
-
-        //     let k = 2;
-        //     let mut top_k = Vec::new();
-        //     for _ in 0..k.min(max_heap.len()) {
-        //         if let Some(item) = max_heap.pop() {
-        //             top_k.push((item.doc_id, item.score));
-        //         }
-        //     }
-
-        //     tx2.send(top_k).unwrap();
-        // });
-
-        // // Thread 3: Statistical analysis of scores
-        // let handle3 = thread::spawn(move || {
-        //     // let docs = find_documents_optimized(
-        //     //     &unigram_postings_offsets_copy,
-        //     //     &unigram_postings_list_copy,
-        //     //     false,
-        //     // );
-
-        //     let mut max_heap: BinaryHeap<ScoredDoc> = BinaryHeap::new();
-        //     // for doc_id in docs {
-        //     //     max_heap.push(ScoredDoc {
-        //     //         doc_id: doc_id,
-        //     //         score: unigram_scores_copy[(doc_id - 1) as usize],
-        //     //     });
-        //     // }
-
-        //     let k = 2;
-        //     let mut top_k = Vec::new();
-        //     for _ in 0..k.min(max_heap.len()) {
-        //         if let Some(item) = max_heap.pop() {
-        //             top_k.push((item.doc_id, item.score));
-        //         }
-        //     }
-
-        //     tx3.send(top_k).unwrap();
-        // });
-
-        // // Wait for all threads to complete and collect results
-        // let mut phrase_docs: Vec<(u32, f32)> = rx1.recv().unwrap();
-        // let mut bigram_docs: Vec<(u32, f32)> = rx2.recv().unwrap();
-        // let mut unigram_docs: Vec<(u32, f32)> = rx3.recv().unwrap();
-
-        // let mut query_result: Vec<(u32, f32)> = Vec::new();
-        // query_result.append(&mut phrase_docs);
-        // query_result.append(&mut bigram_docs);
-        // query_result.append(&mut unigram_docs);
-
-        // // Optional: Join handles to ensure threads completed properly
-        // handle1.join().unwrap();
-        // handle2.join().unwrap();
-        // handle3.join().unwrap();
+        let terms = query_result.unwrap();
 
-        Ok(())
+        let segments = self.segment_set.get_segments();
+        // (doc id, score, segment index, doc id within the segment) of every match
+        let mut hits = Vec::new();
+        let mut doc_base = 0;
+        for (segment_index, segment) in segments.iter().enumerate() {
+            let segment_doc_ids = segment.search(&terms)?;
+            let matching_doc_ids: HashSet<u32> = segment_doc_ids.iter().copied().collect();
+            let segment_scores = segment.score_docs(&terms, &segment_doc_ids)?;
+            for (doc_id, score) in segment_doc_ids.into_iter().zip(segment_scores) {
+                if self.collapse_duplicates
+                    && let Some(duplicate_of) = segment.get_duplicate_of(doc_id)
+                    && matching_doc_ids.contains(&duplicate_of)
+                {
+                    continue;
+                }
+                let static_score = segment.get_static_score(doc_id).unwrap_or(0.0);
+                let score = score + self.static_score_weight * static_score;
+                hits.push((doc_base + doc_id, score, segment_index, doc_id));
+            }
+            doc_base += segment.get_no_of_docs();
+        }
+        // best score first, ties keep doc id order so the ranking is deterministic
+        hits.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        let mut snippets = HashMap::new();
+        for &(doc_id, _, segment_index, segment_doc_id) in &hits {
+            if snippets.len() >= self.highlighter.get_max_snippets() {
+                break;
+            }
+            let segment = &segments[segment_index];
+            if let Some(snippet) = self.build_snippet(segment, segment_doc_id, &terms)? {
+                snippets.insert(doc_id, snippet);
+            }
+        }
+        let doc_ids = hits.iter().map(|hit| hit.0).collect();
+        let scores = hits.iter().map(|hit| hit.1).collect();
+        Ok(QueryResult { doc_ids, scores, snippets })
+    }
+
+    // highlights the query terms that can occur in the body, the positions come from the
+    // postings so they match the lemmatized words the document was indexed with
+    fn build_snippet(
+        &self,
+        segment: &SegmentReader,
+        doc_id: u32,
+        terms: &[QueryTerm],
+    ) -> Result<Option<String>, io::Error> {
+        let Some(document) = segment.get_document(doc_id)? else {
+            return Ok(None);
+        };
+        let mut term_positions = Vec::new();
+        for term in terms {
+            if term.field.is_none() || term.field == Some(Field::Body) {
+                term_positions.push(segment.get_positions(&Field::Body.term(&term.word), doc_id)?);
+            }
+        }
+        Ok(Some(self.highlighter.highlight(&document.body, &term_positions)))
+    }
+
+    // maps a doc id returned by handle_query to the segment holding the document and the
+    // doc id inside that segment
+    fn find_segment_for_doc(
+        segments: &[Arc<SegmentReader>],
+        doc_id: u32,
+    ) -> Option<(&Arc<SegmentReader>, u32)> {
+        let mut doc_base = 0;
+        for segment in segments {
+            if doc_id > doc_base && doc_id <= doc_base + segment.get_no_of_docs() {
+                return Some((segment, doc_id - doc_base));
+            }
+            doc_base += segment.get_no_of_docs();
+        }
+        None
+    }
+
+    pub fn get_document_metadata(&self, doc_id: u32) -> Option<DocumentMetadata> {
+        self.segment_set.with_segments(|segments| {
+            let (segment, segment_doc_id) = Self::find_segment_for_doc(segments, doc_id)?;
+            segment.get_document_metadata(segment_doc_id).cloned()
+        })
+    }
+
+    // the PageRank of a document returned by handle_query, added to its tf-idf score with the
+    // static score weight when ranking. None if there is no such document, it has been
+    // deleted or its segment has no link graph
+    pub fn get_static_score(&self, doc_id: u32) -> Option<f32> {
+        self.segment_set.with_segments(|segments| {
+            let (segment, segment_doc_id) = Self::find_segment_for_doc(segments, doc_id)?;
+            segment.get_static_score(segment_doc_id)
+        })
+    }
+
+    // the doc id of the document a document returned by handle_query is a near-duplicate of,
+    // None if it is not marked as one or the original has been deleted
+    pub fn get_duplicate_of(&self, doc_id: u32) -> Option<u32> {
+        self.segment_set.with_segments(|segments| {
+            let (segment, segment_doc_id) = Self::find_segment_for_doc(segments, doc_id)?;
+            let doc_base = doc_id - segment_doc_id;
+            Some(doc_base + segment.get_duplicate_of(segment_doc_id)?)
+        })
+    }
+
+    // the stored title, url and body of a document returned by handle_query, None if there
+    // is no such document or it has been deleted
+    pub fn get_document(&self, doc_id: u32) -> Result<Option<Document>, io::Error> {
+        let segment = self.segment_set.with_segments(|segments| {
+            Self::find_segment_for_doc(segments, doc_id)
+                .map(|(segment, segment_doc_id)| (segment.clone(), segment_doc_id))
+        });
+        match segment {
+            Some((segment, segment_doc_id)) => segment.get_document(segment_doc_id),
+            None => Ok(None),
+        }
+    }
+
+    // deletes the document with a doc id returned by handle_query, the deletion is persisted
+    // right away and the document is no longer returned by queries. Doc ids of the other
+    // documents do not change until segments are merged. Returns false if there is no such
+    // document or it was already deleted
+    pub fn delete_document(&self, doc_id: u32) -> Result<bool, io::Error> {
+        self.segment_set.with_segments(|segments| {
+            match Self::find_segment_for_doc(segments, doc_id) {
+                Some((segment, segment_doc_id)) => segment.delete_document(segment_doc_id),
+                None => Ok(false),
+            }
+        })
+    }
+
+    // deletes every document whose id in the corpus matches doc_external_id, in all
+    // segments, and returns how many documents were deleted
+    pub fn delete_documents_by_external_id(
+        &self,
+        doc_external_id: &str,
+    ) -> Result<u32, io::Error> {
+        self.segment_set.with_segments(|segments| {
+            let mut no_of_deleted_docs = 0;
+            for segment in segments {
+                for doc_id in segment.find_doc_ids_by_external_id(doc_external_id) {
+                    if segment.delete_document(doc_id)? {
+                        no_of_deleted_docs += 1;
+                    }
+                }
+            }
+            Ok(no_of_deleted_docs)
+        })
+    }
+
+    // runs the merges the merge policy asks for on the calling thread and returns the number
+    // of merges that were run. Doc ids handed out before a merge are no longer valid after it
+    pub fn maybe_merge_segments(&self) -> Result<u32, io::Error> {
+        self.segment_set.maybe_merge(&self.merge_policy)
+    }
+
+    // same as maybe_merge_segments but on a background thread, queries keep running against
+    // the current segments and see the merged segment once the merge is done
+    pub fn start_background_merge(&self) -> JoinHandle<Result<u32, io::Error>> {
+        let segment_set = self.segment_set.clone();
+        let merge_policy = self.merge_policy.clone();
+        thread::spawn(move || segment_set.maybe_merge(&merge_policy))
+    }
+
+    // rewrites every segment that has deleted documents without them. The rewritten segment
+    // is built under a temporary name and only replaces the old one once it is complete, so
+    // a failure leaves the old segment in place. Doc ids handed out before this call are
+    // no longer valid afterwards
+    pub fn expunge_deletes(&self) -> Result<(), io::Error> {
+        self.segment_set.expunge_deletes()
+    }
+
+    // checks every block of every segment against its checksum and the lexicon, see
+    // SegmentReader::verify. Corruption is returned as an error of kind InvalidData that
+    // holds a CorruptionError::InFile with the path of the corrupt final.idx
+    pub fn verify(&self) -> Result<VerifyReport, io::Error> {
+        let mut report = VerifyReport::default();
+        for segment in self.segment_set.get_segments() {
+            match segment.verify() {
+                Ok(segment_report) => report.add(&segment_report),
+                Err(error) => {
+                    // the corruption itself does not know which file it was found in
+                    return Err(match as_corruption_error(&error) {
+                        Some(corruption) => {
+                            corruption.clone().in_file(segment.get_index_file_path()).into()
+                        }
+                        None => error,
+                    });
+                }
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        document_source::plain_text_source::PlainTextSource,
+        indexer::{
+            build_statistics::BUILD_STATISTICS_FILE_NAME,
+            checksum::CorruptionError,
+        },
+        segment::segment_set::WRITE_LOCK_FILE_NAME,
+    };
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn create_test_corpus(documents: &[(&str, &str)]) -> TempDir {
+        let corpus_directory = TempDir::new().unwrap();
+        for (name, body) in documents {
+            fs::write(corpus_directory.path().join(format!("{}.txt", name)), body).unwrap();
+        }
+        corpus_directory
+    }
+
+    // a search engine over a new and empty index directory, which is removed once the TempDir
+    // is dropped
+    fn create_test_engine(document_source: Box<dyn DocumentSource>) -> (TempDir, SearchEngine) {
+        let index_directory = TempDir::new().unwrap();
+        let search_engine =
+            SearchEngine::new(index_directory.path().display().to_string(), document_source)
+                .unwrap();
+        (index_directory, search_engine)
+    }
+
+    fn build_test_index(corpus_directory: &TempDir) -> TempDir {
+        let (index_directory, mut search_engine) =
+            create_test_engine(Box::new(PlainTextSource::new()));
+        search_engine.build_index(corpus_directory.path()).unwrap();
+        index_directory
+    }
+
+    fn matching_titles(search_engine: &SearchEngine, query: &str) -> Vec<String> {
+        let result = search_engine.handle_query(query.to_string()).unwrap();
+        let mut titles: Vec<String> = result
+            .doc_ids
+            .iter()
+            .map(|doc_id| search_engine.get_document_metadata(*doc_id).unwrap().doc_name.clone())
+            .collect();
+        titles.sort();
+        titles
+    }
+
+    // plain text source that fails on one file, as if the build crashed while reading it
+    struct FailingSource {
+        failing_file_name: &'static str,
+    }
+
+    impl DocumentSource for FailingSource {
+        fn is_supported_file(&self, path: &Path) -> bool {
+            PlainTextSource::new().is_supported_file(path)
+        }
+
+        fn read_file(&self, path: &Path) -> io::Result<Box<dyn Iterator<Item = Document>>> {
+            if path.file_name().unwrap() == self.failing_file_name {
+                return Err(io::Error::other("simulated crash"));
+            }
+            PlainTextSource::new().read_file(path)
+        }
+    }
+
+    #[test]
+    fn test_add_documents_resumes_interrupted_build() {
+        let corpus_directory = create_test_corpus(&[
+            ("a", "apple zebra"),
+            ("b", "banana zebra"),
+            ("c", "cherry zebra"),
+            ("d", "date zebra"),
+        ]);
+        let (index_directory, mut search_engine) =
+            create_test_engine(Box::new(FailingSource { failing_file_name: "c.txt" }));
+        search_engine.set_checkpoint_interval(1);
+        assert!(search_engine.add_documents(corpus_directory.path()).is_err());
+        assert_eq!(search_engine.get_no_of_segments(), 0);
+        assert!(
+            index_directory
+                .path()
+                .join("segment_000001.tmp")
+                .join(CHECKPOINT_FILE_NAME)
+                .is_file()
+        );
+
+        // a.txt and b.txt were consumed before the crash, so they are not read again
+        search_engine.set_document_source(Box::new(FailingSource { failing_file_name: "a.txt" }));
+        search_engine.add_documents(corpus_directory.path()).unwrap();
+        assert_eq!(search_engine.get_no_of_segments(), 1);
+        assert_eq!(search_engine.get_no_of_docs(), 4);
+        assert_eq!(
+            matching_titles(&search_engine, "zebra"),
+            vec!["a", "b", "c", "d"]
+        );
+        for (doc_id, title) in [(1, "a"), (2, "b"), (3, "c"), (4, "d")] {
+            assert_eq!(search_engine.get_document_metadata(doc_id).unwrap().doc_name, title);
+        }
+
+        let segment_directory = index_directory.path().join("segment_000002");
+        assert!(segment_directory.join("final.idx").is_file());
+        assert!(!segment_directory.join(CHECKPOINT_FILE_NAME).exists());
+        assert!(!index_directory.path().join("segment_000001.tmp").exists());
+    }
+
+    #[test]
+    fn test_resumed_build_refuses_a_corrupt_run_without_decoding_it() {
+        let text: String = (0..200).map(|i| format!("word{} zebra ", i)).collect();
+        let corpus_directory =
+            create_test_corpus(&[("a", &text), ("b", &text), ("c", "cherry zebra")]);
+        let (index_directory, mut search_engine) =
+            create_test_engine(Box::new(FailingSource { failing_file_name: "c.txt" }));
+        search_engine.set_checkpoint_interval(1);
+        assert!(search_engine.add_documents(corpus_directory.path()).is_err());
+
+        // the record lengths stay intact, the middle of the largest posting list is replaced
+        // with bytes that overflow the positions if they are decoded
+        let run_directory = index_directory.path().join("segment_000001.tmp").join("runs");
+        let run_path = fs::read_dir(&run_directory).unwrap().next().unwrap().unwrap().path();
+        let mut bytes = fs::read(&run_path).unwrap();
+        let read_u32 = |bytes: &[u8], offset: usize| {
+            u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize
+        };
+        let mut offset = 4;
+        let mut largest_postings = 0..0;
+        for _ in 0..read_u32(&bytes, 0) {
+            offset += 4 + read_u32(&bytes, offset);
+            let postings = offset + 4..offset + 4 + read_u32(&bytes, offset);
+            if postings.len() > largest_postings.len() {
+                largest_postings = postings.clone();
+            }
+            offset = postings.end;
+        }
+        let middle = (largest_postings.start + largest_postings.end) / 2;
+        bytes[middle - 8..middle + 8].fill(0x7f);
+        fs::write(&run_path, bytes).unwrap();
+
+        search_engine.set_document_source(Box::new(PlainTextSource::new()));
+        let error = search_engine.add_documents(corpus_directory.path()).unwrap_err();
+        assert!(matches!(
+            as_corruption_error(&error),
+            Some(CorruptionError::RunFileChecksumMismatch { .. })
+        ));
+        assert_eq!(search_engine.get_no_of_segments(), 0);
+    }
+
+    #[test]
+    fn test_resumed_build_skips_duplicates_of_documents_indexed_before() {
+        let text: String = (0..50).map(|i| format!("word{} zebra ", i)).collect();
+        let corpus_directory = create_test_corpus(&[
+            ("a", &text),
+            ("b", "banana zebra"),
+            ("c", "cherry zebra"),
+            ("d", &text),
+        ]);
+        let (_index_directory, mut search_engine) =
+            create_test_engine(Box::new(FailingSource { failing_file_name: "c.txt" }));
+        search_engine.set_checkpoint_interval(1);
+        search_engine.set_duplicate_handling(DuplicateHandling::Skip);
+        assert!(search_engine.add_documents(corpus_directory.path()).is_err());
+
+        search_engine.set_document_source(Box::new(PlainTextSource::new()));
+        let statistics = search_engine.add_documents(corpus_directory.path()).unwrap();
+        assert_eq!(statistics.no_of_duplicates, 1);
+        assert_eq!(matching_titles(&search_engine, "zebra"), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_marked_duplicates_are_collapsed_and_survive_merges() {
+        let text: String = (0..50).map(|i| format!("word{} zebra ", i)).collect();
+        let corpus_directory = create_test_corpus(&[
+            ("a", "apple zebra"),
+            ("b", &text),
+            ("c", &text.replace("word20 ", "cherry ")),
+            ("d", &text),
+        ]);
+        let (_index_directory, mut search_engine) =
+            create_test_engine(Box::new(PlainTextSource::new()));
+        search_engine.set_duplicate_handling(DuplicateHandling::Mark);
+        search_engine.build_index(corpus_directory.path()).unwrap();
+        assert_eq!(search_engine.get_duplicate_of(3), Some(2));
+        assert_eq!(search_engine.get_duplicate_of(4), Some(2));
+        assert_eq!(search_engine.get_duplicate_of(2), None);
+
+        assert_eq!(matching_titles(&search_engine, "zebra"), vec!["a", "b", "c", "d"]);
+        search_engine.set_collapse_duplicates(true);
+        assert_eq!(matching_titles(&search_engine, "zebra"), vec!["a", "b"]);
+        // the original does not match, so its duplicate is the one returned
+        assert_eq!(matching_titles(&search_engine, "cherry"), vec!["c"]);
+
+        // the marks move along with the doc ids when the segment is merged
+        search_engine.delete_document(1).unwrap();
+        search_engine.expunge_deletes().unwrap();
+        assert_eq!(search_engine.get_duplicate_of(2), Some(1));
+        assert_eq!(search_engine.get_duplicate_of(3), Some(1));
+        assert_eq!(matching_titles(&search_engine, "zebra"), vec!["b"]);
+        // without the original the duplicates are no longer marked
+        search_engine.delete_document(1).unwrap();
+        assert_eq!(search_engine.get_duplicate_of(2), None);
+        assert_eq!(matching_titles(&search_engine, "zebra"), vec!["c", "d"]);
+    }
+
+    #[test]
+    fn test_failed_build_without_checkpoint_leaves_nothing_behind() {
+        let corpus_directory = create_test_corpus(&[("a", "apple"), ("b", "banana")]);
+        let (index_directory, mut search_engine) =
+            create_test_engine(Box::new(FailingSource { failing_file_name: "a.txt" }));
+        assert!(search_engine.add_documents(corpus_directory.path()).is_err());
+        let entries: Vec<_> = fs::read_dir(index_directory.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(entries, vec![WRITE_LOCK_FILE_NAME]);
+    }
+
+    #[test]
+    fn test_new_requires_existing_index_directory() {
+        let result = SearchEngine::new(
+            "/this/directory/does/not/exist".to_string(),
+            Box::new(PlainTextSource::new()),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_build_index_writes_artifacts_into_index_directory() {
+        let corpus_directory = create_test_corpus(&[("a", "quick brown fox")]);
+        let index_directory = build_test_index(&corpus_directory);
+
+        let segment_directory = index_directory.path().join("segment_000001");
+        let mut files: Vec<String> = fs::read_dir(segment_directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                "build_statistics.json",
+                "documents.dat",
+                "final.idx",
+                "lexicon.dat",
+                "link_graph.dat",
+                "page_rank.dat",
+                "stored_fields.dat"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_index_returns_and_writes_statistics() {
+        let corpus_directory = create_test_corpus(&[
+            ("fox", "the quick brown fox"),
+            ("dog", "the lazy brown dog"),
+        ]);
+        let (index_directory, mut search_engine) =
+            create_test_engine(Box::new(PlainTextSource::new()));
+        let statistics = search_engine.build_index(corpus_directory.path()).unwrap();
+
+        assert_eq!(statistics.no_of_documents, 2);
+        // body terms, one title term per document and the terms of the file path url
+        assert!(statistics.no_of_distinct_terms > 6);
+        assert!(statistics.no_of_postings >= statistics.no_of_distinct_terms as u64);
+        assert!(statistics.no_of_positions >= statistics.no_of_postings);
+        assert_eq!(statistics.no_of_blocks, 1);
+        assert!(statistics.no_of_runs >= 1);
+        assert!(statistics.bytes_per_posting > 0.0);
+        assert!(statistics.total_seconds >= statistics.merge_seconds);
+
+        let statistics_path = index_directory
+            .path()
+            .join("segment_000001")
+            .join(BUILD_STATISTICS_FILE_NAME);
+        assert_eq!(BuildStatistics::read_from_disk(&statistics_path).unwrap(), statistics);
+    }
+
+    #[test]
+    fn test_open_queries_existing_index_without_rebuilding() {
+        let corpus_directory = create_test_corpus(&[
+            ("fox", "the quick brown fox jumps"),
+            ("dog", "the lazy brown dog sleeps"),
+            ("cat", "a cat sleeps all day"),
+        ]);
+        let index_directory = build_test_index(&corpus_directory);
+
+        let search_engine =
+            SearchEngine::open(index_directory.path().display().to_string()).unwrap();
+        assert_eq!(matching_titles(&search_engine, "brown"), vec!["dog", "fox"]);
+        assert_eq!(matching_titles(&search_engine, "brown sleeps"), vec!["dog"]);
+        assert!(matching_titles(&search_engine, "unicorn").is_empty());
+    }
+
+    #[test]
+    fn test_results_are_scored_by_term_frequency() {
+        let corpus_directory = create_test_corpus(&[
+            ("once", "rust is a language"),
+            ("thrice", "rust rust rust everywhere"),
+            ("other", "go is a language"),
+        ]);
+        let index_directory = build_test_index(&corpus_directory);
+        let search_engine =
+            SearchEngine::open(index_directory.path().display().to_string()).unwrap();
+
+        let result = search_engine.handle_query("rust".to_string()).unwrap();
+        assert_eq!(result.doc_ids.len(), 2);
+        assert_eq!(result.scores.len(), 2);
+        let score_of = |title: &str| {
+            let index = result
+                .doc_ids
+                .iter()
+                .position(|doc_id| {
+                    search_engine.get_document_metadata(*doc_id).unwrap().doc_name == title
+                })
+                .unwrap();
+            result.scores[index]
+        };
+        assert!(score_of("once") > 0.0);
+        assert!(score_of("thrice") > score_of("once"));
+    }
+
+    #[test]
+    fn test_results_are_ranked_by_score_and_snippets_go_to_top_hits() {
+        let corpus_directory = create_test_corpus(&[
+            ("a_once", "rust is a language"),
+            ("b_twice", "rust and rust again"),
+            ("c_thrice", "rust rust rust everywhere"),
+            ("d_other", "go is a language"),
+        ]);
+        let index_directory = build_test_index(&corpus_directory);
+        let mut search_engine =
+            SearchEngine::open(index_directory.path().display().to_string()).unwrap();
+        search_engine.set_highlighter(Highlighter::new("[", "]", 6, 1));
+
+        let result = search_engine.handle_query("rust".to_string()).unwrap();
+        let titles: Vec<String> = result
+            .doc_ids
+            .iter()
+            .map(|doc_id| search_engine.get_document_metadata(*doc_id).unwrap().doc_name)
+            .collect();
+        assert_eq!(titles, vec!["c_thrice", "b_twice", "a_once"]);
+        assert!(result.scores.windows(2).all(|pair| pair[0] >= pair[1]));
+        assert_eq!(result.snippets.len(), 1);
+        assert!(result.snippets.contains_key(&result.doc_ids[0]));
+    }
+
+    #[test]
+    fn test_add_documents_writes_new_segment_and_searches_all_segments() {
+        let corpus_directory = create_test_corpus(&[
+            ("fox", "the quick brown fox jumps"),
+            ("cat", "a cat sleeps all day"),
+        ]);
+        let index_directory = build_test_index(&corpus_directory);
+        let update_directory = create_test_corpus(&[("dog", "the lazy brown dog sleeps")]);
+
+        let mut search_engine =
+            SearchEngine::open(index_directory.path().display().to_string()).unwrap();
+        search_engine.set_document_source(Box::new(PlainTextSource::new()));
+        search_engine.add_documents(update_directory.path()).unwrap();
+
+        assert_eq!(search_engine.get_no_of_segments(), 2);
+        assert!(index_directory.path().join("segment_000002").join("final.idx").is_file());
+        assert_eq!(matching_titles(&search_engine, "brown"), vec!["dog", "fox"]);
+        assert_eq!(matching_titles(&search_engine, "sleeps"), vec!["cat", "dog"]);
+
+        // doc ids of the new segment come after the ones of the first segment
+        let result = search_engine.handle_query("lazy".to_string()).unwrap();
+        assert_eq!(result.doc_ids, vec![3]);
+
+        // the index directory is locked by the engine holding it open
+        drop(search_engine);
+        let reopened = SearchEngine::open(index_directory.path().display().to_string()).unwrap();
+        assert_eq!(reopened.get_no_of_segments(), 2);
+        assert_eq!(matching_titles(&reopened, "brown"), vec!["dog", "fox"]);
+    }
+
+    #[test]
+    fn test_build_index_replaces_existing_segments() {
+        let corpus_directory = create_test_corpus(&[("fox", "the quick brown fox")]);
+        let index_directory = build_test_index(&corpus_directory);
+        let other_corpus = create_test_corpus(&[("dog", "the lazy brown dog")]);
+
+        let mut search_engine =
+            SearchEngine::open(index_directory.path().display().to_string()).unwrap();
+        search_engine.set_document_source(Box::new(PlainTextSource::new()));
+        search_engine.add_documents(other_corpus.path()).unwrap();
+        search_engine.build_index(other_corpus.path()).unwrap();
+
+        assert_eq!(search_engine.get_no_of_segments(), 1);
+        assert_eq!(matching_titles(&search_engine, "brown"), vec!["dog"]);
+    }
+
+    #[test]
+    fn test_failed_add_documents_leaves_no_segment_behind() {
+        let corpus_directory = create_test_corpus(&[("fox", "the quick brown fox")]);
+        let index_directory = build_test_index(&corpus_directory);
+
+        let mut search_engine =
+            SearchEngine::open(index_directory.path().display().to_string()).unwrap();
+        let missing_corpus = index_directory.path().join("missing");
+        assert!(search_engine.add_documents(&missing_corpus).is_err());
+
+        assert_eq!(search_engine.get_no_of_segments(), 1);
+        assert!(!index_directory.path().join("segment_000002").exists());
+    }
+
+    #[test]
+    fn test_add_documents_without_documents_adds_no_segment() {
+        let corpus_directory = create_test_corpus(&[("fox", "the quick brown fox")]);
+        let index_directory = build_test_index(&corpus_directory);
+        let empty_corpus = TempDir::new().unwrap();
+
+        let mut search_engine =
+            SearchEngine::open(index_directory.path().display().to_string()).unwrap();
+        search_engine.add_documents(empty_corpus.path()).unwrap();
+
+        assert_eq!(search_engine.get_no_of_segments(), 1);
+        assert!(!index_directory.path().join("segment_000002").exists());
+    }
+
+    fn find_doc_id(search_engine: &SearchEngine, query: &str, title: &str) -> u32 {
+        let result = search_engine.handle_query(query.to_string()).unwrap();
+        *result
+            .doc_ids
+            .iter()
+            .find(|doc_id| search_engine.get_document_metadata(**doc_id).unwrap().doc_name == title)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_deleted_document_is_not_returned_and_deletion_is_persisted() {
+        let corpus_directory = create_test_corpus(&[
+            ("fox", "the quick brown fox"),
+            ("dog", "the lazy brown dog"),
+        ]);
+        let index_directory = build_test_index(&corpus_directory);
+
+        let search_engine =
+            SearchEngine::open(index_directory.path().display().to_string()).unwrap();
+        let fox_doc_id = find_doc_id(&search_engine, "brown", "fox");
+        assert!(search_engine.delete_document(fox_doc_id).unwrap());
+        assert!(!search_engine.delete_document(fox_doc_id).unwrap());
+        assert!(!search_engine.delete_document(42).unwrap());
+        assert_eq!(search_engine.get_no_of_docs(), 1);
+
+        assert_eq!(matching_titles(&search_engine, "brown"), vec!["dog"]);
+        assert!(matching_titles(&search_engine, "quick").is_empty());
+        assert!(search_engine.get_document_metadata(fox_doc_id).is_none());
+        assert!(index_directory.path().join("segment_000001").join("live_docs.dat").is_file());
+
+        drop(search_engine);
+        let reopened = SearchEngine::open(index_directory.path().display().to_string()).unwrap();
+        assert_eq!(matching_titles(&reopened, "brown"), vec!["dog"]);
+    }
+
+    #[test]
+    fn test_delete_documents_by_external_id_across_segments() {
+        let corpus_directory = create_test_corpus(&[
+            ("fox", "the quick brown fox"),
+            ("dog", "the lazy brown dog"),
+        ]);
+        let index_directory = build_test_index(&corpus_directory);
+
+        let mut search_engine =
+            SearchEngine::open(index_directory.path().display().to_string()).unwrap();
+        search_engine.set_document_source(Box::new(PlainTextSource::new()));
+        // the same corpus file indexed a second time lands in a second segment with the
+        // same external id
+        search_engine.add_documents(corpus_directory.path()).unwrap();
+        let fox_path = corpus_directory.path().join("fox.txt").display().to_string();
+
+        assert_eq!(search_engine.delete_documents_by_external_id(&fox_path).unwrap(), 2);
+        assert_eq!(search_engine.delete_documents_by_external_id(&fox_path).unwrap(), 0);
+        assert_eq!(matching_titles(&search_engine, "brown"), vec!["dog", "dog"]);
+    }
+
+    #[test]
+    fn test_expunge_deletes_rewrites_segment_without_deleted_documents() {
+        let corpus_directory = create_test_corpus(&[
+            ("fox", "the quick brown fox"),
+            ("dog", "the lazy brown dog"),
+            ("cat", "a brown cat"),
+        ]);
+        let index_directory = build_test_index(&corpus_directory);
+
+        let search_engine =
+            SearchEngine::open(index_directory.path().display().to_string()).unwrap();
+        let fox_doc_id = find_doc_id(&search_engine, "brown", "fox");
+        search_engine.delete_document(fox_doc_id).unwrap();
+        search_engine.expunge_deletes().unwrap();
+
+        assert_eq!(search_engine.get_no_of_segments(), 1);
+        assert!(!index_directory.path().join("segment_000001").exists());
+        let segment_directory = index_directory.path().join("segment_000002");
+        assert!(segment_directory.join("final.idx").is_file());
+        assert!(!segment_directory.join("live_docs.dat").exists());
+
+        assert_eq!(matching_titles(&search_engine, "brown"), vec!["cat", "dog"]);
+        assert!(matching_titles(&search_engine, "quick").is_empty());
+        let result = search_engine.handle_query("brown".to_string()).unwrap();
+        assert_eq!(result.doc_ids, vec![1, 2]);
+
+        drop(search_engine);
+        let reopened = SearchEngine::open(index_directory.path().display().to_string()).unwrap();
+        assert_eq!(matching_titles(&reopened, "brown"), vec!["cat", "dog"]);
+    }
+
+    #[test]
+    fn test_expunge_deletes_removes_fully_deleted_segment() {
+        let corpus_directory = create_test_corpus(&[("fox", "the quick brown fox")]);
+        let index_directory = build_test_index(&corpus_directory);
+        let update_directory = create_test_corpus(&[("dog", "the lazy brown dog")]);
+
+        let mut search_engine =
+            SearchEngine::open(index_directory.path().display().to_string()).unwrap();
+        search_engine.set_document_source(Box::new(PlainTextSource::new()));
+        search_engine.add_documents(update_directory.path()).unwrap();
+        search_engine.delete_document(1).unwrap();
+        search_engine.expunge_deletes().unwrap();
+
+        assert_eq!(search_engine.get_no_of_segments(), 1);
+        assert_eq!(matching_titles(&search_engine, "brown"), vec!["dog"]);
+        assert_eq!(search_engine.handle_query("dog".to_string()).unwrap().doc_ids, vec![1]);
+    }
+
+    fn add_test_segments(search_engine: &mut SearchEngine, bodies: &[(&str, &str)]) {
+        for (name, body) in bodies {
+            let corpus_directory = create_test_corpus(&[(name, body)]);
+            search_engine.add_documents(corpus_directory.path()).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_maybe_merge_segments_merges_and_purges_deletes() {
+        let (index_directory, mut search_engine) =
+            create_test_engine(Box::new(PlainTextSource::new()));
+        search_engine.set_merge_policy(TieredMergePolicy::new(3, 10, 10));
+        add_test_segments(
+            &mut search_engine,
+            &[
+                ("fox", "the quick brown fox"),
+                ("dog", "the lazy brown dog"),
+            ],
+        );
+        assert_eq!(search_engine.maybe_merge_segments().unwrap(), 0);
+
+        add_test_segments(&mut search_engine, &[("cat", "a brown cat")]);
+        search_engine.delete_document(2).unwrap();
+        assert_eq!(search_engine.maybe_merge_segments().unwrap(), 1);
+
+        assert_eq!(search_engine.get_no_of_segments(), 1);
+        assert_eq!(search_engine.get_no_of_docs(), 2);
+        assert_eq!(matching_titles(&search_engine, "brown"), vec!["cat", "fox"]);
+        assert_eq!(search_engine.handle_query("brown".to_string()).unwrap().doc_ids, vec![1, 2]);
+
+        drop(search_engine);
+        let reopened = SearchEngine::open(index_directory.path().display().to_string()).unwrap();
+        assert_eq!(reopened.get_no_of_segments(), 1);
+        assert_eq!(matching_titles(&reopened, "brown"), vec!["cat", "fox"]);
+    }
+
+    #[test]
+    fn test_background_merge_while_searching() {
+        let (_index_directory, mut search_engine) =
+            create_test_engine(Box::new(PlainTextSource::new()));
+        search_engine.set_merge_policy(TieredMergePolicy::new(2, 10, 10));
+        add_test_segments(
+            &mut search_engine,
+            &[
+                ("fox", "the quick brown fox"),
+                ("dog", "the lazy brown dog"),
+                ("cat", "a brown cat"),
+                ("owl", "an owl that is not brown"),
+            ],
+        );
+
+        let handle = search_engine.start_background_merge();
+        while !handle.is_finished() {
+            // every query sees either the old or the merged segments, never a mix
+            assert_eq!(matching_titles(&search_engine, "brown"), vec!["cat", "dog", "fox", "owl"]);
+        }
+        assert!(handle.join().unwrap().unwrap() > 0);
+        assert_eq!(search_engine.get_no_of_segments(), 1);
+        assert_eq!(matching_titles(&search_engine, "brown"), vec!["cat", "dog", "fox", "owl"]);
+    }
+
+    #[test]
+    fn test_field_qualified_queries() {
+        let corpus_directory = create_test_corpus(&[
+            ("rust", "a systems programming language"),
+            ("ferris", "the crab is the mascot of rust"),
+        ]);
+        let index_directory = build_test_index(&corpus_directory);
+        let search_engine =
+            SearchEngine::open(index_directory.path().display().to_string()).unwrap();
+
+        // the title of a plain text document is its file name
+        assert_eq!(matching_titles(&search_engine, "title:rust"), vec!["rust"]);
+        assert_eq!(matching_titles(&search_engine, "body:rust"), vec!["ferris"]);
+        assert_eq!(matching_titles(&search_engine, "rust"), vec!["ferris", "rust"]);
+        assert_eq!(matching_titles(&search_engine, "url:txt crab"), vec!["ferris"]);
+        assert!(matching_titles(&search_engine, "title:crab").is_empty());
+
+        let doc_id = find_doc_id(&search_engine, "title:ferris", "ferris");
+        let metadata = search_engine.get_document_metadata(doc_id).unwrap();
+        assert_eq!(metadata.title_length, 1);
+        assert_eq!(metadata.doc_length, 7);
+        assert!(metadata.url_length >= 2);
+    }
+
+    #[test]
+    fn test_get_document_returns_stored_fields() {
+        let corpus_directory = create_test_corpus(&[
+            ("fox", "the quick brown fox"),
+            ("dog", "the lazy brown dog"),
+        ]);
+        let index_directory = build_test_index(&corpus_directory);
+        let search_engine =
+            SearchEngine::open(index_directory.path().display().to_string()).unwrap();
+
+        let doc_id = find_doc_id(&search_engine, "lazy", "dog");
+        let document = search_engine.get_document(doc_id).unwrap().unwrap();
+        assert_eq!(document.title, "dog");
+        assert_eq!(document.body, "the lazy brown dog");
+        assert_eq!(document.url, corpus_directory.path().join("dog.txt").display().to_string());
+
+        assert_eq!(search_engine.get_document(42).unwrap(), None);
+        search_engine.delete_document(doc_id).unwrap();
+        assert_eq!(search_engine.get_document(doc_id).unwrap(), None);
+    }
+
+    #[test]
+    fn test_query_result_has_highlighted_snippets() {
+        let corpus_directory = create_test_corpus(&[
+            ("rust", "rust is a language that is fast and keeps memory safe without a garbage collector"),
+            ("crab", "ferris the crab"),
+        ]);
+        let index_directory = build_test_index(&corpus_directory);
+        let mut search_engine =
+            SearchEngine::open(index_directory.path().display().to_string()).unwrap();
+        search_engine.set_highlighter(Highlighter::new("[", "]", 6, 10));
+
+        let result = search_engine.handle_query("memory garbage".to_string()).unwrap();
+        assert_eq!(result.doc_ids.len(), 1);
+        assert_eq!(
+            result.snippets[&result.doc_ids[0]],
+            "...[memory] safe without a [garbage] collector"
+        );
+
+        // a match in the title only still gets the start of the body as its snippet
+        let result = search_engine.handle_query("title:crab".to_string()).unwrap();
+        assert_eq!(result.snippets[&result.doc_ids[0]], "ferris the crab");
+
+        search_engine.set_highlighter(Highlighter::new("[", "]", 6, 0));
+        assert!(search_engine.handle_query("rust".to_string()).unwrap().snippets.is_empty());
+    }
+
+    // writes the articles, given as title and links of (target, anchor text), into a
+    // Wikipedia dump file, the article ids count up from 1
+    fn create_wikipedia_corpus(articles: &[(&str, Vec<(&str, &str)>)]) -> TempDir {
+        use bzip2::{Compression, write::BzEncoder};
+        use std::io::Write;
+
+        let corpus_directory = TempDir::new().unwrap();
+        let file = fs::File::create(corpus_directory.path().join("wiki_00.bz2")).unwrap();
+        let mut encoder = BzEncoder::new(file, Compression::default());
+        for (i, (title, links)) in articles.iter().enumerate() {
+            let text: String = links
+                .iter()
+                .map(|(target, anchor_text)| {
+                    format!("see <a href=\\\"{}\\\">{}</a> ", target, anchor_text)
+                })
+                .collect();
+            writeln!(
+                encoder,
+                r#"{{"id":"{}","url":"https://en.wikipedia.org/wiki?curid={}","title":"{}","text":[["{} article"],["{}"]]}}"#,
+                i + 1, i + 1, title, title, text
+            )
+            .unwrap();
+        }
+        encoder.finish().unwrap();
+        corpus_directory
+    }
+
+    fn build_wikipedia_index(corpus_directory: &TempDir) -> (SearchEngine, TempDir, BuildStatistics) {
+        let (index_directory, mut search_engine) =
+            create_test_engine(Box::new(WikipediaBz2Source::new()));
+        let statistics = search_engine.build_index(corpus_directory.path()).unwrap();
+        (search_engine, index_directory, statistics)
+    }
+
+    #[test]
+    fn test_most_linked_article_has_highest_static_score() {
+        let corpus_directory = create_wikipedia_corpus(&[
+            ("Rust", vec![("Cargo", "it"), ("Compiler", "it")]),
+            ("Cargo", vec![("Rust", "it"), ("Crate%20registry", "it")]),
+            ("Compiler", vec![("Rust", "it")]),
+            ("Crate registry", vec![("Rust", "it"), ("Cargo", "it")]),
+            ("Zebra", vec![("Rust#History", "it"), ("Unknown", "it")]),
+        ]);
+        let (search_engine, _index_directory, statistics) = build_wikipedia_index(&corpus_directory);
+        assert_eq!(statistics.no_of_links, 8);
+
+        let static_scores: Vec<f32> = (1..=5)
+            .map(|doc_id| search_engine.get_static_score(doc_id).unwrap())
+            .collect();
+        let rust_score = static_scores[0];
+        assert!(static_scores[1..].iter().all(|score| *score < rust_score));
+        // nothing links to Zebra
+        assert!(static_scores[..4].iter().all(|score| *score > static_scores[4]));
+        assert_eq!(search_engine.get_static_score(6), None);
+
+        // the graph survives a merge, without Cargo the Compiler is second to Rust
+        search_engine.delete_document(2).unwrap();
+        search_engine.expunge_deletes().unwrap();
+        let static_scores: Vec<f32> = (1..=4)
+            .map(|doc_id| search_engine.get_static_score(doc_id).unwrap())
+            .collect();
+        assert!(static_scores[1..].iter().all(|score| *score < static_scores[0]));
+        assert!(static_scores[1] > static_scores[2]);
+    }
+
+    #[test]
+    fn test_static_score_is_added_to_the_ranking_score() {
+        let corpus_directory = create_wikipedia_corpus(&[
+            ("Cargo", vec![("Rust", "it")]),
+            ("Rust", vec![("Cargo", "it")]),
+            ("Zebra", vec![("Rust", "it")]),
+        ]);
+        let (mut search_engine, _index_directory, _) = build_wikipedia_index(&corpus_directory);
+
+        // every body has "article", its idf and so the tf-idf scores are 0
+        search_engine.set_static_score_weight(0.0);
+        let result = search_engine.handle_query("article".to_string()).unwrap();
+        assert_eq!(result.doc_ids, vec![1, 2, 3]);
+        assert!(result.scores.iter().all(|score| *score == 0.0));
+
+        search_engine.set_static_score_weight(2.0);
+        let result = search_engine.handle_query("article".to_string()).unwrap();
+        // Rust is linked to most, nothing links to Zebra
+        assert_eq!(result.doc_ids, vec![2, 1, 3]);
+        for (doc_id, score) in result.doc_ids.iter().zip(&result.scores) {
+            assert_eq!(*score, 2.0 * search_engine.get_static_score(*doc_id).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_articles_match_the_anchor_text_of_links_to_them() {
+        let corpus_directory = create_wikipedia_corpus(&[
+            ("Rust", vec![("Ferris", "crab mascot")]),
+            ("Cargo", vec![("ferris", "rustacean"), ("Cargo", "package manager")]),
+            ("Ferris", vec![]),
+        ]);
+        let (search_engine, _index_directory, _) = build_wikipedia_index(&corpus_directory);
+
+        assert_eq!(matching_titles(&search_engine, "anchor:rustacean"), vec!["Ferris"]);
+        // the text of a link stays in the body of the linking article as well
+        assert_eq!(matching_titles(&search_engine, "mascot"), vec!["Ferris", "Rust"]);
+        assert_eq!(matching_titles(&search_engine, "body:mascot"), vec!["Rust"]);
+        // the anchor text of a link to the article itself is not counted
+        assert!(matching_titles(&search_engine, "anchor:package").is_empty());
+        assert_eq!(search_engine.get_document_metadata(3).unwrap().anchor_length, 3);
+        assert_eq!(search_engine.get_document_metadata(1).unwrap().anchor_length, 0);
+    }
+
+    #[test]
+    fn test_documents_are_numbered_in_title_order() {
+        let corpus_directory = create_wikipedia_corpus(&[
+            ("Zebra", vec![("Mango", "yellow fruit")]),
+            ("Mango", vec![]),
+            ("Banana", vec![("Mango", "tropical fruit"), ("Zebra", "striped animal")]),
+            ("Apple", vec![("Mango", "sweet fruit")]),
+        ]);
+        let (index_directory, mut search_engine) =
+            create_test_engine(Box::new(WikipediaBz2Source::new()));
+        search_engine.set_doc_id_order(DocIdOrder::Title);
+        let statistics = search_engine.build_index(corpus_directory.path()).unwrap();
+        assert_eq!(statistics.doc_id_order, DocIdOrder::Title);
+        assert!(statistics.doc_id_stream_bytes <= statistics.doc_id_stream_bytes_before_reordering);
+        assert_eq!(
+            statistics.index_size_in_bytes,
+            fs::metadata(index_directory.path().join("segment_000001").join("final.idx"))
+                .unwrap()
+                .len()
+        );
+        assert!(statistics.index_size_in_bytes_before_reordering > 0);
+
+        drop(search_engine);
+        let search_engine =
+            SearchEngine::open(index_directory.path().display().to_string()).unwrap();
+        for (doc_id, title) in [(1, "Apple"), (2, "Banana"), (3, "Mango"), (4, "Zebra")] {
+            assert_eq!(search_engine.get_document_metadata(doc_id).unwrap().doc_name, title);
+            assert_eq!(search_engine.get_document(doc_id).unwrap().unwrap().title, title);
+        }
+        assert_eq!(matching_titles(&search_engine, "title:zebra"), vec!["Zebra"]);
+        assert_eq!(matching_titles(&search_engine, "anchor:fruit"), vec!["Mango"]);
+        assert_eq!(matching_titles(&search_engine, "anchor:striped"), vec!["Zebra"]);
+        assert_eq!(search_engine.get_document_metadata(3).unwrap().anchor_length, 6);
+        // the link graph follows the new doc ids, everything links to Mango
+        let mango_score = search_engine.get_static_score(3).unwrap();
+        for doc_id in [1, 2, 4] {
+            assert!(search_engine.get_static_score(doc_id).unwrap() < mango_score);
+        }
+        // the reordered store replaced the one written in read order
+        assert_eq!(fs::read_dir(index_directory.path().join("segment_000001")).unwrap().count(), 7);
+    }
+
+    #[test]
+    fn test_open_without_index_fails() {
+        let index_directory = TempDir::new().unwrap();
+        assert!(SearchEngine::open(index_directory.path().display().to_string()).is_err());
+    }
+
+    #[test]
+    fn test_verify_checks_every_block_and_the_lexicon() {
+        let corpus_directory = create_test_corpus(&[
+            ("fox", "the quick brown fox"),
+            ("dog", "the lazy brown dog"),
+        ]);
+        let index_directory = build_test_index(&corpus_directory);
+        let index_file_path = index_directory.path().join("segment_000001").join("final.idx");
+        let search_engine =
+            SearchEngine::open(index_directory.path().display().to_string()).unwrap();
+        let report = search_engine.verify().unwrap();
+        assert_eq!(report.no_of_blocks, 1);
+        let statistics = BuildStatistics::read_from_disk(
+            &index_directory.path().join("segment_000001").join(BUILD_STATISTICS_FILE_NAME),
+        )
+        .unwrap();
+        assert_eq!(report.no_of_terms, statistics.no_of_distinct_terms);
+        assert_eq!(report.no_of_postings, statistics.no_of_postings);
+        assert_eq!(report.no_of_positions, statistics.no_of_positions);
+
+        // a flipped bit in the block is found by verify and by queries reading the block
+        let mut bytes = fs::read(&index_file_path).unwrap();
+        bytes[200] ^= 0x04;
+        fs::write(&index_file_path, bytes).unwrap();
+        let error = search_engine.verify().unwrap_err();
+        let Some(CorruptionError::InFile { path, corruption }) = as_corruption_error(&error) else {
+            panic!("verify returned {:?}", error);
+        };
+        assert_eq!(path, &index_file_path);
+        assert!(matches!(**corruption, CorruptionError::BlockChecksumMismatch { block_id: 0, .. }));
+        assert!(error.to_string().starts_with(&index_file_path.display().to_string()));
+        let Err(error) = search_engine.handle_query("brown".to_string()) else {
+            panic!("a query decoded a corrupt block");
+        };
+        assert!(as_corruption_error(&error).is_some());
+    }
+
+    #[test]
+    fn test_every_posting_codec_builds_the_same_index() {
+        // enough documents for a term to span several chunks
+        let documents: Vec<(String, String)> = (0..300)
+            .map(|i| {
+                let body = if i % 3 == 0 { "common rare word" } else { "common word" };
+                (format!("doc{:03}", i), body.to_string())
+            })
+            .collect();
+        let documents: Vec<(&str, &str)> = documents
+            .iter()
+            .map(|(name, body)| (name.as_str(), body.as_str()))
+            .collect();
+        let corpus_directory = create_test_corpus(&documents);
+
+        let mut doc_id_bytes = Vec::new();
+        for posting_codec in PostingCodecId::ALL {
+            let (index_directory, mut search_engine) =
+                create_test_engine(Box::new(PlainTextSource::new()));
+            search_engine.set_posting_codec(posting_codec);
+            let statistics = search_engine.build_index(corpus_directory.path()).unwrap();
+            assert_eq!(statistics.posting_codec, posting_codec);
+            doc_id_bytes.push(statistics.doc_id_stream_bytes);
+
+            drop(search_engine);
+            let search_engine =
+                SearchEngine::open(index_directory.path().display().to_string()).unwrap();
+            let segment = &search_engine.segment_set.get_segments()[0];
+            assert_eq!(segment.get_index_file_header().get_doc_id_codec(), posting_codec);
+            assert_eq!(search_engine.handle_query("common".to_string()).unwrap().doc_ids.len(), 300);
+            assert_eq!(search_engine.handle_query("rare".to_string()).unwrap().doc_ids.len(), 100);
+            assert_eq!(segment.get_positions(&Field::Body.term("rare"), 1).unwrap(), vec![1]);
+            assert_eq!(search_engine.verify().unwrap().no_of_postings, statistics.no_of_postings);
+        }
+        // mostly consecutive doc ids take a byte each with variable byte encoding, a bit or
+        // two with the Elias codes and none in a PForDelta block, the Simple codecs spend a
+        // word on every title term
+        assert!(doc_id_bytes[1] < doc_id_bytes[0]);
+        assert!(doc_id_bytes[2] < doc_id_bytes[0]);
+        assert!(doc_id_bytes[6] < doc_id_bytes[0]);
+    }
+
+    #[test]
+    fn test_merged_segment_keeps_the_posting_codec() {
+        let (_index_directory, mut search_engine) =
+            create_test_engine(Box::new(PlainTextSource::new()));
+        search_engine.set_posting_codec(PostingCodecId::GolombRice);
+        search_engine.set_merge_policy(TieredMergePolicy::new(2, 10, 10));
+        add_test_segments(
+            &mut search_engine,
+            &[("fox", "the quick brown fox"), ("dog", "the lazy brown dog")],
+        );
+        assert_eq!(search_engine.maybe_merge_segments().unwrap(), 1);
+
+        let segment = &search_engine.segment_set.get_segments()[0];
+        assert_eq!(segment.get_index_file_header().get_doc_id_codec(), PostingCodecId::GolombRice);
+        assert_eq!(matching_titles(&search_engine, "brown"), vec!["dog", "fox"]);
+    }
+
+    #[test]
+    fn test_open_refuses_index_file_of_another_format() {
+        let corpus_directory = create_test_corpus(&[("a", "quick brown fox")]);
+        let index_directory = build_test_index(&corpus_directory);
+        let index_file_path = index_directory.path().join("segment_000001").join("final.idx");
+
+        // an index written before final.idx had a header is nothing but blocks
+        let bytes = fs::read(&index_file_path).unwrap();
+        fs::write(&index_file_path, &bytes[32..bytes.len() - 24]).unwrap();
+        let Err(error) = SearchEngine::open(index_directory.path().display().to_string()) else {
+            panic!("opened an index file without header");
+        };
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(error.to_string().contains("rebuild the index"));
     }
 }
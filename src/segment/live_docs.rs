@@ -0,0 +1,192 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    indexer::helper::{read_u32, write_u32},
+    segment::manifest::sync_directory,
+};
+
+pub const LIVE_DOCS_FILE_NAME: &str = "live_docs.dat";
+
+/*
+One bit per document of a segment, a set bit means the document has not
+been deleted. Segments are immutable, so deleting a document only rewrites
+this file, the postings of the document stay in final.idx until the
+segment is merged.
+
+File Layout->
+no_of_docs
+bits (no_of_docs bits rounded up to whole u64 words, doc id 1 is bit 0)
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiveDocs {
+    no_of_docs: u32,
+    no_of_deleted_docs: u32,
+    bits: Vec<u64>,
+}
+
+impl LiveDocs {
+    pub fn new(no_of_docs: u32) -> Self {
+        let no_of_words = (no_of_docs as usize).div_ceil(64);
+        let mut bits = vec![u64::MAX; no_of_words];
+        // bits past the last document are kept clear so they are never reported as live
+        if !no_of_docs.is_multiple_of(64) {
+            bits[no_of_words - 1] = (1u64 << (no_of_docs % 64)) - 1;
+        }
+        Self {
+            no_of_docs,
+            no_of_deleted_docs: 0,
+            bits,
+        }
+    }
+
+    pub fn is_live(&self, doc_id: u32) -> bool {
+        if doc_id == 0 || doc_id > self.no_of_docs {
+            return false;
+        }
+        let bit = (doc_id - 1) as usize;
+        self.bits[bit / 64] & (1u64 << (bit % 64)) != 0
+    }
+
+    // returns false if the document does not exist or was already deleted
+    pub fn delete(&mut self, doc_id: u32) -> bool {
+        if !self.is_live(doc_id) {
+            return false;
+        }
+        let bit = (doc_id - 1) as usize;
+        self.bits[bit / 64] &= !(1u64 << (bit % 64));
+        self.no_of_deleted_docs += 1;
+        true
+    }
+
+    pub fn get_no_of_deleted_docs(&self) -> u32 {
+        self.no_of_deleted_docs
+    }
+
+    pub fn get_no_of_live_docs(&self) -> u32 {
+        self.no_of_docs - self.no_of_deleted_docs
+    }
+
+    pub fn has_deletions(&self) -> bool {
+        self.get_no_of_deleted_docs() > 0
+    }
+
+    // the file is written under a temporary name and renamed, so a crash while deleting
+    // leaves either the old or the new set of deletions and never a partial file
+    pub fn write_to_disk(&self, path: &Path) -> io::Result<()> {
+        let temporary_path = path.with_extension("tmp");
+        {
+            let file = File::create(&temporary_path)?;
+            let mut writer = BufWriter::new(file);
+            write_u32(&mut writer, self.no_of_docs)?;
+            for word in &self.bits {
+                writer.write_all(&word.to_le_bytes())?;
+            }
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+        }
+        fs::rename(&temporary_path, path)?;
+        // without syncing the segment directory a crash can undo the rename, and with it
+        // the deletes
+        match path.parent() {
+            Some(segment_directory) if !segment_directory.as_os_str().is_empty() => {
+                sync_directory(segment_directory)
+            }
+            _ => sync_directory(Path::new(".")),
+        }
+    }
+
+    // a segment without a live docs file has no deletions
+    pub fn read_from_disk(path: &Path, no_of_docs: u32) -> io::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::new(no_of_docs));
+        }
+        let file = File::open(path)?;
+        let mut reader = BufReader::new(file);
+        let stored_no_of_docs = read_u32(&mut reader)?;
+        if stored_no_of_docs != no_of_docs {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "live docs file {} is for {} documents but the segment has {}",
+                    path.display(),
+                    stored_no_of_docs,
+                    no_of_docs
+                ),
+            ));
+        }
+        let mut live_docs = Self::new(no_of_docs);
+        let mut buf = [0u8; 8];
+        for word in live_docs.bits.iter_mut() {
+            reader.read_exact(&mut buf)?;
+            *word = u64::from_le_bytes(buf);
+        }
+        let no_of_live_docs: u32 = live_docs.bits.iter().map(|word| word.count_ones()).sum();
+        live_docs.no_of_deleted_docs = no_of_docs - no_of_live_docs;
+        Ok(live_docs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_new_live_docs_are_all_live() {
+        let live_docs = LiveDocs::new(70);
+        assert!(live_docs.is_live(1));
+        assert!(live_docs.is_live(70));
+        assert!(!live_docs.is_live(0));
+        assert!(!live_docs.is_live(71));
+        assert_eq!(live_docs.get_no_of_live_docs(), 70);
+        assert!(!live_docs.has_deletions());
+    }
+
+    #[test]
+    fn test_delete() {
+        let mut live_docs = LiveDocs::new(10);
+        assert!(live_docs.delete(3));
+        assert!(!live_docs.delete(3));
+        assert!(!live_docs.delete(11));
+        assert!(!live_docs.is_live(3));
+        assert!(live_docs.is_live(4));
+        assert_eq!(live_docs.get_no_of_deleted_docs(), 1);
+        assert_eq!(live_docs.get_no_of_live_docs(), 9);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(LIVE_DOCS_FILE_NAME);
+
+        let mut live_docs = LiveDocs::new(130);
+        live_docs.delete(1);
+        live_docs.delete(64);
+        live_docs.delete(65);
+        live_docs.delete(130);
+        live_docs.write_to_disk(&path).unwrap();
+
+        let read_back = LiveDocs::read_from_disk(&path, 130).unwrap();
+        assert_eq!(read_back, live_docs);
+        assert!(!dir.path().join("live_docs.tmp").exists());
+    }
+
+    #[test]
+    fn test_missing_file_means_no_deletions() {
+        let dir = TempDir::new().unwrap();
+        let live_docs = LiveDocs::read_from_disk(&dir.path().join(LIVE_DOCS_FILE_NAME), 5).unwrap();
+        assert_eq!(live_docs, LiveDocs::new(5));
+    }
+
+    #[test]
+    fn test_document_count_mismatch_is_an_error() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(LIVE_DOCS_FILE_NAME);
+        LiveDocs::new(5).write_to_disk(&path).unwrap();
+        assert!(LiveDocs::read_from_disk(&path, 6).is_err());
+    }
+}
@@ -0,0 +1,151 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::indexer::{
+    checksum::{CorruptionError, crc32},
+    helper::{read_string, read_u32, read_u64, write_string, write_u32, write_u64},
+};
+
+pub const MANIFEST_FILE_NAME: &str = "segments.manifest";
+
+/*
+The segments that make up the current generation of an index, readers only
+open the segments listed here.
+
+File Layout->
+generation (u64)
+no_of_segments
+segment_directory_name (one per segment, in segment order)
+crc32 (of everything before it)
+
+Every change to the segment set writes a new manifest under a temporary
+name, syncs it and renames it over the old one, so a reader sees either the
+old or the new segment set and never a mix of both. A segment is synced to
+disk before it is listed, every listed segment is complete. Segment
+directories that are not listed are left over from a process that died
+before publishing them or after publishing their replacement.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Manifest {
+    pub generation: u64,
+    pub segment_directory_names: Vec<String>,
+}
+
+impl Manifest {
+    pub fn write_to_disk(&self, index_directory: &Path) -> io::Result<()> {
+        let mut manifest = Vec::new();
+        write_u64(&mut manifest, self.generation)?;
+        write_u32(&mut manifest, self.segment_directory_names.len() as u32)?;
+        for segment_directory_name in &self.segment_directory_names {
+            write_string(&mut manifest, segment_directory_name)?;
+        }
+        let checksum = crc32(&manifest);
+        write_u32(&mut manifest, checksum)?;
+
+        let path = index_directory.join(MANIFEST_FILE_NAME);
+        let temporary_path = path.with_extension("tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&temporary_path)?);
+            writer.write_all(&manifest)?;
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+        }
+        fs::rename(&temporary_path, &path)?;
+        // the rename itself only survives a crash once the directory is synced
+        sync_directory(index_directory)
+    }
+
+    // None for an index directory that never had a manifest
+    pub fn read_from_disk(index_directory: &Path) -> io::Result<Option<Self>> {
+        let path = index_directory.join(MANIFEST_FILE_NAME);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let manifest = fs::read(&path)?;
+        let invalid = |reason: &str| -> io::Error {
+            CorruptionError::InvalidManifest {
+                reason: reason.to_string(),
+            }
+            .into()
+        };
+        if manifest.len() < 16 {
+            return Err(invalid("it is too short"));
+        }
+        let (contents, mut checksum) = manifest.split_at(manifest.len() - 4);
+        if read_u32(&mut checksum)? != crc32(contents) {
+            return Err(invalid("its checksum does not match"));
+        }
+        let mut reader = contents;
+        let generation = read_u64(&mut reader)?;
+        let no_of_segments = read_u32(&mut reader)?;
+        let mut segment_directory_names = Vec::new();
+        for _ in 0..no_of_segments {
+            segment_directory_names.push(read_string(&mut reader)?);
+        }
+        Ok(Some(Self {
+            generation,
+            segment_directory_names,
+        }))
+    }
+}
+
+pub fn sync_directory(path: &Path) -> io::Result<()> {
+    File::open(path)?.sync_all()
+}
+
+// syncs every file of the segment and the segment directory itself, so the segment is
+// complete on disk before a manifest lists it
+pub fn sync_segment_directory(segment_directory: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(segment_directory)? {
+        let path = entry?.path();
+        if path.is_file() {
+            File::open(&path)?.sync_all()?;
+        }
+    }
+    sync_directory(segment_directory)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::checksum::as_corruption_error;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_manifest_round_trip() {
+        let index_directory = TempDir::new().unwrap();
+        assert_eq!(Manifest::read_from_disk(index_directory.path()).unwrap(), None);
+
+        let manifest = Manifest {
+            generation: 3,
+            segment_directory_names: vec!["segment_000001".to_string(), "segment_000004".to_string()],
+        };
+        manifest.write_to_disk(index_directory.path()).unwrap();
+        assert_eq!(Manifest::read_from_disk(index_directory.path()).unwrap(), Some(manifest));
+        assert!(!index_directory.path().join("segments.tmp").exists());
+    }
+
+    #[test]
+    fn test_corrupt_manifest_is_refused() {
+        let index_directory = TempDir::new().unwrap();
+        Manifest {
+            generation: 1,
+            segment_directory_names: vec!["segment_000001".to_string()],
+        }
+        .write_to_disk(index_directory.path())
+        .unwrap();
+        let path = index_directory.path().join(MANIFEST_FILE_NAME);
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[14] ^= 0x01;
+        fs::write(&path, bytes).unwrap();
+
+        let error = Manifest::read_from_disk(index_directory.path()).unwrap_err();
+        assert!(matches!(
+            as_corruption_error(&error),
+            Some(CorruptionError::InvalidManifest { .. })
+        ));
+    }
+}
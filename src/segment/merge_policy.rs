@@ -0,0 +1,110 @@
+/*
+Tiered merge policy, decides which segments are merged together so that
+the number of segments a query has to visit stays small.
+
+Segments are put into tiers by their number of live documents, tier 0
+holds the segments with up to floor_segment_size documents and every
+following tier holds segments up to segments_per_tier times larger than
+the tier before it. Once a tier has segments_per_tier segments, up to
+max_merge_at_once of its smallest segments are merged into one segment,
+which lands in a higher tier. Merging only similarly sized segments keeps
+a document from being rewritten more than once per tier.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct TieredMergePolicy {
+    segments_per_tier: usize,
+    max_merge_at_once: usize,
+    floor_segment_size: u32,
+}
+
+impl Default for TieredMergePolicy {
+    fn default() -> Self {
+        Self::new(10, 10, 1000)
+    }
+}
+
+impl TieredMergePolicy {
+    pub fn new(segments_per_tier: usize, max_merge_at_once: usize, floor_segment_size: u32) -> Self {
+        Self {
+            // merging a single segment into itself would never reduce the segment count
+            segments_per_tier: segments_per_tier.max(2),
+            max_merge_at_once: max_merge_at_once.max(2),
+            floor_segment_size: floor_segment_size.max(1),
+        }
+    }
+
+    fn get_tier(&self, segment_size: u32) -> u32 {
+        let mut tier = 0;
+        let mut tier_size = self.floor_segment_size as u64;
+        while segment_size as u64 > tier_size {
+            tier_size *= self.segments_per_tier as u64;
+            tier += 1;
+        }
+        tier
+    }
+
+    // takes the number of live documents of every segment and returns the merges to run,
+    // each merge is a list of indices into segment_sizes. A segment is part of at most one
+    // merge
+    pub fn find_merges(&self, segment_sizes: &[u32]) -> Vec<Vec<usize>> {
+        let mut tiers: Vec<(u32, Vec<usize>)> = Vec::new();
+        for (segment_index, segment_size) in segment_sizes.iter().enumerate() {
+            let tier = self.get_tier(*segment_size);
+            match tiers.iter_mut().find(|(t, _)| *t == tier) {
+                Some((_, segments)) => segments.push(segment_index),
+                None => tiers.push((tier, vec![segment_index])),
+            }
+        }
+        tiers.sort();
+
+        let mut merges = Vec::new();
+        for (_, mut segments) in tiers {
+            segments.sort_by_key(|segment_index| (segment_sizes[*segment_index], *segment_index));
+            while segments.len() >= self.segments_per_tier {
+                let no_of_segments = segments.len().min(self.max_merge_at_once);
+                let mut merge: Vec<usize> = segments.drain(..no_of_segments).collect();
+                merge.sort();
+                merges.push(merge);
+            }
+        }
+        merges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_merge_below_segments_per_tier() {
+        let policy = TieredMergePolicy::new(3, 10, 10);
+        assert!(policy.find_merges(&[]).is_empty());
+        assert!(policy.find_merges(&[5, 7]).is_empty());
+    }
+
+    #[test]
+    fn test_merges_similarly_sized_segments() {
+        let policy = TieredMergePolicy::new(3, 10, 10);
+        // 5, 7 and 9 are in tier 0, 25 is in tier 1, 90 in tier 2 and 250 in tier 3
+        assert_eq!(policy.find_merges(&[250, 5, 25, 7, 90, 9]), vec![vec![1, 3, 5]]);
+    }
+
+    #[test]
+    fn test_merge_is_limited_to_max_merge_at_once() {
+        let policy = TieredMergePolicy::new(2, 3, 10);
+        assert_eq!(
+            policy.find_merges(&[4, 1, 3, 2, 5]),
+            vec![vec![1, 2, 3], vec![0, 4]]
+        );
+    }
+
+    #[test]
+    fn test_tiers() {
+        let policy = TieredMergePolicy::new(10, 10, 1000);
+        assert_eq!(policy.get_tier(0), 0);
+        assert_eq!(policy.get_tier(1000), 0);
+        assert_eq!(policy.get_tier(1001), 1);
+        assert_eq!(policy.get_tier(10_000), 1);
+        assert_eq!(policy.get_tier(10_001), 2);
+    }
+}
@@ -0,0 +1,149 @@
+pub mod live_docs;
+pub mod manifest;
+pub mod merge_policy;
+pub mod segment_merger;
+pub mod segment_reader;
+pub mod segment_set;
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/*
+An index directory holds one directory per segment->
+
+index_directory
+    segments.manifest
+    write.lock
+    segment_000001
+        final.idx
+        lexicon.dat
+        documents.dat
+        live_docs.dat
+    segment_000002
+    .
+    .
+    .
+
+Every segment is written once by the Indexer or the SegmentMerger and never
+modified afterwards, apart from live_docs.dat which records deletions. New
+documents are added to the index by writing a new segment. Doc ids are
+local to a segment and start at 1, the doc id seen by callers is the doc id
+inside the segment plus the number of documents in all the segments before it.
+The manifest names the segments of the current generation, see manifest.rs.
+ */
+const SEGMENT_DIRECTORY_PREFIX: &str = "segment_";
+
+pub fn segment_directory_name(segment_number: u32) -> String {
+    format!("{}{:06}", SEGMENT_DIRECTORY_PREFIX, segment_number)
+}
+
+// a segment that is still being written lives under this name, which list_segments ignores
+pub fn temporary_segment_directory_name(segment_number: u32) -> String {
+    format!("{}.tmp", segment_directory_name(segment_number))
+}
+
+fn parse_segment_number(directory_name: &str) -> Option<u32> {
+    directory_name
+        .strip_prefix(SEGMENT_DIRECTORY_PREFIX)
+        .and_then(|number| number.parse::<u32>().ok())
+}
+
+fn parse_temporary_segment_number(directory_name: &str) -> Option<u32> {
+    directory_name
+        .strip_suffix(".tmp")
+        .and_then(parse_segment_number)
+}
+
+fn list_directories(
+    index_directory: &Path,
+    parse: fn(&str) -> Option<u32>,
+) -> io::Result<Vec<(u32, PathBuf)>> {
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(index_directory)? {
+        let entry = entry?;
+        let path = entry.path();
+        if !path.is_dir() {
+            continue;
+        }
+        let directory_name = entry.file_name().to_string_lossy().to_string();
+        if let Some(segment_number) = parse(&directory_name) {
+            segments.push((segment_number, path));
+        }
+    }
+    segments.sort();
+    Ok(segments)
+}
+
+// returns the segments of the index directory ordered by segment number
+pub fn list_segments(index_directory: &Path) -> io::Result<Vec<(u32, PathBuf)>> {
+    list_directories(index_directory, parse_segment_number)
+}
+
+// returns the segments that were still being written when the last process stopped
+pub fn list_temporary_segments(index_directory: &Path) -> io::Result<Vec<(u32, PathBuf)>> {
+    list_directories(index_directory, parse_temporary_segment_number)
+}
+
+// temporary segments count as well, an interrupted build may still be resumed under its
+// number
+pub fn next_segment_number(index_directory: &Path) -> io::Result<u32> {
+    let last_segment_number = list_segments(index_directory)?
+        .into_iter()
+        .chain(list_temporary_segments(index_directory)?)
+        .map(|(segment_number, _)| segment_number)
+        .max();
+    Ok(last_segment_number.map(|segment_number| segment_number + 1).unwrap_or(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_segment_directory_name() {
+        assert_eq!(segment_directory_name(1), "segment_000001");
+        assert_eq!(parse_segment_number("segment_000042"), Some(42));
+        assert_eq!(parse_segment_number("runs"), None);
+        assert_eq!(parse_segment_number("segment_abc"), None);
+        assert_eq!(parse_segment_number(&temporary_segment_directory_name(3)), None);
+    }
+
+    #[test]
+    fn test_list_segments_in_order() {
+        let index_directory = TempDir::new().unwrap();
+        for segment_number in [3, 1, 2] {
+            fs::create_dir(index_directory.path().join(segment_directory_name(segment_number)))
+                .unwrap();
+        }
+        fs::create_dir(index_directory.path().join("runs")).unwrap();
+        fs::write(index_directory.path().join("segment_000009"), b"not a directory").unwrap();
+
+        let segments = list_segments(index_directory.path()).unwrap();
+        let segment_numbers: Vec<u32> = segments.iter().map(|(number, _)| *number).collect();
+        assert_eq!(segment_numbers, vec![1, 2, 3]);
+        assert_eq!(next_segment_number(index_directory.path()).unwrap(), 4);
+    }
+
+    #[test]
+    fn test_temporary_segments() {
+        let index_directory = TempDir::new().unwrap();
+        fs::create_dir(index_directory.path().join(segment_directory_name(1))).unwrap();
+        fs::create_dir(index_directory.path().join(temporary_segment_directory_name(4))).unwrap();
+
+        assert_eq!(
+            list_temporary_segments(index_directory.path()).unwrap(),
+            vec![(4, index_directory.path().join("segment_000004.tmp"))]
+        );
+        assert_eq!(list_segments(index_directory.path()).unwrap().len(), 1);
+        assert_eq!(next_segment_number(index_directory.path()).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_next_segment_number_of_empty_index() {
+        let index_directory = TempDir::new().unwrap();
+        assert_eq!(next_segment_number(index_directory.path()).unwrap(), 1);
+    }
+}
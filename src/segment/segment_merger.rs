@@ -0,0 +1,375 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+};
+
+use crate::{
+    dictionary::Posting,
+    indexer::{
+        block::Block,
+        document_metadata::{DOCUMENT_METADATA_FILE_NAME, DocumentMetadata, write_document_metadata},
+        document_store::{DOCUMENT_STORE_FILE_NAME, DocumentStoreWriter},
+        index_metadata::LEXICON_FILE_NAME,
+        link_graph::{LINK_GRAPH_FILE_NAME, LinkGraph},
+        run_file_writer::RunFileWriter,
+        spimi::Spmi,
+    },
+    scoring::page_rank::{PAGE_RANK_FILE_NAME, compute_page_rank, write_page_rank},
+    segment::segment_reader::SegmentReader,
+};
+
+/*
+Merges segments into a single new segment. The postings of every input
+segment are streamed block by block out of its final.idx into one run file
+per segment, with the doc ids remapped to the doc ids of the new segment,
+and the runs are merged into the new final.idx the same way the Indexer
+merges the runs of a build. Deleted documents get no doc id in the new
+segment, so their postings and metadata are dropped by the merge.
+
+Doc ids of the new segment are handed out in segment order and, inside a
+segment, in doc id order, so the order of the live documents is kept.
+
+The link graphs of the segments are remapped the same way and PageRank is
+computed again over the merged graph. Links only exist between documents of
+the same segment, links across the merged segments are not recovered.
+
+The live docs of the input segments are read once, when the merger is
+created. Documents deleted while the merge is running are still in the
+merged segment and have to be deleted there with carry_over_deletes.
+ */
+pub struct SegmentMerger<'a> {
+    segments: Vec<&'a SegmentReader>,
+    // per input segment, the new doc id of every old doc id, 0 for deleted documents
+    doc_id_maps: Vec<Vec<u32>>,
+    no_of_docs: u32,
+}
+
+impl<'a> SegmentMerger<'a> {
+    pub fn new(segments: Vec<&'a SegmentReader>) -> Self {
+        let mut doc_id_maps = Vec::with_capacity(segments.len());
+        let mut no_of_docs = 0;
+        for segment in &segments {
+            let live_docs = segment.get_live_docs();
+            let mut doc_id_map = vec![0; segment.get_no_of_docs() as usize + 1];
+            for doc_id in 1..=segment.get_no_of_docs() {
+                if live_docs.is_live(doc_id) {
+                    no_of_docs += 1;
+                    doc_id_map[doc_id as usize] = no_of_docs;
+                }
+            }
+            doc_id_maps.push(doc_id_map);
+        }
+        Self {
+            segments,
+            doc_id_maps,
+            no_of_docs,
+        }
+    }
+
+    // number of documents the merged segment will hold
+    pub fn get_no_of_docs(&self) -> u32 {
+        self.no_of_docs
+    }
+
+    // returns the doc id a document of the segment at segment_index gets in the merged
+    // segment, or None if the document is deleted
+    pub fn get_new_doc_id(&self, segment_index: usize, doc_id: u32) -> Option<u32> {
+        match self.doc_id_maps[segment_index].get(doc_id as usize) {
+            Some(0) | None => None,
+            Some(new_doc_id) => Some(*new_doc_id),
+        }
+    }
+
+    // writes the merged segment into target_directory, which has to exist already
+    pub fn merge(&self, target_directory: &Path) -> io::Result<()> {
+        let mut spmi = Spmi::new(target_directory);
+        if let Some(newest_segment) = self.segments.last() {
+            spmi.set_posting_codec(newest_segment.get_index_file_header().get_doc_id_codec());
+        }
+        spmi.prepare_run_directory()?;
+        for (segment_index, segment) in self.segments.iter().enumerate() {
+            let run_file_writer = spmi.create_run_file()?;
+            Self::write_segment_run(segment, &self.doc_id_maps[segment_index], run_file_writer)?;
+        }
+        let index_metadata = spmi.merge_index_files(64)?;
+        index_metadata.write_to_disk(&target_directory.join(LEXICON_FILE_NAME))?;
+
+        let mut document_metadata: HashMap<u32, DocumentMetadata> = HashMap::new();
+        for (segment_index, segment) in self.segments.iter().enumerate() {
+            for (doc_id, metadata) in segment.get_all_document_metadata() {
+                if let Some(new_doc_id) = self.get_new_doc_id(segment_index, *doc_id) {
+                    let mut metadata = metadata.clone();
+                    // a duplicate of a deleted document is no longer marked as one
+                    metadata.duplicate_of = self
+                        .get_new_doc_id(segment_index, metadata.duplicate_of)
+                        .unwrap_or(0);
+                    document_metadata.insert(new_doc_id, metadata);
+                }
+            }
+        }
+        write_document_metadata(
+            &target_directory.join(DOCUMENT_METADATA_FILE_NAME),
+            &document_metadata,
+        )?;
+
+        // the stored documents are copied block by block, the new doc ids grow in the same
+        // order the blocks are read in
+        let mut document_store_writer =
+            DocumentStoreWriter::create(&target_directory.join(DOCUMENT_STORE_FILE_NAME))?;
+        for (segment_index, segment) in self.segments.iter().enumerate() {
+            let document_store = segment.get_document_store();
+            for block_number in 0..document_store.get_no_of_blocks() {
+                for (doc_id, document) in document_store.read_block(block_number)? {
+                    if let Some(new_doc_id) = self.get_new_doc_id(segment_index, doc_id) {
+                        document_store_writer.add_document(new_doc_id, &document)?;
+                    }
+                }
+            }
+        }
+        document_store_writer.finish()?;
+
+        let mut link_graph = LinkGraph::new(self.no_of_docs);
+        for (segment_index, segment) in self.segments.iter().enumerate() {
+            let Some(segment_link_graph) = segment.read_link_graph()? else {
+                continue;
+            };
+            for doc_id in 1..=segment_link_graph.get_no_of_docs() {
+                let Some(new_doc_id) = self.get_new_doc_id(segment_index, doc_id) else {
+                    continue;
+                };
+                for target_doc_id in segment_link_graph.get_links(doc_id) {
+                    if let Some(new_target_doc_id) = self.get_new_doc_id(segment_index, *target_doc_id) {
+                        link_graph.add_link(new_doc_id, new_target_doc_id);
+                    }
+                }
+            }
+        }
+        link_graph.write_to_disk(&target_directory.join(LINK_GRAPH_FILE_NAME))?;
+        write_page_rank(
+            &target_directory.join(PAGE_RANK_FILE_NAME),
+            &compute_page_rank(&link_graph),
+        )
+    }
+
+    // deletes the documents from the merged segment that were deleted in the input
+    // segments after the merger was created
+    pub fn carry_over_deletes(&self, merged_segment: &SegmentReader) -> io::Result<()> {
+        for (segment_index, segment) in self.segments.iter().enumerate() {
+            let live_docs = segment.get_live_docs();
+            for doc_id in 1..=segment.get_no_of_docs() {
+                if live_docs.is_live(doc_id) {
+                    continue;
+                }
+                if let Some(new_doc_id) = self.get_new_doc_id(segment_index, doc_id) {
+                    merged_segment.delete_document(new_doc_id)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // term ids are handed out in sorted term order and the blocks of final.idx are written
+    // in term id order, so reading the blocks front to back yields the terms sorted, which
+    // is the order a run file needs them in
+    fn write_segment_run(
+        segment: &SegmentReader,
+        doc_id_map: &[u32],
+        mut run_file_writer: RunFileWriter,
+    ) -> io::Result<()> {
+        let index_metadata = segment.get_index_metadata();
+        let mut terms_by_id: HashMap<u32, String> = HashMap::new();
+        for term in index_metadata.get_all_terms() {
+            let term_id = index_metadata.get_term_id(term.clone());
+            terms_by_id.insert(term_id, term);
+        }
+
+        let mut index_file = File::open(segment.get_index_file_path())?;
+        let no_of_blocks = segment.get_index_file_footer().no_of_blocks;
+        let mut reader = BufReader::new(&mut index_file);
+
+        // a term can be spread across several blocks, its postings are collected until a
+        // different term shows up
+        let mut current_term_id = 0;
+        let mut current_postings: Vec<Posting> = Vec::new();
+        let mut block = Block::new(0);
+        for block_id in 0..no_of_blocks {
+            block.set_block_id(block_id);
+            block.init(&mut reader)?;
+            for term_index in 0..block.terms.len() {
+                let term_id = block.terms[term_index];
+                if term_id != current_term_id {
+                    Self::add_term_to_run(
+                        &mut run_file_writer,
+                        &terms_by_id,
+                        current_term_id,
+                        &current_postings,
+                    )?;
+                    current_term_id = term_id;
+                    current_postings.clear();
+                }
+                for chunk in block.decode_chunks_for_term(term_id, term_index)? {
+                    for posting in chunk.get_postings()? {
+                        let new_doc_id = doc_id_map.get(posting.doc_id as usize).copied().unwrap_or(0);
+                        if new_doc_id != 0 {
+                            current_postings.push(Posting::new(new_doc_id, posting.positions));
+                        }
+                    }
+                }
+            }
+        }
+        Self::add_term_to_run(
+            &mut run_file_writer,
+            &terms_by_id,
+            current_term_id,
+            &current_postings,
+        )?;
+        run_file_writer.finish()?;
+        Ok(())
+    }
+
+    // terms that only occur in deleted documents are left out of the merged segment
+    fn add_term_to_run(
+        run_file_writer: &mut RunFileWriter,
+        terms_by_id: &HashMap<u32, String>,
+        term_id: u32,
+        postings: &Vec<Posting>,
+    ) -> io::Result<()> {
+        if postings.is_empty() {
+            return Ok(());
+        }
+        let term = terms_by_id.get(&term_id).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("term id {} is not in the lexicon", term_id),
+            )
+        })?;
+        run_file_writer.add_term(term, postings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        document_source::plain_text_source::PlainTextSource,
+        indexer::indexer::Indexer,
+        query_parser::{field_query::QueryTerm, tokenizer::SearchTokenizer},
+    };
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn build_segment(segment_directory: &Path, documents: &[(&str, &str)]) -> SegmentReader {
+        let corpus_directory = TempDir::new().unwrap();
+        for (name, body) in documents {
+            fs::write(corpus_directory.path().join(format!("{}.txt", name)), body).unwrap();
+        }
+        fs::create_dir_all(segment_directory).unwrap();
+        let mut indexer =
+            Indexer::new(SearchTokenizer::new().unwrap(), Box::new(PlainTextSource::new()))
+                .unwrap();
+        indexer.set_index_directory(segment_directory.display().to_string());
+        indexer.index(corpus_directory.path()).unwrap();
+        SegmentReader::open(segment_directory).unwrap()
+    }
+
+    fn search_titles(segment: &SegmentReader, term: &str) -> Vec<String> {
+        let mut titles: Vec<String> = segment
+            .search(&[QueryTerm::new(None, term)])
+            .unwrap()
+            .iter()
+            .map(|doc_id| segment.get_document_metadata(*doc_id).unwrap().doc_name.clone())
+            .collect();
+        titles.sort();
+        titles
+    }
+
+    #[test]
+    fn test_merge_combines_segments() {
+        let index_directory = TempDir::new().unwrap();
+        let first = build_segment(
+            &index_directory.path().join("segment_000001"),
+            &[("fox", "the quick brown fox"), ("cat", "a sleepy cat")],
+        );
+        let second = build_segment(
+            &index_directory.path().join("segment_000002"),
+            &[("dog", "the lazy brown dog")],
+        );
+
+        let merged_directory = index_directory.path().join("segment_000003");
+        fs::create_dir_all(&merged_directory).unwrap();
+        let merger = SegmentMerger::new(vec![&first, &second]);
+        assert_eq!(merger.get_no_of_docs(), 3);
+        merger.merge(&merged_directory).unwrap();
+
+        let merged = SegmentReader::open(&merged_directory).unwrap();
+        assert_eq!(merged.get_no_of_docs(), 3);
+        assert_eq!(search_titles(&merged, "brown"), vec!["dog", "fox"]);
+        assert_eq!(search_titles(&merged, "sleepy"), vec!["cat"]);
+        assert!(!merged_directory.join("runs").exists());
+    }
+
+    #[test]
+    fn test_merge_drops_deleted_documents() {
+        let index_directory = TempDir::new().unwrap();
+        let segment = build_segment(
+            &index_directory.path().join("segment_000001"),
+            &[
+                ("a", "alpha zebra"),
+                ("b", "bravo zebra"),
+                ("c", "charlie zebra"),
+            ],
+        );
+        let bravo_doc_id = *segment
+            .get_all_document_metadata()
+            .iter()
+            .find(|(_, metadata)| metadata.doc_name == "b")
+            .unwrap()
+            .0;
+        assert!(segment.delete_document(bravo_doc_id).unwrap());
+
+        let merged_directory = index_directory.path().join("segment_000002");
+        fs::create_dir_all(&merged_directory).unwrap();
+        let merger = SegmentMerger::new(vec![&segment]);
+        assert_eq!(merger.get_no_of_docs(), 2);
+        assert_eq!(merger.get_new_doc_id(0, bravo_doc_id), None);
+        merger.merge(&merged_directory).unwrap();
+
+        let merged = SegmentReader::open(&merged_directory).unwrap();
+        assert_eq!(merged.get_no_of_docs(), 2);
+        assert!(!merged.get_live_docs().has_deletions());
+        assert_eq!(search_titles(&merged, "zebra"), vec!["a", "c"]);
+        // a term only the deleted document contained is gone from the lexicon
+        assert!(merged.find_term_metadata("bravo").is_none());
+        assert!(merged.find_term_metadata("alpha").is_some());
+        // the stored documents moved along with their new doc ids
+        let mut bodies: Vec<String> = (1..=2)
+            .map(|doc_id| merged.get_document(doc_id).unwrap().unwrap().body)
+            .collect();
+        bodies.sort();
+        assert_eq!(bodies, vec!["alpha zebra", "charlie zebra"]);
+        assert_eq!(merged.get_document(3).unwrap(), None);
+    }
+
+    #[test]
+    fn test_carry_over_deletes_made_during_merge() {
+        let index_directory = TempDir::new().unwrap();
+        let segment = build_segment(
+            &index_directory.path().join("segment_000001"),
+            &[("a", "alpha zebra"), ("b", "bravo zebra")],
+        );
+
+        let merged_directory = index_directory.path().join("segment_000002");
+        fs::create_dir_all(&merged_directory).unwrap();
+        let merger = SegmentMerger::new(vec![&segment]);
+        merger.merge(&merged_directory).unwrap();
+        // deleted after the merger took its snapshot of the live docs
+        segment.delete_document(1).unwrap();
+
+        let merged = SegmentReader::open(&merged_directory).unwrap();
+        assert_eq!(merged.search(&[QueryTerm::new(None, "zebra")]).unwrap().len(), 2);
+        merger.carry_over_deletes(&merged).unwrap();
+        assert_eq!(merged.search(&[QueryTerm::new(None, "zebra")]).unwrap(), vec![2]);
+    }
+}
@@ -0,0 +1,404 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    sync::{
+        RwLock, RwLockReadGuard,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use crate::{
+    document_source::Document,
+    in_memory_dict::map_in_memory_dict::MapInMemoryDictPointer,
+    indexer::{
+        block::Block,
+        checksum::CorruptionError,
+        document_metadata::{DOCUMENT_METADATA_FILE_NAME, DocumentMetadata, read_document_metadata},
+        document_store::{DOCUMENT_STORE_FILE_NAME, DocumentStoreReader},
+        index_file::{IndexFileFooter, IndexFileHeader, read_index_file_info},
+        index_metadata::{InMemoryIndexMetatdata, LEXICON_FILE_NAME},
+        link_graph::{LINK_GRAPH_FILE_NAME, LinkGraph},
+        spimi::FINAL_INDEX_FILE_NAME,
+    },
+    query_parser::field_query::QueryTerm,
+    query_processor::query_processor::QueryProcessor,
+    scoring::page_rank::{PAGE_RANK_FILE_NAME, read_page_rank},
+    segment::live_docs::{LIVE_DOCS_FILE_NAME, LiveDocs},
+};
+
+// what verify read from a segment, or from all segments of an index
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VerifyReport {
+    pub no_of_blocks: u32,
+    pub no_of_terms: u32,
+    pub no_of_postings: u64,
+    pub no_of_positions: u64,
+}
+
+impl VerifyReport {
+    pub fn add(&mut self, other: &VerifyReport) {
+        self.no_of_blocks += other.no_of_blocks;
+        self.no_of_terms += other.no_of_terms;
+        self.no_of_postings += other.no_of_postings;
+        self.no_of_positions += other.no_of_positions;
+    }
+}
+
+fn lexicon_mismatch(term: &str, reason: String) -> io::Error {
+    CorruptionError::LexiconMismatch {
+        term: term.to_string(),
+        reason,
+    }
+    .into()
+}
+
+/*
+Read side of a single segment, the lexicon, the document metadata and the
+live docs are kept in memory and the postings are read from the segment's
+final.idx whenever a query needs them.
+
+A reader is shared between the threads searching the index and the thread
+merging segments, the live docs are the only part that changes after the
+segment is opened.
+ */
+pub struct SegmentReader {
+    segment_directory: PathBuf,
+    index_metadata: InMemoryIndexMetatdata,
+    index_file_header: IndexFileHeader,
+    // counts from the footer of final.idx, whose header was checked on open
+    index_file_footer: IndexFileFooter,
+    document_metadata: HashMap<u32, DocumentMetadata>,
+    document_store: DocumentStoreReader,
+    live_docs: RwLock<LiveDocs>,
+    // PageRank of every doc id, None for segments written before links were extracted
+    static_scores: Option<Vec<f32>>,
+    no_of_docs: u32,
+    // set once the segment has been merged away, the directory is removed when the last
+    // query still using the reader drops it
+    remove_when_unused: AtomicBool,
+}
+
+impl SegmentReader {
+    pub fn open(segment_directory: &Path) -> io::Result<Self> {
+        if !segment_directory.join(FINAL_INDEX_FILE_NAME).is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no index found in {}", segment_directory.display()),
+            ));
+        }
+        let (index_file_header, index_file_footer) =
+            read_index_file_info(&segment_directory.join(FINAL_INDEX_FILE_NAME))?;
+        let index_metadata =
+            InMemoryIndexMetatdata::read_from_disk(&segment_directory.join(LEXICON_FILE_NAME))?;
+        let document_metadata =
+            read_document_metadata(&segment_directory.join(DOCUMENT_METADATA_FILE_NAME))?;
+        let no_of_docs = document_metadata.keys().copied().max().unwrap_or(0);
+        let document_store =
+            DocumentStoreReader::open(&segment_directory.join(DOCUMENT_STORE_FILE_NAME))?;
+        let live_docs =
+            LiveDocs::read_from_disk(&segment_directory.join(LIVE_DOCS_FILE_NAME), no_of_docs)?;
+        let static_scores = read_page_rank(&segment_directory.join(PAGE_RANK_FILE_NAME))?;
+        Ok(Self {
+            segment_directory: segment_directory.to_path_buf(),
+            index_metadata,
+            index_file_header,
+            index_file_footer,
+            document_metadata,
+            document_store,
+            live_docs: RwLock::new(live_docs),
+            static_scores,
+            no_of_docs,
+            remove_when_unused: AtomicBool::new(false),
+        })
+    }
+
+    // doc ids of the segment go from 1 to no_of_docs
+    pub fn get_no_of_docs(&self) -> u32 {
+        self.no_of_docs
+    }
+
+    pub fn get_segment_directory(&self) -> &Path {
+        &self.segment_directory
+    }
+
+    pub fn get_index_file_path(&self) -> PathBuf {
+        self.segment_directory.join(FINAL_INDEX_FILE_NAME)
+    }
+
+    pub fn get_index_file_header(&self) -> &IndexFileHeader {
+        &self.index_file_header
+    }
+
+    pub fn get_index_file_footer(&self) -> &IndexFileFooter {
+        &self.index_file_footer
+    }
+
+    // a poisoned lock still holds a valid bitset, a deletion either happened or it did not
+    pub fn get_live_docs(&self) -> RwLockReadGuard<'_, LiveDocs> {
+        self.live_docs
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // deleted documents have no metadata as far as readers are concerned
+    pub fn get_document_metadata(&self, doc_id: u32) -> Option<&DocumentMetadata> {
+        if !self.get_live_docs().is_live(doc_id) {
+            return None;
+        }
+        self.document_metadata.get(&doc_id)
+    }
+
+    // the stored title, url and body of the document, None for deleted documents
+    pub fn get_document(&self, doc_id: u32) -> io::Result<Option<Document>> {
+        if !self.get_live_docs().is_live(doc_id) {
+            return Ok(None);
+        }
+        self.document_store.get_document(doc_id)
+    }
+
+    // the document the document is a near-duplicate of, None if it is none, either of them
+    // is deleted or the original was deleted before the segment was merged
+    pub fn get_duplicate_of(&self, doc_id: u32) -> Option<u32> {
+        let duplicate_of = self.get_document_metadata(doc_id)?.duplicate_of;
+        if duplicate_of == 0 || !self.get_live_docs().is_live(duplicate_of) {
+            return None;
+        }
+        Some(duplicate_of)
+    }
+
+    // the query independent score of the document from the links pointing to it, averaging
+    // 1 over the documents of the segment. None for deleted documents
+    pub fn get_static_score(&self, doc_id: u32) -> Option<f32> {
+        if !self.get_live_docs().is_live(doc_id) {
+            return None;
+        }
+        self.static_scores.as_ref()?.get(doc_id as usize).copied()
+    }
+
+    // the links between the documents of the segment, deleted ones included, for the merger
+    pub fn read_link_graph(&self) -> io::Result<Option<LinkGraph>> {
+        LinkGraph::read_from_disk(&self.segment_directory.join(LINK_GRAPH_FILE_NAME))
+    }
+
+    // the stored documents of the segment, deleted ones included, for the merger
+    pub fn get_document_store(&self) -> &DocumentStoreReader {
+        &self.document_store
+    }
+
+    // metadata of every document in the segment, deleted ones included, for the merger
+    pub fn get_all_document_metadata(&self) -> &HashMap<u32, DocumentMetadata> {
+        &self.document_metadata
+    }
+
+    pub fn get_index_metadata(&self) -> &InMemoryIndexMetatdata {
+        &self.index_metadata
+    }
+
+    // returns the sorted segment doc ids of the live documents with the given external id
+    pub fn find_doc_ids_by_external_id(&self, doc_external_id: &str) -> Vec<u32> {
+        let live_docs = self.get_live_docs();
+        let mut doc_ids: Vec<u32> = self
+            .document_metadata
+            .iter()
+            .filter(|(doc_id, metadata)| {
+                metadata.doc_external_id == doc_external_id && live_docs.is_live(**doc_id)
+            })
+            .map(|(doc_id, _)| *doc_id)
+            .collect();
+        doc_ids.sort();
+        doc_ids
+    }
+
+    // marks the document as deleted and persists the live docs right away, returns false
+    // if the document does not exist or was already deleted
+    pub fn delete_document(&self, doc_id: u32) -> io::Result<bool> {
+        let mut live_docs = self
+            .live_docs
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if !live_docs.delete(doc_id) {
+            return Ok(false);
+        }
+        live_docs.write_to_disk(&self.segment_directory.join(LIVE_DOCS_FILE_NAME))?;
+        Ok(true)
+    }
+
+    // marks the segment as merged away, its directory is removed once the reader is dropped
+    pub fn remove_when_unused(&self) {
+        self.remove_when_unused.store(true, Ordering::SeqCst);
+    }
+
+    /*
+    Reads every block of final.idx, which checks its checksum, and decodes
+    every chunk and posting in it. The doc ids of every term have to be
+    increasing and belong to the segment, and the blocks and document
+    frequency of every term have to be the ones the lexicon records for it.
+    The first problem found is returned as a CorruptionError.
+     */
+    pub fn verify(&self) -> io::Result<VerifyReport> {
+        let mut index_file = File::open(self.get_index_file_path())?;
+        let mut reader = BufReader::new(&mut index_file);
+        let mut report = VerifyReport::default();
+        let mut block_ids_by_term: HashMap<u32, Vec<u32>> = HashMap::new();
+        let mut no_of_postings_by_term: HashMap<u32, u32> = HashMap::new();
+        let mut last_doc_id_by_term: HashMap<u32, u32> = HashMap::new();
+        let mut block = Block::new(0);
+        for block_id in 0..self.index_file_footer.no_of_blocks {
+            block.set_block_id(block_id);
+            block.init(&mut reader)?;
+            report.no_of_blocks += 1;
+            for term_index in 0..block.terms.len() {
+                let term_id = block.terms[term_index];
+                block_ids_by_term.entry(term_id).or_default().push(block_id);
+                for chunk in block.decode_chunks_for_term(term_id, term_index)? {
+                    let postings = chunk.get_postings()?;
+                    let doc_ids: Vec<u32> = postings.iter().map(|posting| posting.doc_id).collect();
+                    let last_doc_id = last_doc_id_by_term.entry(term_id).or_insert(0);
+                    for (posting, doc_id) in postings.iter().zip(&doc_ids) {
+                        if *doc_id <= *last_doc_id || *doc_id > self.no_of_docs {
+                            return Err(CorruptionError::InvalidBlock {
+                                block_id,
+                                reason: format!(
+                                    "doc id {} of term id {} after doc id {} in a segment of {} documents",
+                                    doc_id, term_id, last_doc_id, self.no_of_docs
+                                ),
+                            }
+                            .into());
+                        }
+                        *last_doc_id = *doc_id;
+                        report.no_of_positions += posting.positions.len() as u64;
+                    }
+                    if doc_ids.last() != Some(&chunk.max_doc_id) {
+                        return Err(CorruptionError::InvalidBlock {
+                            block_id,
+                            reason: format!(
+                                "a chunk of term id {} records {} as its last doc id",
+                                term_id, chunk.max_doc_id
+                            ),
+                        }
+                        .into());
+                    }
+                    *no_of_postings_by_term.entry(term_id).or_default() += doc_ids.len() as u32;
+                    report.no_of_postings += doc_ids.len() as u64;
+                }
+            }
+        }
+
+        for term in self.index_metadata.get_all_terms() {
+            let Some(term_metadata) = self.find_term_metadata(&term) else {
+                continue;
+            };
+            let block_ids = block_ids_by_term
+                .remove(&term_metadata.term_id)
+                .unwrap_or_default();
+            if block_ids != term_metadata.block_ids {
+                return Err(lexicon_mismatch(
+                    &term,
+                    format!(
+                        "it is in blocks {:?} but the lexicon has {:?}",
+                        block_ids, term_metadata.block_ids
+                    ),
+                ));
+            }
+            let no_of_postings = no_of_postings_by_term
+                .get(&term_metadata.term_id)
+                .copied()
+                .unwrap_or(0);
+            if no_of_postings != term_metadata.term_frequency {
+                return Err(lexicon_mismatch(
+                    &term,
+                    format!(
+                        "it has {} postings but the lexicon has a document frequency of {}",
+                        no_of_postings, term_metadata.term_frequency
+                    ),
+                ));
+            }
+            report.no_of_terms += 1;
+        }
+        if let Some(term_id) = block_ids_by_term.keys().min() {
+            return Err(lexicon_mismatch(
+                &format!("#{}", term_id),
+                "the term id is in the index but not in the lexicon".to_string(),
+            ));
+        }
+        if report.no_of_terms != self.index_file_footer.no_of_terms
+            || report.no_of_postings != self.index_file_footer.no_of_postings
+        {
+            return Err(CorruptionError::FooterMismatch {
+                reason: format!(
+                    "it counts {} terms and {} postings but the blocks hold {} and {}",
+                    self.index_file_footer.no_of_terms,
+                    self.index_file_footer.no_of_postings,
+                    report.no_of_terms,
+                    report.no_of_postings
+                ),
+            }
+            .into());
+        }
+        Ok(report)
+    }
+
+    pub fn find_term_metadata(&self, term: &str) -> Option<&MapInMemoryDictPointer> {
+        self.index_metadata.find_term_metadata(term)
+    }
+
+    // the lexicon terms every query term can match, None if a query term matches none
+    fn get_query_metadata(&self, query_terms: &[QueryTerm]) -> Option<Vec<Vec<&MapInMemoryDictPointer>>> {
+        let mut query_metadata = Vec::new();
+        for query_term in query_terms {
+            let term_metadata: Vec<&MapInMemoryDictPointer> = query_term
+                .get_lexicon_terms()
+                .iter()
+                .filter_map(|term| self.find_term_metadata(term))
+                .collect();
+            if term_metadata.is_empty() {
+                return None;
+            }
+            query_metadata.push(term_metadata);
+        }
+        Some(query_metadata)
+    }
+
+    // returns the segment doc ids of the documents matching every query term
+    pub fn search(&self, query_terms: &[QueryTerm]) -> io::Result<Vec<u32>> {
+        // a term that is not in the lexicon cannot be in any document of the segment
+        let Some(query_metadata) = self.get_query_metadata(query_terms) else {
+            return Ok(Vec::new());
+        };
+        let inverted_index_file = File::open(self.get_index_file_path())?;
+        let mut query_processor = QueryProcessor::new(inverted_index_file);
+        query_processor.process_query(query_metadata, &self.get_live_docs())
+    }
+
+    // the tf-idf score of every segment doc id for the query terms, see
+    // QueryProcessor::score_docs
+    pub fn score_docs(&self, query_terms: &[QueryTerm], doc_ids: &[u32]) -> io::Result<Vec<f32>> {
+        let Some(query_metadata) = self.get_query_metadata(query_terms) else {
+            return Ok(vec![0.0; doc_ids.len()]);
+        };
+        let inverted_index_file = File::open(self.get_index_file_path())?;
+        let mut query_processor = QueryProcessor::new(inverted_index_file);
+        query_processor.score_docs(&query_metadata, self.no_of_docs, doc_ids)
+    }
+
+    // returns the positions of the lexicon term in the document, empty if the document does
+    // not contain it
+    pub fn get_positions(&self, term: &str, doc_id: u32) -> io::Result<Vec<u32>> {
+        let Some(term_metadata) = self.find_term_metadata(term) else {
+            return Ok(Vec::new());
+        };
+        let inverted_index_file = File::open(self.get_index_file_path())?;
+        let mut query_processor = QueryProcessor::new(inverted_index_file);
+        query_processor.get_positions(&term_metadata.block_ids, term_metadata.term_id, doc_id)
+    }
+}
+
+impl Drop for SegmentReader {
+    fn drop(&mut self) {
+        if self.remove_when_unused.load(Ordering::SeqCst) {
+            let _ = fs::remove_dir_all(&self.segment_directory);
+        }
+    }
+}
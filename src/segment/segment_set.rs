@@ -0,0 +1,479 @@
+use std::{
+    fs::{self, File, OpenOptions, TryLockError},
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard, RwLock},
+};
+
+use crate::{
+    indexer::checkpoint::CHECKPOINT_FILE_NAME,
+    segment::{
+        list_segments, list_temporary_segments,
+        manifest::{Manifest, sync_directory, sync_segment_directory},
+        merge_policy::TieredMergePolicy, next_segment_number, segment_directory_name,
+        segment_merger::SegmentMerger, segment_reader::SegmentReader,
+        temporary_segment_directory_name,
+    },
+};
+
+/*
+The segments of an index directory, shared between the threads searching
+the index and the thread merging its segments.
+
+Queries work on a snapshot of the segment list, a merge builds the merged
+segment next to the segments it replaces and only swaps it in once it is
+complete. The replaced segments stay on disk until the last query still
+using them has finished.
+
+Every change to the segment list is published in the manifest before it is
+swapped in, under the write lock of the segment list, so the manifest always
+names the segments a newly opened set sees.
+
+Only one process has an index directory open at a time. Open takes an
+exclusive lock on write.lock, held until the set is dropped and released by
+the OS when the process dies, and refuses the directory if another process
+holds it. So whatever open finds half written belongs to a process that is
+gone: temporary segments without a checkpoint are removed right away, a
+segment the manifest does not list was renamed into place but never
+published and is removed by the first change this set publishes, under the
+write lock of the segment list.
+ */
+pub const WRITE_LOCK_FILE_NAME: &str = "write.lock";
+
+pub struct SegmentSet {
+    index_directory: PathBuf,
+    // ordered by segment number, which is also the order the doc ids are handed out in
+    segments: RwLock<Vec<Arc<SegmentReader>>>,
+    last_segment_number: Mutex<u32>,
+    // generation of the last published manifest, only changed under the write lock of segments
+    generation: Mutex<u64>,
+    // only one merge runs at a time, so two merges never pick the same segment
+    merge_lock: Mutex<()>,
+    // segments on disk the manifest did not list at open, never one this set created
+    unpublished_segments: Mutex<Vec<PathBuf>>,
+    // locked for as long as the set exists
+    _write_lock: File,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl SegmentSet {
+    // opens the segments the manifest lists, an index directory without a manifest was
+    // written before manifests existed and all of its segments are opened
+    pub fn open(index_directory: &Path) -> io::Result<Self> {
+        let write_lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(index_directory.join(WRITE_LOCK_FILE_NAME))?;
+        write_lock.try_lock().map_err(|e| match e {
+            TryLockError::WouldBlock => io::Error::new(
+                ErrorKind::WouldBlock,
+                format!("{} is open in another process", index_directory.display()),
+            ),
+            TryLockError::Error(e) => e,
+        })?;
+        // a temporary segment without a checkpoint is a build or a merge that died and
+        // cannot be continued
+        for (_, temporary_directory) in list_temporary_segments(index_directory)? {
+            if !temporary_directory.join(CHECKPOINT_FILE_NAME).is_file() {
+                fs::remove_dir_all(&temporary_directory)?;
+            }
+        }
+
+        let mut segments = Vec::new();
+        let mut unpublished_segments = Vec::new();
+        let mut generation = 0;
+        match Manifest::read_from_disk(index_directory)? {
+            Some(manifest) => {
+                for segment_directory_name in &manifest.segment_directory_names {
+                    segments.push(Arc::new(SegmentReader::open(
+                        &index_directory.join(segment_directory_name),
+                    )?));
+                }
+                // a segment that is not listed was never published or has already been
+                // replaced, a crash kept it from being published or removed
+                for (_, segment_directory) in list_segments(index_directory)? {
+                    let listed = segments
+                        .iter()
+                        .any(|segment| segment.get_segment_directory() == segment_directory);
+                    if !listed {
+                        unpublished_segments.push(segment_directory);
+                    }
+                }
+                generation = manifest.generation;
+            }
+            None => {
+                for (_, segment_directory) in list_segments(index_directory)? {
+                    segments.push(Arc::new(SegmentReader::open(&segment_directory)?));
+                }
+            }
+        }
+        Ok(Self {
+            index_directory: index_directory.to_path_buf(),
+            segments: RwLock::new(segments),
+            last_segment_number: Mutex::new(next_segment_number(index_directory)? - 1),
+            generation: Mutex::new(generation),
+            merge_lock: Mutex::new(()),
+            unpublished_segments: Mutex::new(unpublished_segments),
+            _write_lock: write_lock,
+        })
+    }
+
+    // writes the manifest of the next generation, has to be called with the write lock of
+    // segments held and before the segments are swapped in
+    fn publish(&self, segments: &[Arc<SegmentReader>]) -> io::Result<()> {
+        let mut generation = lock(&self.generation);
+        let manifest = Manifest {
+            generation: *generation + 1,
+            segment_directory_names: segments
+                .iter()
+                .map(|segment| {
+                    segment
+                        .get_segment_directory()
+                        .file_name()
+                        .map(|name| name.to_string_lossy().to_string())
+                        .unwrap_or_default()
+                })
+                .collect(),
+        };
+        manifest.write_to_disk(&self.index_directory)?;
+        *generation = manifest.generation;
+        Ok(())
+    }
+
+    // removes the segments the manifest did not list at open, has to be called with the
+    // write lock of segments held and after a publish. A segment that could not be removed
+    // is tried again after the next publish
+    fn remove_unpublished_segments(&self) -> io::Result<()> {
+        let mut unpublished_segments = lock(&self.unpublished_segments);
+        while let Some(segment_directory) = unpublished_segments.last() {
+            if segment_directory.exists() {
+                fs::remove_dir_all(segment_directory)?;
+            }
+            unpublished_segments.pop();
+        }
+        Ok(())
+    }
+
+    pub fn get_segments(&self) -> Vec<Arc<SegmentReader>> {
+        self.segments
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    pub fn get_no_of_segments(&self) -> usize {
+        self.get_segments().len()
+    }
+
+    // segment numbers are handed out here rather than read from the index directory, so an
+    // indexing run and a merge running at the same time never pick the same number
+    pub fn allocate_segment_number(&self) -> u32 {
+        let mut last_segment_number = lock(&self.last_segment_number);
+        *last_segment_number += 1;
+        *last_segment_number
+    }
+
+    pub fn get_segment_directory(&self, segment_number: u32) -> PathBuf {
+        self.index_directory.join(segment_directory_name(segment_number))
+    }
+
+    pub fn get_temporary_segment_directory(&self, segment_number: u32) -> PathBuf {
+        self.index_directory
+            .join(temporary_segment_directory_name(segment_number))
+    }
+
+    // a temporary segment holding a checkpoint belongs to a build that was interrupted and
+    // can be resumed, temporary segments of interrupted merges have none
+    pub fn find_resumable_segment(&self) -> io::Result<Option<PathBuf>> {
+        Ok(list_temporary_segments(&self.index_directory)?
+            .into_iter()
+            .map(|(_, segment_directory)| segment_directory)
+            .find(|segment_directory| segment_directory.join(CHECKPOINT_FILE_NAME).is_file()))
+    }
+
+    // publishes a segment that was renamed to its final name, its files are synced first so
+    // the manifest never lists a segment that is incomplete on disk
+    pub fn add_segment(&self, segment_directory: &Path) -> io::Result<()> {
+        sync_segment_directory(segment_directory)?;
+        sync_directory(&self.index_directory)?;
+        let segment = Arc::new(SegmentReader::open(segment_directory)?);
+        let mut segments = self
+            .segments
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut new_segments = segments.clone();
+        new_segments.push(segment);
+        self.publish(&new_segments)?;
+        *segments = new_segments;
+        self.remove_unpublished_segments()
+    }
+
+    // removes every segment, waiting for a running merge to finish first
+    pub fn clear(&self) -> io::Result<()> {
+        let _merge_guard = lock(&self.merge_lock);
+        let mut segments = self
+            .segments
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        self.publish(&[])?;
+        for segment in segments.drain(..) {
+            segment.remove_when_unused();
+        }
+        self.remove_unpublished_segments()
+    }
+
+    // runs the merges the policy asks for until it asks for no more, a merged segment can
+    // complete a higher tier. Returns the number of merges that were run
+    pub fn maybe_merge(&self, merge_policy: &TieredMergePolicy) -> io::Result<u32> {
+        let _merge_guard = lock(&self.merge_lock);
+        let mut no_of_merges = 0;
+        loop {
+            let segments = self.get_segments();
+            let segment_sizes: Vec<u32> = segments
+                .iter()
+                .map(|segment| segment.get_live_docs().get_no_of_live_docs())
+                .collect();
+            let merges = merge_policy.find_merges(&segment_sizes);
+            if merges.is_empty() {
+                return Ok(no_of_merges);
+            }
+            for merge in merges {
+                let merge_segments: Vec<Arc<SegmentReader>> = merge
+                    .iter()
+                    .map(|segment_index| segments[*segment_index].clone())
+                    .collect();
+                self.merge_segments(&merge_segments)?;
+                no_of_merges += 1;
+            }
+        }
+    }
+
+    // rewrites every segment that has deleted documents without them
+    pub fn expunge_deletes(&self) -> io::Result<()> {
+        let _merge_guard = lock(&self.merge_lock);
+        for segment in self.get_segments() {
+            if segment.get_live_docs().has_deletions() {
+                self.merge_segments(&[segment])?;
+            }
+        }
+        Ok(())
+    }
+
+    // merges the segments into a new segment and swaps it in for them, has to be called
+    // with the merge lock held
+    fn merge_segments(&self, merge_segments: &[Arc<SegmentReader>]) -> io::Result<()> {
+        let merger =
+            SegmentMerger::new(merge_segments.iter().map(|segment| segment.as_ref()).collect());
+
+        let mut merged_segment = None;
+        if merger.get_no_of_docs() > 0 {
+            let segment_number = self.allocate_segment_number();
+            let temporary_directory = self.get_temporary_segment_directory(segment_number);
+            fs::create_dir_all(&temporary_directory)?;
+            if let Err(e) = merger.merge(&temporary_directory) {
+                let _ = fs::remove_dir_all(&temporary_directory);
+                return Err(e);
+            }
+            let segment_directory = self.get_segment_directory(segment_number);
+            sync_segment_directory(&temporary_directory)?;
+            fs::rename(&temporary_directory, &segment_directory)?;
+            sync_directory(&self.index_directory)?;
+            merged_segment = Some(Arc::new(SegmentReader::open(&segment_directory)?));
+        }
+
+        // deletes take the read lock, holding the write lock keeps any delete from landing
+        // between carrying the deletes over and swapping the segments
+        let mut segments = self
+            .segments
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(merged_segment) = &merged_segment {
+            merger.carry_over_deletes(merged_segment)?;
+        }
+        let mut new_segments: Vec<Arc<SegmentReader>> = segments
+            .iter()
+            .filter(|segment| {
+                !merge_segments
+                    .iter()
+                    .any(|merge_segment| Arc::ptr_eq(segment, merge_segment))
+            })
+            .cloned()
+            .collect();
+        new_segments.extend(merged_segment.clone());
+        if let Err(e) = self.publish(&new_segments) {
+            // the old segments stay published, the merged one is never seen
+            if let Some(merged_segment) = merged_segment {
+                merged_segment.remove_when_unused();
+            }
+            return Err(e);
+        }
+        *segments = new_segments;
+        for segment in merge_segments {
+            segment.remove_when_unused();
+        }
+        self.remove_unpublished_segments()
+    }
+
+    // runs f on the segment list while no merge can swap segments, for operations that
+    // have to map doc ids to segments consistently
+    pub fn with_segments<T>(&self, f: impl FnOnce(&[Arc<SegmentReader>]) -> T) -> T {
+        let segments = self
+            .segments
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        f(&segments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        document_source::plain_text_source::PlainTextSource,
+        indexer::indexer::Indexer,
+        query_parser::{field_query::QueryTerm, tokenizer::SearchTokenizer},
+    };
+    use tempfile::TempDir;
+
+    fn add_test_segment(segment_set: &SegmentSet, documents: &[(&str, &str)]) {
+        let corpus_directory = TempDir::new().unwrap();
+        for (name, body) in documents {
+            fs::write(corpus_directory.path().join(format!("{}.txt", name)), body).unwrap();
+        }
+        let segment_directory =
+            segment_set.get_segment_directory(segment_set.allocate_segment_number());
+        fs::create_dir_all(&segment_directory).unwrap();
+        let mut indexer =
+            Indexer::new(SearchTokenizer::new().unwrap(), Box::new(PlainTextSource::new()))
+                .unwrap();
+        indexer.set_index_directory(segment_directory.display().to_string());
+        indexer.index(corpus_directory.path()).unwrap();
+        segment_set.add_segment(&segment_directory).unwrap();
+    }
+
+    fn count_matches(segment_set: &SegmentSet, term: &str) -> usize {
+        segment_set
+            .get_segments()
+            .iter()
+            .map(|segment| segment.search(&[QueryTerm::new(None, term)]).unwrap().len())
+            .sum()
+    }
+
+    #[test]
+    fn test_maybe_merge_merges_segments_of_a_full_tier() {
+        let index_directory = TempDir::new().unwrap();
+        let segment_set = SegmentSet::open(index_directory.path()).unwrap();
+        add_test_segment(&segment_set, &[("a", "apple zebra")]);
+        add_test_segment(&segment_set, &[("b", "banana zebra")]);
+        add_test_segment(&segment_set, &[("c", "cherry zebra")]);
+
+        let policy = TieredMergePolicy::new(3, 10, 10);
+        assert_eq!(segment_set.maybe_merge(&policy).unwrap(), 1);
+        assert_eq!(segment_set.get_no_of_segments(), 1);
+        assert_eq!(count_matches(&segment_set, "zebra"), 3);
+        assert_eq!(count_matches(&segment_set, "banana"), 1);
+
+        // the merged away segments are gone from disk, the merged one is in their place
+        assert_eq!(
+            list_segments(index_directory.path()).unwrap(),
+            vec![(4, index_directory.path().join("segment_000004"))]
+        );
+        assert_eq!(segment_set.maybe_merge(&policy).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_merged_segment_stays_on_disk_while_in_use() {
+        let index_directory = TempDir::new().unwrap();
+        let segment_set = SegmentSet::open(index_directory.path()).unwrap();
+        add_test_segment(&segment_set, &[("a", "apple zebra")]);
+        add_test_segment(&segment_set, &[("b", "banana zebra")]);
+
+        let snapshot = segment_set.get_segments();
+        segment_set
+            .maybe_merge(&TieredMergePolicy::new(2, 10, 10))
+            .unwrap();
+        // a query that took its snapshot before the merge can still read the old segments
+        assert_eq!(snapshot[0].search(&[QueryTerm::new(None, "zebra")]).unwrap(), vec![1]);
+        assert!(index_directory.path().join("segment_000001").exists());
+
+        drop(snapshot);
+        assert!(!index_directory.path().join("segment_000001").exists());
+        assert!(!index_directory.path().join("segment_000002").exists());
+    }
+
+    #[test]
+    fn test_second_open_of_an_index_directory_is_refused() {
+        let index_directory = TempDir::new().unwrap();
+        let segment_set = SegmentSet::open(index_directory.path()).unwrap();
+        let error = SegmentSet::open(index_directory.path()).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::WouldBlock);
+
+        drop(segment_set);
+        assert!(SegmentSet::open(index_directory.path()).is_ok());
+    }
+
+    #[test]
+    fn test_open_removes_temporary_segments_that_cannot_be_resumed() {
+        let index_directory = TempDir::new().unwrap();
+        let abandoned_directory =
+            index_directory.path().join(temporary_segment_directory_name(3));
+        let resumable_directory =
+            index_directory.path().join(temporary_segment_directory_name(4));
+        fs::create_dir(&abandoned_directory).unwrap();
+        fs::create_dir(&resumable_directory).unwrap();
+        fs::write(resumable_directory.join(CHECKPOINT_FILE_NAME), b"").unwrap();
+
+        let segment_set = SegmentSet::open(index_directory.path()).unwrap();
+        assert!(!abandoned_directory.exists());
+        assert_eq!(segment_set.find_resumable_segment().unwrap(), Some(resumable_directory));
+        // the number of the resumable segment is still taken
+        assert_eq!(segment_set.allocate_segment_number(), 5);
+    }
+
+    #[test]
+    fn test_open_only_sees_published_segments() {
+        let index_directory = TempDir::new().unwrap();
+        let segment_set = SegmentSet::open(index_directory.path()).unwrap();
+        add_test_segment(&segment_set, &[("a", "apple zebra")]);
+        add_test_segment(&segment_set, &[("b", "banana zebra")]);
+        let generation = || {
+            Manifest::read_from_disk(index_directory.path()).unwrap().unwrap().generation
+        };
+        assert_eq!(generation(), 2);
+
+        // a process that died after renaming a segment into place but before publishing it
+        let unpublished_directory = index_directory.path().join(segment_directory_name(9));
+        fs::create_dir(&unpublished_directory).unwrap();
+        for entry in fs::read_dir(index_directory.path().join("segment_000001")).unwrap() {
+            let path = entry.unwrap().path();
+            fs::copy(&path, unpublished_directory.join(path.file_name().unwrap())).unwrap();
+        }
+        drop(segment_set);
+
+        // opening leaves it alone, it is only removed once a manifest without it is published
+        let segment_set = SegmentSet::open(index_directory.path()).unwrap();
+        assert_eq!(segment_set.get_no_of_segments(), 2);
+        assert_eq!(count_matches(&segment_set, "apple"), 1);
+        assert!(unpublished_directory.exists());
+        assert_eq!(generation(), 2);
+
+        // the first publish of the set removes it
+        segment_set
+            .maybe_merge(&TieredMergePolicy::new(2, 10, 10))
+            .unwrap();
+        assert!(!unpublished_directory.exists());
+        let manifest = Manifest::read_from_disk(index_directory.path()).unwrap().unwrap();
+        assert_eq!(manifest.generation, 3);
+        assert_eq!(manifest.segment_directory_names, vec!["segment_000010".to_string()]);
+
+        segment_set.clear().unwrap();
+        drop(segment_set);
+        let segment_set = SegmentSet::open(index_directory.path()).unwrap();
+        assert_eq!(segment_set.get_no_of_segments(), 0);
+        assert_eq!(generation(), 4);
+    }
+}